@@ -1,5 +1,5 @@
 use async_fs as afs;
-use futures_lite::io::{AsyncReadExt, Cursor};
+use futures_lite::io::Cursor;
 use std::path::PathBuf;
 
 use async_sevenz::{Archive, BlockDecoder, Password};
@@ -17,33 +17,20 @@ async fn main() {
 
     for block_index in 0..block_count {
         let forder_dec = BlockDecoder::new(1, block_index, &archive, &password, &mut cursor);
-
-        if !forder_dec
-            .entries()
-            .iter()
-            .any(|entry| entry.name() == my_file_name)
-        {
-            // skip the folder if it does not contain the file we want
-            continue;
-        }
         let dest = PathBuf::from("examples/data/sample_mt/");
 
+        // Blocks that don't contain the file we want are skipped without reading their data.
         forder_dec
-            .for_each_entries(&mut |entry, reader| {
-                if entry.name() == my_file_name {
+            .for_each_selected_entries(
+                &mut |entry| entry.name() == my_file_name,
+                &mut |entry, reader| {
                     let dest = dest.join(entry.name());
                     Box::pin(async move {
                         async_sevenz::default_entry_extract_fn(entry, reader, &dest).await?;
                         Ok(true)
                     })
-                } else {
-                    Box::pin(async move {
-                        let mut buf = Vec::new();
-                        AsyncReadExt::read_to_end(reader, &mut buf).await?;
-                        Ok(true)
-                    })
-                }
-            })
+                },
+            )
             .await
             .expect("ok");
     }
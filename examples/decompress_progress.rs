@@ -1,53 +1,27 @@
-use async_fs as afs;
-use async_sevenz::Password;
-use futures_lite::io::{AsyncReadExt, Cursor};
+use async_sevenz::ProgressEvent;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 #[tokio::main]
 async fn main() {
-    let total_size = {
-        let sz =
-            async_sevenz::ArchiveReader::open("examples/data/sample.7z", Password::from("pass"))
-                .await
-                .unwrap();
-        sz.archive()
-            .files
-            .iter()
-            .filter(|e| e.has_stream())
-            .map(|e| e.size())
-            .sum::<u64>()
-    };
-    let progress = std::sync::Arc::new(std::sync::Mutex::new(0usize));
+    let total_size = AtomicU64::new(0);
+    let done = AtomicU64::new(0);
     let dest = PathBuf::from("examples/data/sample");
 
-    let data = afs::read("examples/data/sample.7z").await.unwrap();
-    async_sevenz::decompress_with_extract_fn_and_password(
-        Cursor::new(data),
+    async_sevenz::decompress_file_with_progress(
+        "examples/data/sample.7z",
         &dest,
-        Password::from("pass"),
-        move |entry, reader, dest| {
-            let progress = progress.clone();
-            Box::pin(async move {
-                let path = dest.join(entry.name());
-                if let Some(parent) = path.parent() {
-                    async_fs::create_dir_all(parent).await.unwrap();
-                }
-                let mut buf = [0u8; 8192];
-                let mut data = Vec::new();
-                loop {
-                    let n = AsyncReadExt::read(reader, &mut buf).await?;
-                    if n == 0 {
-                        break;
-                    }
-                    data.extend_from_slice(&buf[..n]);
-                    let total = total_size;
-                    let mut g = progress.lock().unwrap();
-                    *g += n;
-                    println!("progress:{:.2}%", (*g as f64 / total as f64) * 100f64);
-                }
-                async_fs::write(&path, &data).await.unwrap();
-                Ok(true)
-            })
+        move |event: ProgressEvent<'_>| match event {
+            ProgressEvent::Totals(totals) => {
+                total_size.store(totals.unpacked_size, Ordering::Relaxed);
+            }
+            ProgressEvent::EntryStarted(entry) => println!("extracting {}", entry.name()),
+            ProgressEvent::BytesOut(n) => {
+                let done = done.fetch_add(n, Ordering::Relaxed) + n;
+                let total = total_size.load(Ordering::Relaxed).max(1);
+                println!("progress:{:.2}%", (done as f64 / total as f64) * 100f64);
+            }
+            _ => {}
         },
     )
     .await
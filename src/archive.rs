@@ -54,6 +54,8 @@ pub struct Archive {
     pub stream_map: StreamMap,
     /// Whether this is a solid archive (better compression, slower random access).
     pub is_solid: bool,
+    /// Key-derivation cost of the header encryption, `None` if the header is not encrypted.
+    pub(crate) header_num_cycles_power: Option<u8>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -1,3 +1,8 @@
+use crate::{
+    EncoderMethod,
+    decoder::{get_lzma_dic_size, get_lzma2_dic_size},
+};
+
 /// Represents a compression block.
 ///
 /// A block contains one or more coders (compression/filter methods) that are chained
@@ -68,6 +73,77 @@ impl Block {
     pub fn ordered_coder_iter(&self) -> OrderedCoderIter<'_> {
         OrderedCoderIter::new(self)
     }
+
+    pub(crate) fn is_encrypted(&self) -> bool {
+        self.aes_num_cycles_power().is_some()
+    }
+
+    /// Returns the key-derivation cost of the AES coder of this block, `None` if the block is
+    /// not encrypted. Missing properties are reported as `0`.
+    pub(crate) fn aes_num_cycles_power(&self) -> Option<u8> {
+        self.coders
+            .iter()
+            .find(|coder| coder.encoder_method_id() == EncoderMethod::ID_AES256_SHA256)
+            .map(|coder| coder.properties.first().map_or(0, |b0| b0 & 63))
+    }
+
+    /// Returns the index of the coder that reads the input stream with the given index.
+    #[cfg_attr(not(all(feature = "aes256", feature = "compress")), allow(dead_code))]
+    pub(crate) fn coder_index_for_in_stream(&self, in_index: u64) -> Option<usize> {
+        let mut first = 0;
+        self.coders.iter().position(|coder| {
+            first += coder.num_in_streams;
+            in_index < first
+        })
+    }
+
+    /// Removes the coder with a single input and output at `coder_index` and feeds its input
+    /// directly into the coder that read its output.
+    ///
+    /// If the coder produces the output of the block, it is replaced by a copy coder instead, since
+    /// a block needs at least one coder.
+    #[cfg_attr(not(all(feature = "aes256", feature = "compress")), allow(dead_code))]
+    pub(crate) fn remove_coder(&mut self, coder_index: usize) {
+        let in_index: u64 = self.coders[..coder_index]
+            .iter()
+            .map(|coder| coder.num_in_streams)
+            .sum();
+        let out_index: u64 = self.coders[..coder_index]
+            .iter()
+            .map(|coder| coder.num_out_streams)
+            .sum();
+        let Some(position) = self
+            .bind_pairs
+            .iter()
+            .position(|bp| bp.out_index == out_index)
+        else {
+            self.coders[coder_index] = Coder::copy();
+            return;
+        };
+        let bound_in_index = self.bind_pairs.remove(position).in_index;
+        let renumber = |index: &mut u64, removed: u64| {
+            if *index > removed {
+                *index -= 1;
+            }
+        };
+        for packed in &mut self.packed_streams {
+            if *packed == in_index {
+                *packed = bound_in_index;
+            }
+            renumber(packed, in_index);
+        }
+        for bp in &mut self.bind_pairs {
+            if bp.in_index == in_index {
+                bp.in_index = bound_in_index;
+            }
+            renumber(&mut bp.in_index, in_index);
+            renumber(&mut bp.out_index, out_index);
+        }
+        self.unpack_sizes.remove(out_index as usize);
+        self.coders.remove(coder_index);
+        self.total_input_streams -= 1;
+        self.total_output_streams -= 1;
+    }
 }
 
 /// Represents a single coder within a compression block.
@@ -84,6 +160,32 @@ pub struct Coder {
 }
 
 impl Coder {
+    #[cfg_attr(not(all(feature = "aes256", feature = "compress")), allow(dead_code))]
+    pub(crate) fn copy() -> Self {
+        Self {
+            id_size: EncoderMethod::ID_COPY.len(),
+            num_in_streams: 1,
+            num_out_streams: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a coder with one output stream.
+    #[cfg_attr(not(feature = "compress"), allow(dead_code))]
+    pub(crate) fn new(method_id: &[u8], num_in_streams: u64, properties: Vec<u8>) -> Self {
+        let mut coder = Self {
+            id_size: method_id.len(),
+            num_in_streams,
+            num_out_streams: 1,
+            properties,
+            ..Default::default()
+        };
+        coder
+            .decompression_method_id_mut()
+            .copy_from_slice(method_id);
+        coder
+    }
+
     /// Returns the encoder method ID for this coder.
     ///
     /// This ID identifies the specific compression method, filter, or encryption
@@ -95,6 +197,136 @@ impl Coder {
     pub(crate) fn decompression_method_id_mut(&mut self) -> &mut [u8] {
         &mut self.encoder_method_id[0..self.id_size]
     }
+
+    /// Returns the known method of this coder, `None` if the method ID is not known.
+    pub fn method(&self) -> Option<EncoderMethod> {
+        EncoderMethod::by_id(self.encoder_method_id())
+    }
+
+    /// Returns the raw properties stored for this coder.
+    pub fn properties(&self) -> &[u8] {
+        &self.properties
+    }
+
+    /// Decodes the properties of this coder into typed parameters.
+    ///
+    /// Properties of methods without typed parameters, as well as malformed properties, are
+    /// returned as [`CoderProperties::Raw`].
+    pub fn decoded_properties(&self) -> CoderProperties {
+        let props = self.properties.as_slice();
+        let raw = || CoderProperties::Raw(props.to_vec());
+        match self.encoder_method_id() {
+            _ if props.is_empty() => match self.encoder_method_id() {
+                EncoderMethod::ID_DELTA => CoderProperties::Delta { distance: 1 },
+                id if is_bcj(id) => CoderProperties::Bcj { start_offset: 0 },
+                _ => CoderProperties::None,
+            },
+            EncoderMethod::ID_LZMA => match get_lzma_dic_size(self) {
+                Ok(dict_size) if props[0] < 9 * 5 * 5 => CoderProperties::Lzma {
+                    dict_size,
+                    lc: props[0] % 9,
+                    lp: props[0] / 9 % 5,
+                    pb: props[0] / 9 / 5,
+                },
+                _ => raw(),
+            },
+            EncoderMethod::ID_LZMA2 => match get_lzma2_dic_size(self) {
+                Ok(dict_size) => CoderProperties::Lzma2 { dict_size },
+                Err(_) => raw(),
+            },
+            EncoderMethod::ID_PPMD if props.len() >= 5 => CoderProperties::Ppmd {
+                order: props[0],
+                memory_size: u32::from_le_bytes([props[1], props[2], props[3], props[4]]),
+            },
+            EncoderMethod::ID_DELTA => CoderProperties::Delta {
+                distance: props[0] as u32 + 1,
+            },
+            id if is_bcj(id) && props.len() == 4 => CoderProperties::Bcj {
+                start_offset: u32::from_le_bytes([props[0], props[1], props[2], props[3]]),
+            },
+            EncoderMethod::ID_AES256_SHA256 => {
+                let b0 = props[0];
+                let b1 = props.get(1).copied().unwrap_or_default();
+                CoderProperties::Aes256Sha256 {
+                    num_cycles_power: b0 & 63,
+                    salt_size: ((b0 >> 7) & 1) + (b1 >> 4),
+                    iv_size: ((b0 >> 6) & 1) + (b1 & 15),
+                }
+            }
+            _ => raw(),
+        }
+    }
+}
+
+fn is_bcj(id: &[u8]) -> bool {
+    bcj_alignment(id).is_some()
+}
+
+/// Returns the instruction alignment of a BCJ filter, which its start offset must be a multiple
+/// of, or `None` if `id` is not a BCJ filter.
+pub(crate) fn bcj_alignment(id: &[u8]) -> Option<u32> {
+    match id {
+        EncoderMethod::ID_BCJ_X86 => Some(1),
+        EncoderMethod::ID_BCJ_ARM_THUMB | EncoderMethod::ID_BCJ_RISCV => Some(2),
+        EncoderMethod::ID_BCJ_ARM
+        | EncoderMethod::ID_BCJ_ARM64
+        | EncoderMethod::ID_BCJ_PPC
+        | EncoderMethod::ID_BCJ_SPARC => Some(4),
+        EncoderMethod::ID_BCJ_IA64 => Some(16),
+        _ => None,
+    }
+}
+
+/// Typed parameters decoded from the properties of a [`Coder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoderProperties {
+    /// The coder has no properties.
+    None,
+    /// LZMA parameters.
+    Lzma {
+        /// Dictionary size in bytes.
+        dict_size: u32,
+        /// Number of literal context bits.
+        lc: u8,
+        /// Number of literal position bits.
+        lp: u8,
+        /// Number of position bits.
+        pb: u8,
+    },
+    /// LZMA2 parameters.
+    Lzma2 {
+        /// Dictionary size in bytes.
+        dict_size: u32,
+    },
+    /// PPMd parameters.
+    Ppmd {
+        /// Model order.
+        order: u8,
+        /// Model memory size in bytes.
+        memory_size: u32,
+    },
+    /// Delta filter parameters.
+    Delta {
+        /// Delta distance in bytes.
+        distance: u32,
+    },
+    /// Parameters of the BCJ filters.
+    Bcj {
+        /// Start offset of the filtered data.
+        start_offset: u32,
+    },
+    /// AES-256 + SHA-256 parameters.
+    Aes256Sha256 {
+        /// Key derivation runs `2^num_cycles_power` SHA-256 rounds.
+        num_cycles_power: u8,
+        /// Size of the salt in bytes.
+        salt_size: u8,
+        /// Size of the IV in bytes.
+        iv_size: u8,
+    },
+    /// Raw properties of a method without typed parameters, or properties that could not be
+    /// decoded.
+    Raw(Vec<u8>),
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,70 @@
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use futures_lite::io::AsyncRead;
+
+use crate::Error;
+
+/// A token to cooperatively cancel a running compression or extraction.
+///
+/// Clones share the same state, so a clone can be handed to the operation while the original is
+/// kept to call [`cancel`](Self::cancel) from another task or thread. Operations check the token
+/// between buffers and entries and stop with [`Error::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of all operations observing this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[inline]
+pub(crate) fn check_cancelled(token: &Option<CancellationToken>) -> Result<(), Error> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Fails the next read with [`Error::Cancelled`] once the token is cancelled.
+pub(crate) struct CancellableReader<R> {
+    inner: R,
+    token: Option<CancellationToken>,
+}
+
+impl<R> CancellableReader<R> {
+    pub(crate) fn new(inner: R, token: Option<CancellationToken>) -> Self {
+        Self { inner, token }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CancellableReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if let Err(e) = check_cancelled(&self.token) {
+            return Poll::Ready(Err(std::io::Error::other(e)));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
@@ -1,4 +1,16 @@
+pub(crate) mod bcj2;
 #[cfg(feature = "brotli")]
 pub mod brotli;
+#[cfg(all(feature = "bzip2", feature = "compress"))]
+pub(crate) mod bzip2;
+#[cfg(any(
+    feature = "brotli",
+    feature = "lz4",
+    feature = "zstd",
+    all(feature = "bzip2", feature = "compress")
+))]
+pub(crate) mod framed;
 #[cfg(feature = "lz4")]
 pub mod lz4;
+#[cfg(feature = "zstd")]
+pub(crate) mod zstd;
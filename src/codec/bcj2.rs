@@ -0,0 +1,29 @@
+//! BCJ2 filter, splits x86 code into a main stream and separate streams for the absolute
+//! targets of calls and jumps, which compress better than the relative targets.
+
+mod decoder;
+#[cfg(feature = "compress")]
+mod encoder;
+
+pub(crate) use self::decoder::Bcj2Decoder;
+#[cfg(feature = "compress")]
+pub(crate) use self::encoder::Bcj2Writer;
+
+const NUM_MODEL_BITS: u32 = 11;
+const BIT_MODEL_TOTAL: u16 = 1 << NUM_MODEL_BITS;
+const NUM_MOVE_BITS: u32 = 5;
+const TOP_VALUE: u32 = 1 << 24;
+
+/// Returns whether `byte` following `prev_byte` is the opcode of a `CALL`, `JMP` or `Jcc`.
+fn is_branch(prev_byte: u8, byte: u8) -> bool {
+    byte & 0xFE == 0xE8 || (prev_byte == 0x0F && byte & 0xF0 == 0x80)
+}
+
+/// Returns the index of the probability telling if the branch `opcode` was converted.
+fn prob_index(prev_byte: u8, opcode: u8) -> usize {
+    match opcode {
+        0xE8 => 2 + prev_byte as usize,
+        0xE9 => 1,
+        _ => 0,
+    }
+}
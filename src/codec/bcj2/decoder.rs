@@ -0,0 +1,365 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::io::AsyncRead;
+
+use super::{BIT_MODEL_TOTAL, NUM_MODEL_BITS, NUM_MOVE_BITS, TOP_VALUE, is_branch, prob_index};
+
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// An input stream of a BCJ2 block, read through a buffer of its own.
+struct Input<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+
+impl<R: AsyncRead + Unpin> Input<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the buffered bytes, filling the buffer first if it is empty. An empty slice
+    /// means the stream has ended.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        if self.pos == self.len {
+            let n = match Pin::new(&mut self.inner).poll_read(cx, &mut self.buf) {
+                Poll::Ready(result) => result?,
+                Poll::Pending => return Poll::Pending,
+            };
+            self.pos = 0;
+            self.len = n;
+        }
+        Poll::Ready(Ok(&self.buf[self.pos..self.len]))
+    }
+
+    fn poll_byte(&mut self, cx: &mut Context<'_>, stream: &str) -> Poll<io::Result<u8>> {
+        match self.poll_fill(cx) {
+            Poll::Ready(Ok(&[byte, ..])) => {
+                self.pos += 1;
+                Poll::Ready(Ok(byte))
+            }
+            Poll::Ready(Ok([])) => Poll::Ready(Err(truncated(stream))),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+fn truncated(stream: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("BCJ2 {stream} stream ended early"),
+    )
+}
+
+enum State {
+    /// Reading the first bytes of the range coder, `read` of five so far.
+    Init { read: usize },
+    /// Copying bytes of the main stream.
+    Main,
+    /// The branch `opcode` was copied, the range coder tells if its target was converted.
+    Branch { opcode: u8 },
+    /// Reading the absolute target of a converted branch.
+    Target {
+        opcode: u8,
+        target: [u8; 4],
+        read: usize,
+    },
+    /// Writing the relative target of a converted branch.
+    Relative { target: [u8; 4], written: usize },
+}
+
+/// Restores x86 code from the four streams of a BCJ2 block: the main stream, the targets of
+/// calls, the targets of jumps and the range coded flags telling which branches were converted.
+///
+/// Every stream is polled on its own, so a pending input never blocks the executor.
+pub(crate) struct Bcj2Decoder<R> {
+    main: Input<R>,
+    call: Input<R>,
+    jump: Input<R>,
+    rc: Input<R>,
+    remaining: u64,
+    /// Number of bytes decoded so far, the converted targets are relative to it.
+    pos: u32,
+    prev_byte: u8,
+    probs: [u16; 2 + 256],
+    range: u32,
+    code: u32,
+    state: State,
+    /// An error met after some bytes were decoded, returned by the next read.
+    error: Option<io::Error>,
+}
+
+impl<R: AsyncRead + Unpin> Bcj2Decoder<R> {
+    pub(crate) fn new([main, call, jump, rc]: [R; 4], unpack_size: u64) -> Self {
+        Self {
+            main: Input::new(main),
+            call: Input::new(call),
+            jump: Input::new(jump),
+            rc: Input::new(rc),
+            remaining: unpack_size,
+            pos: 0,
+            prev_byte: 0,
+            probs: [BIT_MODEL_TOTAL >> 1; 2 + 256],
+            range: u32::MAX,
+            code: 0,
+            state: State::Init { read: 0 },
+            error: None,
+        }
+    }
+
+    /// Decodes into `buf`, returning the number of bytes decoded before an input was pending.
+    fn poll_decode(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> (usize, Poll<io::Result<()>>) {
+        let mut n = 0;
+        macro_rules! try_poll {
+            ($e:expr) => {
+                match $e {
+                    Poll::Ready(Ok(value)) => value,
+                    Poll::Ready(Err(e)) => return (n, Poll::Ready(Err(e))),
+                    Poll::Pending => return (n, Poll::Pending),
+                }
+            };
+        }
+        while n < buf.len() {
+            match &mut self.state {
+                State::Init { read } => {
+                    while *read < 5 {
+                        let byte = try_poll!(self.rc.poll_byte(cx, "range coder"));
+                        self.code = (self.code << 8) | byte as u32;
+                        *read += 1;
+                    }
+                    self.state = State::Main;
+                }
+                State::Main => {
+                    let available = try_poll!(self.main.poll_fill(cx));
+                    if available.is_empty() {
+                        return (n, Poll::Ready(Err(truncated("main"))));
+                    }
+                    let count = available.len().min(buf.len() - n);
+                    let mut copied = 0;
+                    while copied < count {
+                        let byte = available[copied];
+                        buf[n + copied] = byte;
+                        copied += 1;
+                        if is_branch(self.prev_byte, byte) {
+                            self.state = State::Branch { opcode: byte };
+                            break;
+                        }
+                        self.prev_byte = byte;
+                    }
+                    self.main.pos += copied;
+                    self.pos = self.pos.wrapping_add(copied as u32);
+                    n += copied;
+                }
+                &mut State::Branch { opcode } => {
+                    // Make sure a byte is at hand in case the range coder needs to be normalized.
+                    let has_byte = !try_poll!(self.rc.poll_fill(cx)).is_empty();
+                    let prob = &mut self.probs[prob_index(self.prev_byte, opcode)];
+                    let bound = (self.range >> NUM_MODEL_BITS) * *prob as u32;
+                    let converted = if self.code < bound {
+                        self.range = bound;
+                        *prob += (BIT_MODEL_TOTAL - *prob) >> NUM_MOVE_BITS;
+                        false
+                    } else {
+                        self.range -= bound;
+                        self.code -= bound;
+                        *prob -= *prob >> NUM_MOVE_BITS;
+                        true
+                    };
+                    if self.range < TOP_VALUE {
+                        if !has_byte {
+                            return (n, Poll::Ready(Err(truncated("range coder"))));
+                        }
+                        self.range <<= 8;
+                        self.code = (self.code << 8) | self.rc.buf[self.rc.pos] as u32;
+                        self.rc.pos += 1;
+                    }
+                    self.state = if converted {
+                        State::Target {
+                            opcode,
+                            target: [0; 4],
+                            read: 0,
+                        }
+                    } else {
+                        self.prev_byte = opcode;
+                        State::Main
+                    };
+                }
+                State::Target {
+                    opcode,
+                    target,
+                    read,
+                } => {
+                    let (input, stream) = match opcode {
+                        0xE8 => (&mut self.call, "call"),
+                        _ => (&mut self.jump, "jump"),
+                    };
+                    while *read < 4 {
+                        target[*read] = try_poll!(input.poll_byte(cx, stream));
+                        *read += 1;
+                    }
+                    // The relative target starts at the position after the instruction.
+                    let relative =
+                        u32::from_be_bytes(*target).wrapping_sub(self.pos.wrapping_add(4));
+                    self.prev_byte = (relative >> 24) as u8;
+                    self.state = State::Relative {
+                        target: relative.to_le_bytes(),
+                        written: 0,
+                    };
+                }
+                State::Relative { target, written } => {
+                    let count = (4 - *written).min(buf.len() - n);
+                    buf[n..n + count].copy_from_slice(&target[*written..*written + count]);
+                    *written += count;
+                    self.pos = self.pos.wrapping_add(count as u32);
+                    n += count;
+                    if *written == 4 {
+                        self.state = State::Main;
+                    }
+                }
+            }
+        }
+        (n, Poll::Ready(Ok(())))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Bcj2Decoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        let limit = buf
+            .len()
+            .min(usize::try_from(this.remaining).unwrap_or(usize::MAX));
+        let (n, result) = this.poll_decode(cx, &mut buf[..limit]);
+        this.remaining -= n as u64;
+        match result {
+            Poll::Ready(Err(e)) if n > 0 => {
+                this.error = Some(e);
+                Poll::Ready(Ok(n))
+            }
+            Poll::Pending if n > 0 => Poll::Ready(Ok(n)),
+            Poll::Ready(result) => Poll::Ready(result.map(|()| n)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compress"))]
+mod tests {
+    use futures_lite::{AsyncReadExt, future::block_on};
+
+    use super::*;
+    use crate::codec::bcj2::encoder::Bcj2Encoder;
+
+    /// Returns one byte per read, and is pending before every other read.
+    struct Stutter<'a> {
+        data: &'a [u8],
+        pending: bool,
+    }
+
+    impl AsyncRead for Stutter<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = self.data.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    fn round_trip(data: &[u8]) {
+        let mut encoder = Bcj2Encoder::new();
+        let mut main = Vec::new();
+        encoder.encode(data, &mut main);
+        let streams = encoder.finish(&mut main);
+        let inputs = [&main, &streams.call, &streams.jump, &streams.rc];
+
+        let mut decoded = Vec::new();
+        let mut decoder = Bcj2Decoder::new(inputs.map(|input| input.as_slice()), data.len() as u64);
+        block_on(decoder.read_to_end(&mut decoded)).unwrap();
+        assert_eq!(decoded, data);
+
+        let mut decoded = Vec::new();
+        let inputs = inputs.map(|input| Stutter {
+            data: input,
+            pending: false,
+        });
+        let mut decoder = Bcj2Decoder::new(inputs, data.len() as u64);
+        let mut chunk = [0; 3];
+        loop {
+            let n = block_on(decoder.read(&mut chunk)).unwrap();
+            if n == 0 {
+                break;
+            }
+            decoded.extend_from_slice(&chunk[..n]);
+        }
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn restores_converted_branches() {
+        let mut data = Vec::new();
+        for i in 0..1000u32 {
+            data.extend_from_slice(&[0x55, 0x8B, 0xEC]);
+            data.push(0xE8);
+            data.extend_from_slice(&(i * 7).wrapping_neg().to_le_bytes());
+            data.push(0xE9);
+            data.extend_from_slice(&(i * 3).to_le_bytes());
+            data.extend_from_slice(&[0x0F, 0x84]);
+            data.extend_from_slice(&(i * 5).to_le_bytes());
+            data.push(0xE8);
+            data.extend_from_slice(&0x4000_0000u32.to_le_bytes());
+        }
+        round_trip(&data);
+        round_trip(&[]);
+        round_trip(&[0xE8]);
+        round_trip(&[0x90, 0xE9, 0xE8, 0xE8, 0x0F, 0x0F, 0x80, 1, 0, 0, 0, 0xE8]);
+    }
+
+    #[test]
+    fn fails_on_truncated_main_stream() {
+        let mut encoder = Bcj2Encoder::new();
+        let mut main = Vec::new();
+        encoder.encode(&[0x90; 16], &mut main);
+        let streams = encoder.finish(&mut main);
+        let inputs = [
+            &main[..8],
+            &streams.call[..],
+            &streams.jump[..],
+            &streams.rc[..],
+        ];
+        let mut decoder = Bcj2Decoder::new(inputs, 16);
+        let mut decoded = Vec::new();
+        let error = block_on(decoder.read_to_end(&mut decoded)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(decoded.len(), 8);
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_lite::io::AsyncWrite;
+
+use super::{BIT_MODEL_TOTAL, NUM_MODEL_BITS, NUM_MOVE_BITS, TOP_VALUE, is_branch, prob_index};
+
+/// Branches are only converted if their relative target is closer than this, farther targets
+/// are most likely not code.
+const RELAT_LIMIT: u32 = 1 << 26;
+
+/// The streams of a BCJ2 block besides the main stream, or the part of them produced by a call to
+/// the encoder.
+#[derive(Debug, Default)]
+pub(crate) struct Bcj2Streams {
+    /// Absolute targets of converted `CALL` instructions, big endian.
+    pub(crate) call: Vec<u8>,
+    /// Absolute targets of converted `JMP` and `Jcc` instructions, big endian.
+    pub(crate) jump: Vec<u8>,
+    /// Range coded flags telling which branches were converted.
+    pub(crate) rc: Vec<u8>,
+}
+
+/// LZMA style range encoder.
+struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    out: Vec<u8>,
+}
+
+impl RangeEncoder {
+    fn new() -> Self {
+        Self {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            out: Vec::new(),
+        }
+    }
+
+    fn encode_bit(&mut self, prob: &mut u16, bit: bool) {
+        let bound = (self.range >> NUM_MODEL_BITS) * *prob as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+            *prob -= *prob >> NUM_MOVE_BITS;
+        } else {
+            self.range = bound;
+            *prob += (BIT_MODEL_TOTAL - *prob) >> NUM_MOVE_BITS;
+        }
+        while self.range < TOP_VALUE {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xFF00_0000 || (self.low >> 32) != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+
+    fn finish(&mut self) {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+    }
+}
+
+/// Converts x86 code into the four streams of a BCJ2 block.
+pub(crate) struct Bcj2Encoder {
+    /// Input that was not converted yet, a branch needs the four bytes following it.
+    pending: Vec<u8>,
+    /// Position of the first pending byte in the input.
+    pos: u32,
+    prev_byte: u8,
+    probs: [u16; 2 + 256],
+    rc: RangeEncoder,
+    call: Vec<u8>,
+    jump: Vec<u8>,
+}
+
+impl Bcj2Encoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            pos: 0,
+            prev_byte: 0,
+            probs: [BIT_MODEL_TOTAL >> 1; 2 + 256],
+            rc: RangeEncoder::new(),
+            call: Vec::new(),
+            jump: Vec::new(),
+        }
+    }
+
+    /// Converts `data`, appending the main stream to `main`. Up to four bytes are kept back
+    /// until the following data or [`finish`](Self::finish) is passed.
+    pub(crate) fn encode(&mut self, data: &[u8], main: &mut Vec<u8>) {
+        self.pending.extend_from_slice(data);
+        self.convert(main, false);
+    }
+
+    /// Returns the parts of the other streams produced since the last call.
+    pub(crate) fn take_streams(&mut self) -> Bcj2Streams {
+        Bcj2Streams {
+            call: std::mem::take(&mut self.call),
+            jump: std::mem::take(&mut self.jump),
+            rc: std::mem::take(&mut self.rc.out),
+        }
+    }
+
+    /// Converts the remaining data and returns the rest of the other streams.
+    pub(crate) fn finish(mut self, main: &mut Vec<u8>) -> Bcj2Streams {
+        self.convert(main, true);
+        self.rc.finish();
+        self.take_streams()
+    }
+
+    fn convert(&mut self, main: &mut Vec<u8>, finish: bool) {
+        let buf = std::mem::take(&mut self.pending);
+        main.reserve(buf.len());
+        let mut i = 0;
+        while i < buf.len() {
+            let b = buf[i];
+            let is_branch = is_branch(self.prev_byte, b);
+            if is_branch && i + 5 > buf.len() && !finish {
+                break;
+            }
+            main.push(b);
+            if !is_branch {
+                self.prev_byte = b;
+                i += 1;
+                continue;
+            }
+            let prob = &mut self.probs[prob_index(self.prev_byte, b)];
+            let relative = buf
+                .get(i + 1..i + 5)
+                .map(|target| u32::from_le_bytes(target.try_into().unwrap()));
+            match relative {
+                Some(relative) if relative.wrapping_add(RELAT_LIMIT) < 2 * RELAT_LIMIT => {
+                    self.rc.encode_bit(prob, true);
+                    // The decoder adds the position after the instruction.
+                    let ip = self.pos.wrapping_add(i as u32 + 5);
+                    let absolute = relative.wrapping_add(ip).to_be_bytes();
+                    match b {
+                        0xE8 => self.call.extend_from_slice(&absolute),
+                        _ => self.jump.extend_from_slice(&absolute),
+                    }
+                    self.prev_byte = buf[i + 4];
+                    i += 5;
+                }
+                _ => {
+                    self.rc.encode_bit(prob, false);
+                    self.prev_byte = b;
+                    i += 1;
+                }
+            }
+        }
+        self.pos = self.pos.wrapping_add(i as u32);
+        self.pending = buf;
+        self.pending.drain(..i);
+    }
+}
+
+/// A writer and the data that was not written to it yet.
+struct Pending<W> {
+    inner: W,
+    buf: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> Pending<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            written: 0,
+        }
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.buf.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Writes the main stream of a BCJ2 block to the inner writer, and the call, jump and range coder
+/// streams to the side writers as they are produced.
+///
+/// Finishing the writer by an empty write or closing it finishes the side writers with an empty
+/// write as well.
+pub(crate) struct Bcj2Writer<W, S> {
+    main: Pending<W>,
+    side: [Pending<S>; 3],
+    /// Number of side writers that were finished.
+    side_finished: usize,
+    encoder: Option<Bcj2Encoder>,
+}
+
+impl<W: AsyncWrite + Unpin, S: AsyncWrite + Unpin> Bcj2Writer<W, S> {
+    pub(crate) fn new(inner: W, [call, jump, rc]: [S; 3]) -> Self {
+        Self {
+            main: Pending::new(inner),
+            side: [Pending::new(call), Pending::new(jump), Pending::new(rc)],
+            side_finished: 0,
+            encoder: Some(Bcj2Encoder::new()),
+        }
+    }
+
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.main.poll_write_buf(cx))?;
+        for side in &mut self.side {
+            ready!(side.poll_write_buf(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn queue_streams(&mut self, streams: Bcj2Streams) {
+        for (side, data) in self
+            .side
+            .iter_mut()
+            .zip([streams.call, streams.jump, streams.rc])
+        {
+            side.buf.extend_from_slice(&data);
+        }
+    }
+
+    fn finish_encoder(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            let streams = encoder.finish(&mut self.main.buf);
+            self.queue_streams(streams);
+        }
+    }
+
+    /// Writes the rest of all streams and finishes the side writers.
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.finish_encoder();
+        ready!(self.poll_write_pending(cx))?;
+        while let Some(side) = self.side.get_mut(self.side_finished) {
+            ready!(Pin::new(&mut side.inner).poll_write(cx, &[]))?;
+            self.side_finished += 1;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin, S: AsyncWrite + Unpin> AsyncWrite for Bcj2Writer<W, S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if buf.is_empty() {
+            ready!(this.poll_finish(cx))?;
+            return Pin::new(&mut this.main.inner).poll_write(cx, buf);
+        }
+        ready!(this.poll_write_pending(cx))?;
+        let Some(encoder) = this.encoder.as_mut() else {
+            return Poll::Ready(Err(io::Error::other("BCJ2 writer is already finished")));
+        };
+        encoder.encode(buf, &mut this.main.buf);
+        let streams = encoder.take_streams();
+        this.queue_streams(streams);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_pending(cx))?;
+        Pin::new(&mut self.main.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_finish(cx))?;
+        Pin::new(&mut self.main.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use futures_lite::{AsyncWriteExt, future::block_on};
+    use lzma_rust2::filter::bcj2::Bcj2Reader;
+
+    use super::*;
+
+    fn round_trip(data: &[u8], chunk_size: usize) -> Bcj2Streams {
+        let mut encoder = Bcj2Encoder::new();
+        let mut main = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            encoder.encode(chunk, &mut main);
+        }
+        let streams = encoder.finish(&mut main);
+        let inputs: Vec<&[u8]> = vec![&main, &streams.call, &streams.jump, &streams.rc];
+        let mut decoded = Vec::new();
+        Bcj2Reader::new(inputs, data.len() as u64)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        streams
+    }
+
+    fn branches() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..1000u32 {
+            data.extend_from_slice(&[0x55, 0x8B, 0xEC]);
+            data.push(0xE8);
+            data.extend_from_slice(&(i * 7).wrapping_neg().to_le_bytes());
+            data.push(0xE9);
+            data.extend_from_slice(&(i * 3).to_le_bytes());
+            data.extend_from_slice(&[0x0F, 0x84]);
+            data.extend_from_slice(&(i * 5).to_le_bytes());
+            // Far targets are kept in the main stream.
+            data.push(0xE8);
+            data.extend_from_slice(&0x4000_0000u32.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn converts_near_branches() {
+        let data = branches();
+        for chunk_size in [1, 3, 4096, data.len()] {
+            let streams = round_trip(&data, chunk_size);
+            assert_eq!(streams.call.len(), 1000 * 4);
+            assert_eq!(streams.jump.len(), 2000 * 4);
+        }
+    }
+
+    #[test]
+    fn writes_side_streams_while_encoding() {
+        let data = branches();
+        let mut main = Vec::new();
+        let mut side = [Vec::new(), Vec::new(), Vec::new()];
+        let [call, jump, rc] = &mut side;
+        let mut writer = Bcj2Writer::new(&mut main, [call, jump, rc]);
+        block_on(async {
+            for chunk in data.chunks(4096) {
+                writer.write_all(chunk).await.unwrap();
+            }
+            // The side streams are handed on before the block is finished.
+            writer.flush().await.unwrap();
+            assert!(writer.side.iter().all(|side| !side.inner.is_empty()));
+            writer.write(&[]).await.unwrap();
+        });
+        drop(writer);
+
+        let [call, jump, rc] = &side;
+        let inputs: Vec<&[u8]> = vec![&main, call, jump, rc];
+        let mut decoded = Vec::new();
+        Bcj2Reader::new(inputs, data.len() as u64)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn keeps_truncated_branches() {
+        round_trip(&[], 1);
+        round_trip(&[0xE8], 1);
+        round_trip(&[0x0F, 0x85, 0, 0], 1);
+        round_trip(
+            &[0x90, 0xE9, 0xE8, 0xE8, 0x0F, 0x0F, 0x80, 1, 0, 0, 0, 0xE8],
+            2,
+        );
+    }
+}
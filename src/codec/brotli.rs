@@ -1,21 +1,28 @@
-#[cfg(feature = "compress")]
-use std::collections::VecDeque;
-#[cfg(feature = "compress")]
 use std::io;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use crate::Error;
+use crate::codec::framed::{FrameDecoder, FrameFn, SKIPPABLE_FRAME_MAGIC};
+#[cfg(feature = "compress")]
+use crate::codec::framed::{FrameEncoder, skippable_frame};
+#[cfg(feature = "compress")]
+use crate::encoder_options::{BrotliMode, BrotliOptions};
+#[cfg(feature = "compress")]
+use async_compression::brotli::EncoderParams;
 use async_compression::futures::bufread::BrotliDecoder as AsyncBrotliDecoder;
 #[cfg(feature = "compress")]
+use async_compression::futures::bufread::BrotliEncoder as AsyncBrotliBufEncoder;
+#[cfg(feature = "compress")]
 use async_compression::futures::write::BrotliEncoder as AsyncBrotliEncoder;
 #[cfg(feature = "compress")]
+use brotli::enc::backward_references::BrotliEncoderMode;
+#[cfg(feature = "compress")]
 use futures_lite::io::AsyncWrite;
 use futures_lite::io::BufReader as AsyncBufReader;
 use futures_lite::io::{AsyncRead, AsyncReadExt, Cursor};
 
-/// Magic bytes of a skippable frame format as used in brotli by zstdmt.
-const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A50;
 /// "BR" in little-endian
 const BROTLI_MAGIC: u16 = 0x5242;
 #[cfg(feature = "compress")]
@@ -23,13 +30,16 @@ const HINT_UNIT_SIZE: usize = 65536;
 
 /// Custom decoder to support the custom format first implemented by zstdmt, which allows to have
 /// optional skippable frames.
+///
+/// Skippable frames are decompressed on up to `threads` threads.
 pub(crate) struct BrotliDecoder<R: AsyncRead + Unpin> {
     inner: Option<AsyncBrotliDecoder<AsyncBufReader<InnerReader<R>>>>,
+    frames: Option<FrameDecoder<R>>,
     buffer_size: usize,
 }
 
 impl<R: AsyncRead + Unpin> BrotliDecoder<R> {
-    pub(crate) fn new(mut input: R, buffer_size: usize) -> Result<Self, Error> {
+    pub(crate) fn new(mut input: R, buffer_size: usize, threads: u32) -> Result<Self, Error> {
         let mut header = [0u8; 16];
         let header_read = match async_io::block_on(AsyncReadExt::read(&mut input, &mut header)) {
             Ok(n) if n >= 4 => n,
@@ -53,6 +63,20 @@ impl<R: AsyncRead + Unpin> BrotliDecoder<R> {
                 return Err(Error::other("Invalid brotli magic value"));
             }
 
+            if threads > 1 {
+                let decompress: FrameFn = Arc::new(decompress_frame);
+                return Ok(BrotliDecoder {
+                    inner: None,
+                    frames: Some(FrameDecoder::new(
+                        input,
+                        decompress,
+                        compressed_size,
+                        threads,
+                    )),
+                    buffer_size,
+                });
+            }
+
             InnerReader::new_skippable(input, compressed_size)
         } else {
             InnerReader::new_standard(input, header[..header_read].to_vec())
@@ -63,6 +87,7 @@ impl<R: AsyncRead + Unpin> BrotliDecoder<R> {
 
         Ok(BrotliDecoder {
             inner: Some(decompressor),
+            frames: None,
             buffer_size,
         })
     }
@@ -74,6 +99,9 @@ impl<R: AsyncRead + Unpin> AsyncRead for BrotliDecoder<R> {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
+        if let Some(frames) = &mut self.frames {
+            return Pin::new(frames).poll_read(cx, buf);
+        }
         if let Some(inner) = &mut self.inner {
             let mut pin_inner = Pin::new(inner);
             match pin_inner.as_mut().poll_read(cx, buf) {
@@ -250,70 +278,77 @@ impl<R: AsyncRead + Unpin> AsyncRead for InnerReader<R> {
 #[cfg(feature = "compress")]
 pub(crate) struct BrotliEncoder<W: AsyncWrite + Unpin> {
     inner: InnerWriter<W>,
-    quality: u32,
 }
 
 #[cfg(feature = "compress")]
 enum InnerWriter<W: AsyncWrite + Unpin> {
-    Standard(AsyncBrotliEncoder<W>),
-    Framed {
-        writer: W,
-        compressor: Option<AsyncBrotliEncoder<Cursor<Vec<u8>>>>,
-        frame_size: usize,
-        uncompressed_bytes_in_frame: usize,
-        pending_frames: VecDeque<Vec<u8>>,
-        pending_offset: usize,
-    },
+    Standard(Box<AsyncBrotliEncoder<W>>),
+    Framed(FrameEncoder<W>),
 }
 
 #[cfg(feature = "compress")]
 impl<W: AsyncWrite + Unpin> BrotliEncoder<W> {
-    pub(crate) fn new(writer: W, quality: u32, frame_size: usize) -> Result<Self, Error> {
-        let inner = if frame_size == 0 {
-            let compressor = AsyncBrotliEncoder::with_quality(
-                writer,
-                async_compression::Level::Precise(quality as i32),
-            );
-            InnerWriter::Standard(compressor)
+    pub(crate) fn new(writer: W, options: &BrotliOptions) -> Result<Self, Error> {
+        let params = encoder_params(options);
+        let inner = if options.skippable_frame_size == 0 {
+            let compressor = AsyncBrotliEncoder::with_params(writer, params);
+            InnerWriter::Standard(Box::new(compressor))
         } else {
-            let cursor = Cursor::new(Vec::with_capacity(frame_size));
-            let compressor = Some(AsyncBrotliEncoder::with_quality(
-                cursor,
-                async_compression::Level::Precise(quality as i32),
-            ));
-            InnerWriter::Framed {
+            let compress: FrameFn = Arc::new(move |data| compress_frame(data, params.clone()));
+            InnerWriter::Framed(FrameEncoder::new(
                 writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame: 0,
-                pending_frames: VecDeque::new(),
-                pending_offset: 0,
-            }
+                compress,
+                options.skippable_frame_size as usize,
+                options.threads,
+            ))
         };
 
-        Ok(Self { inner, quality })
+        Ok(Self { inner })
     }
+}
 
-    #[cfg(feature = "compress")]
-    fn build_frame_bytes(compressed_data: &[u8], uncompressed_bytes: usize) -> Vec<u8> {
-        if compressed_data.is_empty() {
-            return Vec::new();
-        }
-        let mut out = Vec::with_capacity(12 + 2 + 2 + compressed_data.len());
-        out.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
-        out.extend_from_slice(&(8u32).to_le_bytes());
-        out.extend_from_slice(&(compressed_data.len() as u32).to_le_bytes());
-        out.extend_from_slice(&BROTLI_MAGIC.to_le_bytes());
-        let hint_value = uncompressed_bytes.div_ceil(HINT_UNIT_SIZE);
-        let hint_value = if hint_value > usize::from(u16::MAX) {
-            u16::MAX
-        } else {
-            hint_value as u16
-        };
-        out.extend_from_slice(&hint_value.to_le_bytes());
-        out.extend_from_slice(compressed_data);
-        out
-    }
+#[cfg(feature = "compress")]
+fn encoder_params(options: &BrotliOptions) -> EncoderParams {
+    let mode = match options.mode {
+        BrotliMode::Generic => BrotliEncoderMode::BROTLI_MODE_GENERIC,
+        BrotliMode::Text => BrotliEncoderMode::BROTLI_MODE_TEXT,
+        BrotliMode::Font => BrotliEncoderMode::BROTLI_MODE_FONT,
+    };
+    EncoderParams::default()
+        .quality(async_compression::Level::Precise(options.quality as i32))
+        .window_size(options.window_size as i32)
+        .mode(mode)
+}
+
+/// Compresses a chunk into a brotli stream preceded by its skippable frame.
+#[cfg(feature = "compress")]
+fn compress_frame(data: Vec<u8>, params: EncoderParams) -> io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    let mut compressor = AsyncBrotliBufEncoder::with_params(data.as_slice(), params);
+    async_io::block_on(AsyncReadExt::read_to_end(&mut compressor, &mut compressed))?;
+
+    let hint_value = data.len().div_ceil(HINT_UNIT_SIZE);
+    let hint_value = if hint_value > usize::from(u16::MAX) {
+        u16::MAX
+    } else {
+        hint_value as u16
+    };
+    let mut content = [0u8; 8];
+    content[0..4].copy_from_slice(&(compressed.len() as u32).to_le_bytes());
+    content[4..6].copy_from_slice(&BROTLI_MAGIC.to_le_bytes());
+    content[6..8].copy_from_slice(&hint_value.to_le_bytes());
+    Ok(skippable_frame(&content, &compressed))
+}
+
+/// Decompresses the brotli stream of a skippable frame.
+fn decompress_frame(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    let mut decompressor = AsyncBrotliDecoder::new(data.as_slice());
+    async_io::block_on(AsyncReadExt::read_to_end(
+        &mut decompressor,
+        &mut decompressed,
+    ))?;
+    Ok(decompressed)
 }
 
 #[cfg(feature = "compress")]
@@ -322,246 +357,24 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for BrotliEncoder<W> {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
-    ) -> Poll<std::io::Result<usize>> {
-        let quality = self.quality;
+    ) -> Poll<io::Result<usize>> {
         match &mut self.inner {
-            InnerWriter::Standard(compressor) => {
-                let mut pin = Pin::new(compressor);
-                pin.as_mut().poll_write(cx, buf)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset < front.len() {
-                        match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                            Poll::Ready(Ok(w)) => {
-                                if w == 0 {
-                                    return Poll::Ready(Ok(0));
-                                }
-                                *pending_offset += w;
-                                if *pending_offset >= front.len() {
-                                    pending_frames.pop_front();
-                                    *pending_offset = 0;
-                                }
-                            }
-                            Poll::Ready(Err(e)) => {
-                                return Poll::Ready(Err(e));
-                            }
-                            Poll::Pending => {}
-                        }
-                    }
-                }
-
-                if buf.is_empty() {
-                    return Poll::Ready(Ok(0));
-                }
-
-                let cap = *frame_size - *uncompressed_bytes_in_frame;
-                let to_write = std::cmp::min(buf.len(), cap);
-                if to_write == 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame =
-                                Self::build_frame_bytes(&data, *uncompressed_bytes_in_frame);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncBrotliEncoder::with_quality(
-                                new_cursor,
-                                async_compression::Level::Precise(quality as i32),
-                            ));
-                            *uncompressed_bytes_in_frame = 0;
-                            Poll::Pending
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
-                    }
-                } else {
-                    let comp = compressor.as_mut().expect("no compressor set");
-                    let mut pin = Pin::new(comp);
-                    match pin.as_mut().poll_write(cx, &buf[..to_write]) {
-                        Poll::Ready(Ok(n)) => {
-                            *uncompressed_bytes_in_frame += n;
-                            if *uncompressed_bytes_in_frame >= *frame_size {
-                                let mut comp2 = compressor.take().expect("no compressor set");
-                                let mut pin2 = Pin::new(&mut comp2);
-                                match pin2.as_mut().poll_close(cx) {
-                                    Poll::Ready(Ok(())) => {
-                                        let cursor = comp2.into_inner();
-                                        let data = cursor.into_inner();
-                                        let frame = Self::build_frame_bytes(
-                                            &data,
-                                            *uncompressed_bytes_in_frame,
-                                        );
-                                        if !frame.is_empty() {
-                                            pending_frames.push_back(frame);
-                                        }
-                                        let new_cursor =
-                                            Cursor::new(Vec::with_capacity(*frame_size));
-                                        *compressor = Some(AsyncBrotliEncoder::with_quality(
-                                            new_cursor,
-                                            async_compression::Level::Precise(quality as i32),
-                                        ));
-                                        *uncompressed_bytes_in_frame = 0;
-                                    }
-                                    Poll::Ready(Err(e)) => {
-                                        return Poll::Ready(Err(e));
-                                    }
-                                    Poll::Pending => return Poll::Pending,
-                                }
-                            }
-                            Poll::Ready(Ok(n))
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
-                    }
-                }
-            }
+            InnerWriter::Standard(compressor) => Pin::new(compressor.as_mut()).poll_write(cx, buf),
+            InnerWriter::Framed(compressor) => Pin::new(compressor).poll_write(cx, buf),
         }
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-        let quality = self.quality;
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match &mut self.inner {
-            InnerWriter::Standard(compressor) => {
-                let mut pin = Pin::new(compressor);
-                pin.as_mut().poll_flush(cx)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if *uncompressed_bytes_in_frame > 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame =
-                                Self::build_frame_bytes(&data, *uncompressed_bytes_in_frame);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncBrotliEncoder::with_quality(
-                                new_cursor,
-                                async_compression::Level::Precise(quality as i32),
-                            ));
-                            *uncompressed_bytes_in_frame = 0;
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                while let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset >= front.len() {
-                        pending_frames.pop_front();
-                        *pending_offset = 0;
-                        continue;
-                    }
-                    match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                        Poll::Ready(Ok(w)) => {
-                            if w == 0 {
-                                return Poll::Pending;
-                            }
-                            *pending_offset += w;
-                            if *pending_offset >= front.len() {
-                                pending_frames.pop_front();
-                                *pending_offset = 0;
-                            }
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                let mut pin = Pin::new(&mut *writer);
-                pin.as_mut().poll_flush(cx)
-            }
+            InnerWriter::Standard(compressor) => Pin::new(compressor.as_mut()).poll_flush(cx),
+            InnerWriter::Framed(compressor) => Pin::new(compressor).poll_flush(cx),
         }
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-        let quality = self.quality;
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match &mut self.inner {
-            InnerWriter::Standard(compressor) => {
-                let mut pin = Pin::new(compressor);
-                pin.as_mut().poll_close(cx)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if *uncompressed_bytes_in_frame > 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame =
-                                Self::build_frame_bytes(&data, *uncompressed_bytes_in_frame);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncBrotliEncoder::with_quality(
-                                new_cursor,
-                                async_compression::Level::Precise(quality as i32),
-                            ));
-                            *uncompressed_bytes_in_frame = 0;
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                while let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset >= front.len() {
-                        pending_frames.pop_front();
-                        *pending_offset = 0;
-                        continue;
-                    }
-                    match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                        Poll::Ready(Ok(w)) => {
-                            if w == 0 {
-                                return Poll::Pending;
-                            }
-                            *pending_offset += w;
-                            if *pending_offset >= front.len() {
-                                pending_frames.pop_front();
-                                *pending_offset = 0;
-                            }
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                let mut pin = Pin::new(&mut *writer);
-                pin.as_mut().poll_close(cx)
-            }
+            InnerWriter::Standard(compressor) => Pin::new(compressor.as_mut()).poll_close(cx),
+            InnerWriter::Framed(compressor) => Pin::new(compressor).poll_close(cx),
         }
     }
 }
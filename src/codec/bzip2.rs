@@ -0,0 +1,243 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::codec::framed::{FrameEncoder, FrameFn, FrameJoiner};
+use crate::encoder_options::Bzip2Options;
+use async_compression::Level;
+use async_compression::futures::bufread::BzEncoder as AsyncBzip2BufEncoder;
+use async_compression::futures::write::BzEncoder as AsyncBzip2Encoder;
+use futures_lite::io::{AsyncReadExt, AsyncWrite};
+
+/// Magic number starting every compressed block.
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+/// Magic number starting the end of stream marker.
+const END_OF_STREAM_MAGIC: u64 = 0x1772_4538_5090;
+/// Bits of the end of stream marker, its magic number followed by the stream CRC.
+const END_OF_STREAM_BITS: u64 = 48 + 32;
+/// Bytes of the stream header "BZh" and the level.
+const HEADER_LEN: usize = 4;
+
+/// BZIP2 encoder, which compresses blocks on several threads if more than one is configured.
+///
+/// bzip2 blocks are independent of each other, so the input is split into chunks that fit into
+/// one block each. Every chunk is compressed on its own and the blocks are then joined into a
+/// single stream, which is what a sequential encoder would have written.
+pub(crate) struct Bzip2Encoder<W: AsyncWrite + Unpin> {
+    inner: InnerWriter<W>,
+}
+
+enum InnerWriter<W: AsyncWrite + Unpin> {
+    Standard(AsyncBzip2Encoder<W>),
+    Parallel(FrameEncoder<W>),
+}
+
+impl<W: AsyncWrite + Unpin> Bzip2Encoder<W> {
+    pub(crate) fn new(writer: W, options: &Bzip2Options) -> Self {
+        let level = options.level;
+        let inner = if options.threads <= 1 {
+            InnerWriter::Standard(AsyncBzip2Encoder::with_quality(
+                writer,
+                Level::Precise(level as i32),
+            ))
+        } else {
+            let compress: FrameFn = Arc::new(move |data| compress_block(data, level));
+            let encoder = FrameEncoder::new(writer, compress, chunk_size(level), options.threads);
+            InnerWriter::Parallel(encoder.with_joiner(BlockJoiner::new(level)))
+        };
+
+        Self { inner }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        match self.inner {
+            InnerWriter::Standard(encoder) => encoder.into_inner(),
+            InnerWriter::Parallel(encoder) => encoder.into_inner(),
+        }
+    }
+}
+
+/// Largest chunk of input that always fits into a single block.
+///
+/// A block holds up to `100000 * level - 19` bytes after the initial run-length encoding, which
+/// expands the input by at most 5/4. A few bytes are kept free for a run that is still pending
+/// when the input ends.
+fn chunk_size(level: u32) -> usize {
+    let block_size = 100_000 * level as usize - 19;
+    (block_size - 5) * 4 / 5
+}
+
+/// Compresses a chunk into a stream of one block and returns the block.
+///
+/// The result is the CRC of the block as little endian `u32`, followed by the length of the
+/// block in bits as little endian `u64` and the bits of the block. Empty input gives no block and
+/// a length of 0.
+fn compress_block(data: Vec<u8>, level: u32) -> io::Result<Vec<u8>> {
+    let mut stream = Vec::new();
+    let mut encoder =
+        AsyncBzip2BufEncoder::with_quality(data.as_slice(), Level::Precise(level as i32));
+    async_io::block_on(AsyncReadExt::read_to_end(&mut encoder, &mut stream))?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unexpected bzip2 stream");
+    let total_bits = stream.len() as u64 * 8;
+    let header_bits = HEADER_LEN as u64 * 8;
+    let (end, stream_crc) = (0..8)
+        .filter(|&padding| total_bits >= header_bits + END_OF_STREAM_BITS + padding)
+        .find_map(|padding| {
+            let end = total_bits - padding - END_OF_STREAM_BITS;
+            let padded = read_bits(&stream, total_bits - padding, padding as u32) == 0;
+            let magic = read_bits(&stream, end, 48) == END_OF_STREAM_MAGIC;
+            (padded && magic).then(|| (end, read_bits(&stream, end + 48, 32) as u32))
+        })
+        .ok_or_else(invalid)?;
+
+    let block_bits = end - header_bits;
+    let mut block = Vec::with_capacity(12 + stream.len() - HEADER_LEN);
+    if block_bits == 0 {
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&0u64.to_le_bytes());
+        return Ok(block);
+    }
+
+    // The combined CRC of a stream with a single block is the CRC of that block, anything else
+    // means the chunk was split into several blocks.
+    let block_crc = read_bits(&stream, header_bits + 48, 32) as u32;
+    if read_bits(&stream, header_bits, 48) != BLOCK_MAGIC || block_crc != stream_crc {
+        return Err(invalid());
+    }
+
+    block.extend_from_slice(&block_crc.to_le_bytes());
+    block.extend_from_slice(&block_bits.to_le_bytes());
+    block.extend_from_slice(&stream[HEADER_LEN..]);
+    Ok(block)
+}
+
+/// Reads `count` bits starting at bit `position`, most significant bit first.
+fn read_bits(data: &[u8], position: u64, count: u32) -> u64 {
+    (position..position + count as u64).fold(0, |value, bit| {
+        let byte = data[(bit / 8) as usize];
+        (value << 1) | ((byte >> (7 - bit % 8)) & 1) as u64
+    })
+}
+
+/// Joins the blocks of [`compress_block`] into one bzip2 stream.
+struct BlockJoiner {
+    level: u32,
+    started: bool,
+    combined_crc: u32,
+    pending: u32,
+    pending_bits: u32,
+}
+
+impl BlockJoiner {
+    fn new(level: u32) -> Self {
+        Self {
+            level,
+            started: false,
+            combined_crc: 0,
+            pending: 0,
+            pending_bits: 0,
+        }
+    }
+
+    fn start(&mut self, output: &mut Vec<u8>) {
+        if !self.started {
+            self.started = true;
+            output.extend_from_slice(b"BZh");
+            output.push(b'0' + self.level as u8);
+        }
+    }
+
+    /// Appends the lowest `count` bits of `value`, at most 8.
+    fn push(&mut self, output: &mut Vec<u8>, value: u8, count: u32) {
+        let mut bits = self.pending_bits + count;
+        let mut buffer = (self.pending << count) | value as u32 & ((1 << count) - 1);
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+        self.pending = buffer;
+        self.pending_bits = bits;
+    }
+
+    fn push_bits(&mut self, output: &mut Vec<u8>, data: &[u8], count: u64) {
+        let bytes = (count / 8) as usize;
+        let remaining = (count % 8) as u32;
+        if self.pending_bits == 0 {
+            output.extend_from_slice(&data[..bytes]);
+        } else {
+            for &byte in &data[..bytes] {
+                self.push(output, byte, 8);
+            }
+        }
+        if remaining > 0 {
+            self.push(output, data[bytes] >> (8 - remaining), remaining);
+        }
+    }
+}
+
+impl FrameJoiner for BlockJoiner {
+    fn join(&mut self, frame: Vec<u8>) -> io::Result<Vec<u8>> {
+        if frame.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated bzip2 block",
+            ));
+        }
+        let crc = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        let mut bits = [0u8; 8];
+        bits.copy_from_slice(&frame[4..12]);
+        let bits = u64::from_le_bytes(bits);
+
+        let mut output = Vec::with_capacity(frame.len());
+        self.start(&mut output);
+        if bits > 0 {
+            self.combined_crc = self.combined_crc.rotate_left(1) ^ crc;
+            self.push_bits(&mut output, &frame[12..], bits);
+        }
+        Ok(output)
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(16);
+        self.start(&mut output);
+        let mut end = [0u8; 10];
+        end[..8].copy_from_slice(&(END_OF_STREAM_MAGIC << 16).to_be_bytes());
+        end[6..].copy_from_slice(&self.combined_crc.to_be_bytes());
+        self.push_bits(&mut output, &end, END_OF_STREAM_BITS);
+        if self.pending_bits > 0 {
+            output.push((self.pending << (8 - self.pending_bits)) as u8);
+            self.pending_bits = 0;
+        }
+        output
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Bzip2Encoder<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_write(cx, buf),
+            InnerWriter::Parallel(encoder) => Pin::new(encoder).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_flush(cx),
+            InnerWriter::Parallel(encoder) => Pin::new(encoder).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_close(cx),
+            InnerWriter::Parallel(encoder) => Pin::new(encoder).poll_close(cx),
+        }
+    }
+}
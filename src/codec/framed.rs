@@ -0,0 +1,375 @@
+//! Encoding and decoding of the frame format first implemented by zstdmt.
+//!
+//! The input is split into chunks which are compressed into independent frames. Every frame is
+//! preceded by a skippable frame holding its compressed size, so frames can be located without
+//! decoding the frames before them, and several frames can be converted at once.
+//!
+//! Formats without such frames can still be compressed in parallel chunks, if a [`FrameJoiner`]
+//! merges the compressed chunks into one stream.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use blocking::{Task, unblock};
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+use futures_lite::io::AsyncRead;
+#[cfg(feature = "compress")]
+use futures_lite::io::AsyncWrite;
+
+/// Magic bytes of a skippable frame as used by zstdmt.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+pub(crate) const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A50;
+
+/// Size of the chunks read from a frame.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Converts one frame. Compressing turns a chunk of input into a frame including its skippable
+/// header, decompressing turns the data of a frame into its content.
+pub(crate) type FrameFn = Arc<dyn Fn(Vec<u8>) -> io::Result<Vec<u8>> + Send + Sync>;
+
+/// Builds a skippable frame with the given content, followed by `data`.
+#[cfg(all(
+    feature = "compress",
+    any(feature = "brotli", feature = "lz4", feature = "zstd")
+))]
+pub(crate) fn skippable_frame(content: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + content.len() + data.len());
+    out.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+    out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(data);
+    out
+}
+
+/// Frame conversions running on up to `threads` threads, whose results are taken in order.
+struct FrameJobs {
+    convert: FrameFn,
+    threads: usize,
+    jobs: VecDeque<Task<io::Result<Vec<u8>>>>,
+}
+
+impl FrameJobs {
+    fn new(convert: FrameFn, threads: u32) -> Self {
+        Self {
+            convert,
+            threads: threads.max(1) as usize,
+            jobs: VecDeque::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    fn push(&mut self, data: Vec<u8>) {
+        let convert = Arc::clone(&self.convert);
+        self.jobs.push_back(unblock(move || convert(data)));
+    }
+
+    /// Waits for the oldest conversion, returns `None` if none is running.
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Vec<u8>>>> {
+        let Some(job) = self.jobs.front_mut() else {
+            return Poll::Ready(None);
+        };
+        let result = ready!(Pin::new(job).poll(cx));
+        self.jobs.pop_front();
+        Poll::Ready(Some(result))
+    }
+}
+
+/// Merges compressed chunks into one stream, for formats whose frames can't be concatenated.
+#[cfg(feature = "compress")]
+pub(crate) trait FrameJoiner: Send {
+    /// Returns the bytes written for the next compressed chunk.
+    fn join(&mut self, frame: Vec<u8>) -> io::Result<Vec<u8>>;
+
+    /// Returns the bytes written after the last chunk.
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+/// Compresses chunks of `frame_size` bytes into frames on several threads.
+///
+/// Flushing compresses the remaining input as a shorter frame. A write of an empty buffer or
+/// closing does the same and ends the stream.
+#[cfg(feature = "compress")]
+pub(crate) struct FrameEncoder<W> {
+    inner: W,
+    jobs: FrameJobs,
+    joiner: Option<Box<dyn FrameJoiner>>,
+    frame_size: usize,
+    chunk: Vec<u8>,
+    frames: usize,
+    finished: bool,
+    output: Vec<u8>,
+    written: usize,
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> FrameEncoder<W> {
+    pub(crate) fn new(inner: W, compress: FrameFn, frame_size: usize, threads: u32) -> Self {
+        Self {
+            inner,
+            jobs: FrameJobs::new(compress, threads),
+            joiner: None,
+            frame_size: frame_size.max(1),
+            chunk: Vec::new(),
+            frames: 0,
+            finished: false,
+            output: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Writes the compressed chunks through `joiner` instead of writing them as they are.
+    #[cfg(feature = "bzip2")]
+    pub(crate) fn with_joiner(mut self, joiner: impl FrameJoiner + 'static) -> Self {
+        self.joiner = Some(Box::new(joiner));
+        self
+    }
+
+    #[cfg(any(feature = "bzip2", feature = "lz4", feature = "zstd"))]
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn push_chunk(&mut self) {
+        let chunk = std::mem::take(&mut self.chunk);
+        self.jobs.push(chunk);
+        self.frames += 1;
+    }
+
+    /// Writes compressed frames until at most `max_jobs` compressions are running.
+    fn poll_drain(&mut self, cx: &mut Context<'_>, max_jobs: usize) -> Poll<io::Result<()>> {
+        loop {
+            while self.written < self.output.len() {
+                let n =
+                    ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.written += n;
+            }
+            if self.jobs.len() <= max_jobs {
+                return Poll::Ready(Ok(()));
+            }
+            if let Some(frame) = ready!(self.jobs.poll_next(cx)) {
+                self.output = match &mut self.joiner {
+                    Some(joiner) => joiner.join(frame?)?,
+                    None => frame?,
+                };
+                self.written = 0;
+            }
+        }
+    }
+
+    /// Compresses the remaining input and writes all frames. At least one frame is written, so
+    /// that empty input still gives a valid stream.
+    fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.chunk.is_empty() || self.frames == 0 {
+            self.push_chunk();
+        }
+        self.poll_drain(cx, 0)
+    }
+
+    /// Writes all frames and ends the stream.
+    pub(crate) fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_frames(cx))?;
+        if !self.finished {
+            self.finished = true;
+            if let Some(joiner) = &mut self.joiner {
+                self.output = joiner.finish();
+                self.written = 0;
+            }
+        }
+        self.poll_drain(cx, 0)
+    }
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> AsyncWrite for FrameEncoder<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if buf.is_empty() {
+            ready!(this.poll_finish(cx))?;
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        if this.chunk.len() >= this.frame_size {
+            ready!(this.poll_drain(cx, this.jobs.threads - 1))?;
+            this.push_chunk();
+        }
+        let size = buf.len().min(this.frame_size - this.chunk.len());
+        if this.chunk.is_empty() {
+            this.chunk.reserve_exact(this.frame_size);
+        }
+        this.chunk.extend_from_slice(&buf[..size]);
+        if this.chunk.len() >= this.frame_size && this.jobs.len() < this.jobs.threads {
+            this.push_chunk();
+        }
+        Poll::Ready(Ok(size))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_frames(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_finish(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// What [`FrameDecoder`] is reading from its input.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+enum ReadState {
+    /// The skippable frame header, magic and content size.
+    Header,
+    /// The content of the skippable frame, starting with the compressed size.
+    Content,
+    /// The data of the frame.
+    Frame,
+    /// The input ended after a frame.
+    Done,
+}
+
+/// Decompresses the frames of a stream on several threads.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+pub(crate) struct FrameDecoder<R> {
+    input: R,
+    jobs: FrameJobs,
+    state: ReadState,
+    buf: Vec<u8>,
+    needed: usize,
+    output: Vec<u8>,
+    read: usize,
+}
+
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+impl<R: AsyncRead + Unpin> FrameDecoder<R> {
+    /// Creates a decoder whose input starts with the data of a frame of `frame_size` bytes,
+    /// its skippable header was already read.
+    pub(crate) fn new(input: R, decompress: FrameFn, frame_size: u32, threads: u32) -> Self {
+        Self {
+            input,
+            jobs: FrameJobs::new(decompress, threads),
+            state: ReadState::Frame,
+            buf: Vec::new(),
+            needed: frame_size as usize,
+            output: Vec::new(),
+            read: 0,
+        }
+    }
+
+    /// Reads the next frame, returns `None` at the end of the input.
+    fn poll_read_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        if matches!(self.state, ReadState::Done) {
+            return Poll::Ready(Ok(None));
+        }
+        loop {
+            while self.buf.len() < self.needed {
+                let start = self.buf.len();
+                let size = (self.needed - start).min(READ_CHUNK_SIZE);
+                self.buf.resize(start + size, 0);
+                let result = ready_or_truncate(
+                    Pin::new(&mut self.input).poll_read(cx, &mut self.buf[start..]),
+                    &mut self.buf,
+                    start,
+                );
+                let n = ready!(result)?;
+                self.buf.truncate(start + n);
+                if n == 0 {
+                    if start == 0 && matches!(self.state, ReadState::Header) {
+                        self.state = ReadState::Done;
+                        return Poll::Ready(Ok(None));
+                    }
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+            }
+            let buf = std::mem::take(&mut self.buf);
+            match self.state {
+                ReadState::Header => {
+                    let magic = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                    if magic & 0xFFFFFFF0 != SKIPPABLE_FRAME_MAGIC {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "expected a skippable frame header",
+                        )));
+                    }
+                    let size = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                    if size < 4 {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "skippable frame too short",
+                        )));
+                    }
+                    self.state = ReadState::Content;
+                    self.needed = size;
+                }
+                ReadState::Content => {
+                    self.state = ReadState::Frame;
+                    self.needed = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+                }
+                ReadState::Frame => {
+                    self.state = ReadState::Header;
+                    self.needed = 8;
+                    return Poll::Ready(Ok(Some(buf)));
+                }
+                ReadState::Done => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Drops the bytes reserved for a read that did not complete.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+fn ready_or_truncate<T>(poll: Poll<T>, buf: &mut Vec<u8>, len: usize) -> Poll<T> {
+    if poll.is_pending() {
+        buf.truncate(len);
+    }
+    poll
+}
+
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+impl<R: AsyncRead + Unpin> AsyncRead for FrameDecoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            if this.read < this.output.len() {
+                let size = buf.len().min(this.output.len() - this.read);
+                buf[..size].copy_from_slice(&this.output[this.read..this.read + size]);
+                this.read += size;
+                return Poll::Ready(Ok(size));
+            }
+            while this.jobs.len() < this.jobs.threads {
+                match this.poll_read_frame(cx)? {
+                    Poll::Ready(Some(frame)) => this.jobs.push(frame),
+                    Poll::Ready(None) | Poll::Pending => break,
+                }
+            }
+            match ready!(this.jobs.poll_next(cx)) {
+                Some(output) => {
+                    this.output = output?;
+                    this.read = 0;
+                }
+                None if matches!(this.state, ReadState::Done) => return Poll::Ready(Ok(0)),
+                // Waiting for the input.
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -1,28 +1,40 @@
 #[cfg(feature = "compress")]
-use std::collections::VecDeque;
+use std::future::poll_fn;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use crate::Error;
+use crate::codec::framed::{FrameDecoder, FrameFn, SKIPPABLE_FRAME_MAGIC};
+#[cfg(feature = "compress")]
+use crate::codec::framed::{FrameEncoder, skippable_frame};
+#[cfg(feature = "compress")]
+use crate::encoder_options::{Lz4BlockSize, Lz4Options};
+#[cfg(feature = "compress")]
+use async_compression::Level;
 use async_compression::futures::bufread::Lz4Decoder as AsyncLz4Decoder;
 #[cfg(feature = "compress")]
+use async_compression::futures::bufread::Lz4Encoder as AsyncLz4BufEncoder;
+#[cfg(feature = "compress")]
 use async_compression::futures::write::Lz4Encoder as AsyncLz4Encoder;
+#[cfg(feature = "compress")]
+use async_compression::lz4::{BlockSize, EncoderParams};
 use futures_lite::io::BufReader as AsyncBufReader;
 use futures_lite::io::{AsyncRead, AsyncReadExt, Cursor};
 #[cfg(feature = "compress")]
 use futures_lite::io::{AsyncWrite, AsyncWriteExt};
 
-/// Magic bytes of a skippable frame as used in LZ4 by zstdmt.
-const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A50;
-
 /// Custom decoder to support the custom format first implemented by zstdmt, which allows to have
 /// optional skippable frames.
+///
+/// Skippable frames are decompressed on up to `threads` threads.
 pub(crate) struct Lz4Decoder<R: AsyncRead + Unpin> {
     inner: Option<AsyncLz4Decoder<AsyncBufReader<InnerReader<R>>>>,
+    frames: Option<FrameDecoder<R>>,
 }
 
 impl<R: AsyncRead + Unpin> Lz4Decoder<R> {
-    pub(crate) async fn new(mut input: R) -> Result<Self, Error> {
+    pub(crate) async fn new(mut input: R, threads: u32) -> Result<Self, Error> {
         let mut header = [0u8; 12];
         let header_read = match AsyncReadExt::read(&mut input, &mut header).await {
             Ok(n) if n >= 4 => n,
@@ -41,6 +53,19 @@ impl<R: AsyncRead + Unpin> Lz4Decoder<R> {
             let compressed_size =
                 u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
 
+            if threads > 1 {
+                let decompress: FrameFn = Arc::new(decompress_frame);
+                return Ok(Lz4Decoder {
+                    inner: None,
+                    frames: Some(FrameDecoder::new(
+                        input,
+                        decompress,
+                        compressed_size,
+                        threads,
+                    )),
+                });
+            }
+
             InnerReader::new_skippable(input, compressed_size)
         } else {
             InnerReader::new_standard(input, header[..header_read].to_vec())
@@ -51,6 +76,7 @@ impl<R: AsyncRead + Unpin> Lz4Decoder<R> {
 
         Ok(Lz4Decoder {
             inner: Some(decoder),
+            frames: None,
         })
     }
 }
@@ -61,6 +87,9 @@ impl<R: AsyncRead + Unpin> AsyncRead for Lz4Decoder<R> {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
+        if let Some(frames) = &mut self.frames {
+            return Pin::new(frames).poll_read(cx, buf);
+        }
         if let Some(inner) = &mut self.inner {
             let mut pin_inner = Pin::new(inner);
             match pin_inner.as_mut().poll_read(cx, buf) {
@@ -231,82 +260,76 @@ pub(crate) struct Lz4Encoder<W: AsyncWrite + Unpin> {
 #[cfg(feature = "compress")]
 enum InnerWriter<W: AsyncWrite + Unpin> {
     Standard(AsyncLz4Encoder<W>),
-    Framed {
-        writer: W,
-        compressor: Option<AsyncLz4Encoder<Cursor<Vec<u8>>>>,
-        frame_size: usize,
-        uncompressed_bytes_in_frame: usize,
-        pending_frames: VecDeque<Vec<u8>>,
-        pending_offset: usize,
-    },
+    Framed(FrameEncoder<W>),
 }
 
 #[cfg(feature = "compress")]
 impl<W: AsyncWrite + Unpin> Lz4Encoder<W> {
-    pub(crate) fn new(writer: W, frame_size: usize) -> Result<Self, Error> {
-        let inner = if frame_size == 0 {
-            let encoder = AsyncLz4Encoder::new(writer);
+    pub(crate) fn new(writer: W, options: &Lz4Options) -> Result<Self, Error> {
+        let level = Level::Precise(options.level as i32);
+        let params = encoder_params(options);
+        let inner = if options.skippable_frame_size == 0 {
+            let encoder = AsyncLz4Encoder::with_quality_and_params(writer, level, params);
             InnerWriter::Standard(encoder)
         } else {
-            let cursor = Cursor::new(Vec::with_capacity(frame_size));
-            let compressor = Some(AsyncLz4Encoder::new(cursor));
-            InnerWriter::Framed {
+            let compress: FrameFn =
+                Arc::new(move |data| compress_frame(data, level, params.clone()));
+            InnerWriter::Framed(FrameEncoder::new(
                 writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame: 0,
-                pending_frames: VecDeque::new(),
-                pending_offset: 0,
-            }
+                compress,
+                options.skippable_frame_size as usize,
+                options.threads,
+            ))
         };
 
         Ok(Self { inner })
     }
 
-    fn build_frame_bytes(compressed_data: &[u8]) -> Vec<u8> {
-        if compressed_data.is_empty() {
-            return Vec::new();
-        }
-        let mut out = Vec::with_capacity(12 + compressed_data.len());
-        out.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
-        out.extend_from_slice(&(4u32).to_le_bytes());
-        out.extend_from_slice(&(compressed_data.len() as u32).to_le_bytes());
-        out.extend_from_slice(compressed_data);
-        out
-    }
-
     pub fn finish(self) -> std::io::Result<W> {
         match self.inner {
             InnerWriter::Standard(mut encoder) => {
                 async_io::block_on(AsyncWriteExt::flush(&mut encoder))?;
                 Ok(encoder.into_inner())
             }
-            InnerWriter::Framed {
-                mut writer,
-                mut compressor,
-                frame_size: _,
-                uncompressed_bytes_in_frame: _,
-                mut pending_frames,
-                pending_offset: _,
-            } => {
-                if let Some(mut comp) = compressor.take() {
-                    async_io::block_on(comp.close())?;
-                    let cursor = comp.into_inner();
-                    let data = cursor.into_inner();
-                    if !data.is_empty() {
-                        let frame = Self::build_frame_bytes(&data);
-                        pending_frames.push_back(frame);
-                    }
-                }
-                while let Some(frame) = pending_frames.pop_front() {
-                    async_io::block_on(AsyncWriteExt::write_all(&mut writer, &frame))?;
-                }
-                Ok(writer)
+            InnerWriter::Framed(mut encoder) => {
+                async_io::block_on(poll_fn(|cx| encoder.poll_finish(cx)))?;
+                Ok(encoder.into_inner())
             }
         }
     }
 }
 
+#[cfg(feature = "compress")]
+fn encoder_params(options: &Lz4Options) -> EncoderParams {
+    let block_size = match options.block_size {
+        Lz4BlockSize::Max64KB => BlockSize::Max64KB,
+        Lz4BlockSize::Max256KB => BlockSize::Max256KB,
+        Lz4BlockSize::Max1MB => BlockSize::Max1MB,
+        Lz4BlockSize::Max4MB => BlockSize::Max4MB,
+    };
+    EncoderParams::default().block_size(block_size)
+}
+
+/// Compresses a chunk into a LZ4 frame preceded by its skippable frame.
+#[cfg(feature = "compress")]
+fn compress_frame(data: Vec<u8>, level: Level, params: EncoderParams) -> std::io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    let mut encoder = AsyncLz4BufEncoder::with_quality_and_params(data.as_slice(), level, params);
+    async_io::block_on(AsyncReadExt::read_to_end(&mut encoder, &mut compressed))?;
+    Ok(skippable_frame(
+        &(compressed.len() as u32).to_le_bytes(),
+        &compressed,
+    ))
+}
+
+/// Decompresses the LZ4 frame of a skippable frame.
+fn decompress_frame(data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    let mut decoder = AsyncLz4Decoder::new(data.as_slice());
+    async_io::block_on(AsyncReadExt::read_to_end(&mut decoder, &mut decompressed))?;
+    Ok(decompressed)
+}
+
 #[cfg(feature = "compress")]
 impl<W: AsyncWrite + Unpin> AsyncWrite for Lz4Encoder<W> {
     fn poll_write(
@@ -315,223 +338,22 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Lz4Encoder<W> {
         buf: &[u8],
     ) -> Poll<std::io::Result<usize>> {
         match &mut self.inner {
-            InnerWriter::Standard(encoder) => {
-                let mut pin = Pin::new(encoder);
-                pin.as_mut().poll_write(cx, buf)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset < front.len() {
-                        match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                            Poll::Ready(Ok(w)) => {
-                                if w == 0 {
-                                    return Poll::Ready(Ok(0));
-                                }
-                                *pending_offset += w;
-                                if *pending_offset >= front.len() {
-                                    pending_frames.pop_front();
-                                    *pending_offset = 0;
-                                }
-                            }
-                            Poll::Ready(Err(e)) => {
-                                return Poll::Ready(Err(e));
-                            }
-                            Poll::Pending => {}
-                        }
-                    }
-                }
-
-                if buf.is_empty() {
-                    return Poll::Ready(Ok(0));
-                }
-
-                let cap = *frame_size - *uncompressed_bytes_in_frame;
-                let to_write = std::cmp::min(buf.len(), cap);
-                if to_write == 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame = Self::build_frame_bytes(&data);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncLz4Encoder::new(new_cursor));
-                            *uncompressed_bytes_in_frame = 0;
-                            Poll::Pending
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
-                    }
-                } else {
-                    let comp = compressor.as_mut().expect("no compressor set");
-                    let mut pin = Pin::new(comp);
-                    match pin.as_mut().poll_write(cx, &buf[..to_write]) {
-                        Poll::Ready(Ok(n)) => {
-                            *uncompressed_bytes_in_frame += n;
-                            if *uncompressed_bytes_in_frame >= *frame_size {
-                                let mut comp2 = compressor.take().expect("no compressor set");
-                                let mut pin2 = Pin::new(&mut comp2);
-                                match pin2.as_mut().poll_close(cx) {
-                                    Poll::Ready(Ok(())) => {
-                                        let cursor = comp2.into_inner();
-                                        let data = cursor.into_inner();
-                                        let frame = Self::build_frame_bytes(&data);
-                                        if !frame.is_empty() {
-                                            pending_frames.push_back(frame);
-                                        }
-                                        let new_cursor =
-                                            Cursor::new(Vec::with_capacity(*frame_size));
-                                        *compressor = Some(AsyncLz4Encoder::new(new_cursor));
-                                        *uncompressed_bytes_in_frame = 0;
-                                    }
-                                    Poll::Ready(Err(e)) => {
-                                        return Poll::Ready(Err(e));
-                                    }
-                                    Poll::Pending => return Poll::Pending,
-                                }
-                            }
-                            Poll::Ready(Ok(n))
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
-                    }
-                }
-            }
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_write(cx, buf),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
         match &mut self.inner {
-            InnerWriter::Standard(encoder) => {
-                let mut pin = Pin::new(encoder);
-                pin.as_mut().poll_flush(cx)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if *uncompressed_bytes_in_frame > 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame = Self::build_frame_bytes(&data);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncLz4Encoder::new(new_cursor));
-                            *uncompressed_bytes_in_frame = 0;
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                while let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset >= front.len() {
-                        pending_frames.pop_front();
-                        *pending_offset = 0;
-                        continue;
-                    }
-                    match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                        Poll::Ready(Ok(w)) => {
-                            if w == 0 {
-                                return Poll::Pending;
-                            }
-                            *pending_offset += w;
-                            if *pending_offset >= front.len() {
-                                pending_frames.pop_front();
-                                *pending_offset = 0;
-                            }
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                let mut pin = Pin::new(&mut *writer);
-                pin.as_mut().poll_flush(cx)
-            }
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_flush(cx),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_flush(cx),
         }
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
         match &mut self.inner {
-            InnerWriter::Standard(encoder) => {
-                let mut pin = Pin::new(encoder);
-                pin.as_mut().poll_close(cx)
-            }
-            InnerWriter::Framed {
-                writer,
-                compressor,
-                frame_size,
-                uncompressed_bytes_in_frame,
-                pending_frames,
-                pending_offset,
-            } => {
-                if *uncompressed_bytes_in_frame > 0 {
-                    let mut comp = compressor.take().expect("no compressor set");
-                    let mut pin = Pin::new(&mut comp);
-                    match pin.as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let cursor = comp.into_inner();
-                            let data = cursor.into_inner();
-                            let frame = Self::build_frame_bytes(&data);
-                            if !frame.is_empty() {
-                                pending_frames.push_back(frame);
-                            }
-                            let new_cursor = Cursor::new(Vec::with_capacity(*frame_size));
-                            *compressor = Some(AsyncLz4Encoder::new(new_cursor));
-                            *uncompressed_bytes_in_frame = 0;
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                while let Some(front) = pending_frames.front_mut() {
-                    if *pending_offset >= front.len() {
-                        pending_frames.pop_front();
-                        *pending_offset = 0;
-                        continue;
-                    }
-                    match Pin::new(&mut *writer).poll_write(cx, &front[*pending_offset..]) {
-                        Poll::Ready(Ok(w)) => {
-                            if w == 0 {
-                                return Poll::Pending;
-                            }
-                            *pending_offset += w;
-                            if *pending_offset >= front.len() {
-                                pending_frames.pop_front();
-                                *pending_offset = 0;
-                            }
-                        }
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
-                        Poll::Pending => return Poll::Pending,
-                    }
-                }
-
-                let mut pin = Pin::new(&mut *writer);
-                pin.as_mut().poll_close(cx)
-            }
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_close(cx),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_close(cx),
         }
     }
 }
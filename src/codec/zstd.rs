@@ -0,0 +1,381 @@
+#[cfg(feature = "compress")]
+use std::future::Future;
+use std::io;
+#[cfg(feature = "compress")]
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "compress")]
+use std::task::ready;
+use std::task::{Context, Poll};
+
+use crate::Error;
+use crate::codec::framed::{FrameDecoder, FrameFn, SKIPPABLE_FRAME_MAGIC};
+#[cfg(feature = "compress")]
+use crate::codec::framed::{FrameEncoder, skippable_frame};
+#[cfg(feature = "compress")]
+use crate::encoder_options::{ZstandardOptions, ZstdStrategy};
+use async_compression::futures::bufread::ZstdDecoder as AsyncZstdDecoder;
+use async_compression::zstd::DParameter;
+#[cfg(feature = "compress")]
+use blocking::{Task, unblock};
+#[cfg(feature = "compress")]
+use futures_lite::io::AsyncWrite;
+use futures_lite::io::{AsyncRead, AsyncReadExt, BufReader, Chain, Cursor};
+#[cfg(feature = "compress")]
+use zstd::stream::raw::CParameter;
+#[cfg(feature = "compress")]
+use zstd::zstd_safe::Strategy;
+
+/// Size of the input chunks the single frame encoder compresses at once.
+#[cfg(feature = "compress")]
+const STREAM_CHUNK_SIZE: usize = 1 << 20;
+
+/// Largest window the decoder accepts, zstd's default limit only allows windows up to 128 MiB.
+const WINDOW_LOG_MAX: u32 = if cfg!(target_pointer_width = "64") {
+    31
+} else {
+    30
+};
+
+/// Zstandard decoder, which decompresses the skippable frames written by zstdmt on several
+/// threads.
+///
+/// Plain zstd decoding skips these frames by itself, so only the parallel decoding needs to know
+/// about them.
+pub(crate) struct ZstdDecoder<R: AsyncRead + Unpin> {
+    inner: InnerReader<R>,
+}
+
+enum InnerReader<R: AsyncRead + Unpin> {
+    Standard(AsyncZstdDecoder<BufReader<Chain<Cursor<Vec<u8>>, R>>>),
+    Framed(FrameDecoder<R>),
+}
+
+impl<R: AsyncRead + Unpin> ZstdDecoder<R> {
+    pub(crate) async fn new(mut input: R, threads: u32) -> Result<Self, Error> {
+        let mut header = [0u8; 12];
+        let mut header_read = 0;
+        while header_read < header.len() {
+            match AsyncReadExt::read(&mut input, &mut header[header_read..]).await? {
+                0 => break,
+                n => header_read += n,
+            }
+        }
+
+        let magic_value = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let skippable_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        let inner = if threads > 1
+            && header_read == header.len()
+            && magic_value == SKIPPABLE_FRAME_MAGIC
+            && skippable_size == 4
+        {
+            let compressed_size =
+                u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+            let decompress: FrameFn = Arc::new(decompress_frame);
+            InnerReader::Framed(FrameDecoder::new(
+                input,
+                decompress,
+                compressed_size,
+                threads,
+            ))
+        } else {
+            let header = Cursor::new(header[..header_read].to_vec());
+            let mut decoder = AsyncZstdDecoder::with_params(
+                BufReader::new(header.chain(input)),
+                &[DParameter::window_log_max(WINDOW_LOG_MAX)],
+            );
+            decoder.multiple_members(true);
+            InnerReader::Standard(decoder)
+        };
+
+        Ok(Self { inner })
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ZstdDecoder<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.inner {
+            InnerReader::Standard(decoder) => Pin::new(decoder).poll_read(cx, buf),
+            InnerReader::Framed(decoder) => Pin::new(decoder).poll_read(cx, buf),
+        }
+    }
+}
+
+/// Zstandard encoder, which writes the skippable frame format of zstdmt if a frame size is set.
+///
+/// The encoder is finished by [`poll_finish`](Self::poll_finish) or by closing it.
+#[cfg(feature = "compress")]
+pub(crate) struct ZstdEncoder<W: AsyncWrite + Unpin> {
+    inner: InnerWriter<W>,
+}
+
+#[cfg(feature = "compress")]
+enum InnerWriter<W: AsyncWrite + Unpin> {
+    Standard(StreamEncoder<W>),
+    Framed(FrameEncoder<W>),
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> ZstdEncoder<W> {
+    pub(crate) fn new(writer: W, options: &ZstandardOptions) -> io::Result<Self> {
+        let level = options.level as i32;
+        let parameters = parameters(options);
+        let inner = if options.skippable_frame_size == 0 {
+            let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), level)?;
+            for parameter in &parameters {
+                encoder.set_parameter(*parameter)?;
+            }
+            InnerWriter::Standard(StreamEncoder::new(writer, encoder))
+        } else {
+            let compress: FrameFn = Arc::new(move |data| compress_frame(data, level, &parameters));
+            InnerWriter::Framed(FrameEncoder::new(
+                writer,
+                compress,
+                options.skippable_frame_size as usize,
+                options.threads,
+            ))
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Writes the rest of the compressed data and ends the stream.
+    pub(crate) fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => encoder.poll_finish(cx),
+            InnerWriter::Framed(encoder) => encoder.poll_finish(cx),
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        match self.inner {
+            InnerWriter::Standard(encoder) => encoder.inner,
+            InnerWriter::Framed(encoder) => encoder.into_inner(),
+        }
+    }
+}
+
+/// A step of the single frame encoder, run on the blocking thread pool.
+#[cfg(feature = "compress")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StreamStep {
+    Write,
+    Flush,
+    Finish,
+}
+
+#[cfg(feature = "compress")]
+type StreamCompressor = Box<zstd::stream::write::Encoder<'static, Vec<u8>>>;
+
+/// Compresses into a single zstd frame. The input is collected into chunks that are compressed
+/// on the blocking thread pool, one at a time, while the next chunk is collected and the
+/// compressed data is written to the inner writer.
+#[cfg(feature = "compress")]
+struct StreamEncoder<W> {
+    inner: W,
+    /// The compressor, unless it is running a step.
+    compressor: Option<StreamCompressor>,
+    job: Option<Task<(StreamCompressor, io::Result<()>)>>,
+    /// A flush or finish step that was started and has to complete.
+    step: Option<StreamStep>,
+    finished: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    written: usize,
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> StreamEncoder<W> {
+    fn new(inner: W, compressor: zstd::stream::write::Encoder<'static, Vec<u8>>) -> Self {
+        Self {
+            inner,
+            compressor: Some(Box::new(compressor)),
+            job: None,
+            step: None,
+            finished: false,
+            input: Vec::new(),
+            output: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Waits for the running step and writes all compressed data to the inner writer.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            while self.written < self.output.len() {
+                let n =
+                    ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.written += n;
+            }
+            self.output.clear();
+            self.written = 0;
+            let Some(job) = &mut self.job else {
+                return Poll::Ready(Ok(()));
+            };
+            let (mut compressor, result) = ready!(Pin::new(job).poll(cx));
+            self.job = None;
+            std::mem::swap(compressor.get_mut(), &mut self.output);
+            self.compressor = Some(compressor);
+            result?;
+        }
+    }
+
+    /// Starts compressing the collected input, followed by `step`.
+    fn start(&mut self, step: StreamStep) -> io::Result<()> {
+        let mut compressor = self
+            .compressor
+            .take()
+            .ok_or_else(|| io::Error::other("zstd encoder failed before"))?;
+        let input = std::mem::take(&mut self.input);
+        self.job = Some(unblock(move || {
+            let result = compressor.write_all(&input).and_then(|()| match step {
+                StreamStep::Write => Ok(()),
+                StreamStep::Flush => compressor.flush(),
+                StreamStep::Finish => compressor.do_finish(),
+            });
+            (compressor, result)
+        }));
+        Ok(())
+    }
+
+    /// Runs `step` on all collected input and writes the compressed data.
+    fn poll_step(&mut self, cx: &mut Context<'_>, step: StreamStep) -> Poll<io::Result<()>> {
+        if self.step != Some(step) {
+            ready!(self.poll_idle(cx))?;
+            self.start(step)?;
+            self.step = Some(step);
+        }
+        ready!(self.poll_idle(cx))?;
+        self.step = None;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.finished {
+            ready!(self.poll_step(cx, StreamStep::Finish))?;
+            self.finished = true;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> AsyncWrite for StreamEncoder<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.finished {
+            return Poll::Ready(Err(io::Error::other("zstd encoder is already finished")));
+        }
+        if this.input.len() >= STREAM_CHUNK_SIZE {
+            ready!(this.poll_idle(cx))?;
+            this.start(StreamStep::Write)?;
+        }
+        let n = buf.len().min(STREAM_CHUNK_SIZE - this.input.len());
+        this.input.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.finished {
+            ready!(self.poll_step(cx, StreamStep::Flush))?;
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_finish(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Maps the options to the parameters set in addition to the level.
+#[cfg(feature = "compress")]
+fn parameters(options: &ZstandardOptions) -> Vec<CParameter> {
+    let mut parameters = Vec::new();
+    if options.window_log != 0 {
+        parameters.push(CParameter::WindowLog(options.window_log));
+    }
+    if options.long_distance_matching {
+        parameters.push(CParameter::EnableLongDistanceMatching(true));
+    }
+    if let Some(strategy) = options.strategy {
+        parameters.push(CParameter::Strategy(match strategy {
+            ZstdStrategy::Fast => Strategy::ZSTD_fast,
+            ZstdStrategy::DFast => Strategy::ZSTD_dfast,
+            ZstdStrategy::Greedy => Strategy::ZSTD_greedy,
+            ZstdStrategy::Lazy => Strategy::ZSTD_lazy,
+            ZstdStrategy::Lazy2 => Strategy::ZSTD_lazy2,
+            ZstdStrategy::BtLazy2 => Strategy::ZSTD_btlazy2,
+            ZstdStrategy::BtOpt => Strategy::ZSTD_btopt,
+            ZstdStrategy::BtUltra => Strategy::ZSTD_btultra,
+            ZstdStrategy::BtUltra2 => Strategy::ZSTD_btultra2,
+        }));
+    }
+    parameters
+}
+
+/// Compresses a chunk into a zstd frame preceded by its skippable frame.
+#[cfg(feature = "compress")]
+fn compress_frame(data: Vec<u8>, level: i32, parameters: &[CParameter]) -> io::Result<Vec<u8>> {
+    let mut compressor = zstd::bulk::Compressor::new(level)?;
+    for parameter in parameters {
+        compressor.set_parameter(*parameter)?;
+    }
+    let compressed = compressor.compress(&data)?;
+    Ok(skippable_frame(
+        &(compressed.len() as u32).to_le_bytes(),
+        &compressed,
+    ))
+}
+
+/// Decompresses the zstd frame of a skippable frame.
+fn decompress_frame(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    let mut decoder = AsyncZstdDecoder::with_params(
+        data.as_slice(),
+        &[DParameter::window_log_max(WINDOW_LOG_MAX)],
+    );
+    async_io::block_on(AsyncReadExt::read_to_end(&mut decoder, &mut decompressed))?;
+    Ok(decompressed)
+}
+
+#[cfg(feature = "compress")]
+impl<W: AsyncWrite + Unpin> AsyncWrite for ZstdEncoder<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_write(cx, buf),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_flush(cx),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.inner {
+            InnerWriter::Standard(encoder) => Pin::new(encoder).poll_close(cx),
+            InnerWriter::Framed(encoder) => Pin::new(encoder).poll_close(cx),
+        }
+    }
+}
@@ -6,15 +6,16 @@ use std::{
 #[cfg(feature = "bzip2")]
 use async_compression::futures::bufread::BzDecoder as AsyncBzip2Decoder;
 #[cfg(feature = "deflate")]
+use async_compression::futures::bufread::Deflate64Decoder as AsyncDeflate64Decoder;
+#[cfg(feature = "deflate")]
 use async_compression::futures::bufread::DeflateDecoder as AsyncDeflateDecoder;
-use async_compression::futures::bufread::LzmaDecoder as AsyncLzmaDecoder;
-#[cfg(feature = "zstd")]
-use async_compression::futures::bufread::ZstdDecoder as AsyncZstdDecoder;
-use futures_lite::io::{AsyncRead, AsyncReadExt, BufReader, Cursor};
+use futures_lite::io::AsyncRead;
+#[cfg(any(feature = "bzip2", feature = "deflate"))]
+use futures_lite::io::BufReader;
 use lzma_rust2::{
-    Lzma2Reader, Lzma2ReaderMt,
+    Lzma2Reader, Lzma2ReaderMt, LzmaReader,
     filter::{bcj::BcjReader, delta::DeltaReader},
-    lzma2_get_memory_usage,
+    lzma_get_memory_usage_by_props, lzma2_get_memory_usage,
 };
 #[cfg(feature = "ppmd")]
 use ppmd_rust::{
@@ -25,15 +26,19 @@ use ppmd_rust::{
 use crate::codec::brotli::BrotliDecoder;
 #[cfg(feature = "lz4")]
 use crate::codec::lz4::Lz4Decoder;
+#[cfg(feature = "zstd")]
+use crate::codec::zstd::ZstdDecoder;
 #[cfg(feature = "aes256")]
 use crate::encryption::Aes256Sha256Decoder;
 use crate::{
-    Password, archive::EncoderMethod, block::Coder, error::Error,
+    Password,
+    archive::EncoderMethod,
+    block::{Coder, bcj_alignment},
+    encryption::KeyCache,
+    error::Error,
     util::decompress::AsyncReadSeekAsStd,
 };
 
-type LzmaBuf<R> = BufReader<futures_lite::io::Chain<Cursor<Vec<u8>>, R>>;
-
 /// 归档数据解码器枚举，根据编码方法选择相应的异步解码器。
 ///
 /// 所有变体均实现 `AsyncRead`，可在统一的读取管线中以异步方式解码数据。
@@ -41,7 +46,7 @@ pub enum Decoder<R: AsyncRead + Unpin> {
     /// 原样读取，不进行任何解码处理。
     Copy(R),
     /// LZMA 算法解码器。
-    Lzma(Box<AsyncLzmaDecoder<LzmaBuf<R>>>),
+    Lzma(Box<AsyncStdRead<LzmaReader<AsyncReadSeekAsStd<R>>>>),
     /// LZMA2 算法解码器（单线程）。
     Lzma2(Box<AsyncStdRead<Lzma2Reader<AsyncReadSeekAsStd<R>>>>),
     /// LZMA2 算法解码器（多线程）。
@@ -62,12 +67,15 @@ pub enum Decoder<R: AsyncRead + Unpin> {
     #[cfg(feature = "deflate")]
     /// deflate 算法解码器。
     Deflate(Box<AsyncDeflateDecoder<BufReader<R>>>),
+    #[cfg(feature = "deflate")]
+    /// deflate64 算法解码器。
+    Deflate64(Box<AsyncDeflate64Decoder<BufReader<R>>>),
     #[cfg(feature = "lz4")]
     /// LZ4 算法解码器。
     Lz4(Box<Lz4Decoder<R>>),
     #[cfg(feature = "zstd")]
     /// Zstd 算法解码器。
-    Zstd(Box<AsyncZstdDecoder<BufReader<R>>>),
+    Zstd(Box<ZstdDecoder<R>>),
     #[cfg(feature = "aes256")]
     /// AES-256-SHA256 加密数据流解码器。
     Aes256Sha256(Box<Aes256Sha256Decoder<R>>),
@@ -94,6 +102,8 @@ impl<R: AsyncRead + Unpin> AsyncRead for Decoder<R> {
             Decoder::Bzip2(r) => Pin::new(r.as_mut()).poll_read(cx, buf),
             #[cfg(feature = "deflate")]
             Decoder::Deflate(r) => Pin::new(r.as_mut()).poll_read(cx, buf),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate64(r) => Pin::new(r.as_mut()).poll_read(cx, buf),
             #[cfg(feature = "lz4")]
             Decoder::Lz4(r) => Pin::new(r.as_mut()).poll_read(cx, buf),
             #[cfg(feature = "zstd")]
@@ -129,6 +139,7 @@ pub async fn add_decoder<I: AsyncRead + Unpin>(
     uncompressed_len: usize,
     coder: &Coder,
     _password: &Password,
+    _keys: &KeyCache,
     max_mem_limit_kb: usize,
     threads: u32,
 ) -> Result<Decoder<I>, Error> {
@@ -145,20 +156,25 @@ pub async fn add_decoder<I: AsyncRead + Unpin>(
         EncoderMethod::ID_COPY => Ok(Decoder::Copy(input)),
         EncoderMethod::ID_LZMA => {
             let dict_size = get_lzma_dic_size(coder)?;
-            if coder.properties.is_empty() {
-                return Err(Error::Other("LZMA properties too short".into()));
+            let props = coder.properties[0];
+            let mem_size = lzma_get_memory_usage_by_props(dict_size, props)? as usize;
+            if mem_size > max_mem_limit_kb {
+                return Err(Error::MaxMemLimited {
+                    max_kb: max_mem_limit_kb,
+                    actaul_kb: mem_size,
+                });
             }
 
-            let mut header = Vec::with_capacity(13);
-            header.push(coder.properties[0]);
-            header.extend_from_slice(&dict_size.to_le_bytes());
-            header.extend_from_slice(&(uncompressed_len as u64).to_le_bytes());
-
-            let header_cursor = Cursor::new(header);
-            let chained = AsyncReadExt::chain(header_cursor, input);
-            let bufread = BufReader::new(chained);
-            let de = AsyncLzmaDecoder::new(bufread);
-            Ok(Decoder::Lzma(Box::new(de)))
+            // The unpack size is known, so streams with or without end marker are accepted.
+            let std_in = AsyncReadSeekAsStd::new(input);
+            let de = LzmaReader::new_with_props(
+                std_in,
+                uncompressed_len as u64,
+                props,
+                dict_size,
+                None,
+            )?;
+            Ok(Decoder::Lzma(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_LZMA2 => {
             let dic_size = get_lzma2_dic_size(coder)?;
@@ -193,7 +209,7 @@ pub async fn add_decoder<I: AsyncRead + Unpin>(
         }
         #[cfg(feature = "brotli")]
         EncoderMethod::ID_BROTLI => {
-            let de = BrotliDecoder::new(input, 4096)?;
+            let de = BrotliDecoder::new(input, 4096, threads)?;
             Ok(Decoder::Brotli(Box::new(de)))
         }
         #[cfg(feature = "bzip2")]
@@ -208,55 +224,68 @@ pub async fn add_decoder<I: AsyncRead + Unpin>(
             let de = AsyncDeflateDecoder::new(br);
             Ok(Decoder::Deflate(Box::new(de)))
         }
+        #[cfg(feature = "deflate")]
+        EncoderMethod::ID_DEFLATE64 => {
+            let br = BufReader::new(input);
+            let de = AsyncDeflate64Decoder::new(br);
+            Ok(Decoder::Deflate64(Box::new(de)))
+        }
         #[cfg(feature = "lz4")]
         EncoderMethod::ID_LZ4 => {
-            let de = Lz4Decoder::new(input).await?;
+            let de = Lz4Decoder::new(input, threads).await?;
             Ok(Decoder::Lz4(Box::new(de)))
         }
         #[cfg(feature = "zstd")]
         EncoderMethod::ID_ZSTD => {
-            let br = BufReader::new(input);
-            let zs = AsyncZstdDecoder::new(br);
+            let zs = ZstdDecoder::new(input, threads).await?;
             Ok(Decoder::Zstd(Box::new(zs)))
         }
         EncoderMethod::ID_BCJ_X86 => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_x86(std_in, 0);
+            let de = BcjReader::new_x86(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_ARM => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_arm(std_in, 0);
+            let de = BcjReader::new_arm(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_ARM64 => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_arm64(std_in, 0);
+            let de = BcjReader::new_arm64(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_ARM_THUMB => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_arm_thumb(std_in, 0);
+            let de = BcjReader::new_arm_thumb(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_PPC => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_ppc(std_in, 0);
+            let de = BcjReader::new_ppc(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_IA64 => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_ia64(std_in, 0);
+            let de = BcjReader::new_ia64(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_SPARC => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_sparc(std_in, 0);
+            let de = BcjReader::new_sparc(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_BCJ_RISCV => {
+            let start_offset = get_bcj_start_offset(coder)?;
             let std_in = AsyncReadSeekAsStd::new(input);
-            let de = BcjReader::new_riscv(std_in, 0);
+            let de = BcjReader::new_riscv(std_in, start_offset);
             Ok(Decoder::Bcj(Box::new(AsyncStdRead::new(de))))
         }
         EncoderMethod::ID_DELTA => {
@@ -274,7 +303,7 @@ pub async fn add_decoder<I: AsyncRead + Unpin>(
             if _password.is_empty() {
                 return Err(Error::PasswordRequired);
             }
-            let de = Aes256Sha256Decoder::new(input, &coder.properties, _password)?;
+            let de = Aes256Sha256Decoder::new(input, &coder.properties, _password, _keys).await?;
             Ok(Decoder::Aes256Sha256(Box::new(de)))
         }
         _ => Err(Error::UnsupportedCompressionMethod(
@@ -326,7 +355,23 @@ fn get_ppmd_order_memory_size(coder: &Coder, max_mem_limit_kb: usize) -> Result<
     Ok((order, memory_size))
 }
 
-fn get_lzma2_dic_size(coder: &Coder) -> Result<u32, Error> {
+/// Returns the start offset of a BCJ filter, which 7-Zip stores as 4 bytes if it isn't 0.
+pub(crate) fn get_bcj_start_offset(coder: &Coder) -> Result<usize, Error> {
+    let start_offset = match coder.properties.as_slice() {
+        [] => 0,
+        &[b0, b1, b2, b3] => u32::from_le_bytes([b0, b1, b2, b3]),
+        _ => return Err(Error::other("BCJ properties must be empty or 4 bytes")),
+    };
+    let alignment = bcj_alignment(coder.encoder_method_id()).unwrap_or(1);
+    if start_offset % alignment != 0 {
+        return Err(Error::other(format!(
+            "BCJ start offset {start_offset} is not a multiple of {alignment}"
+        )));
+    }
+    Ok(start_offset as usize)
+}
+
+pub(crate) fn get_lzma2_dic_size(coder: &Coder) -> Result<u32, Error> {
     if coder.properties.is_empty() {
         return Err(Error::other("LZMA2 properties too short"));
     }
@@ -344,7 +389,7 @@ fn get_lzma2_dic_size(coder: &Coder) -> Result<u32, Error> {
     Ok(size)
 }
 
-fn get_lzma_dic_size(coder: &Coder) -> io::Result<u32> {
+pub(crate) fn get_lzma_dic_size(coder: &Coder) -> io::Result<u32> {
     if coder.properties.len() < 5 {
         return Err(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
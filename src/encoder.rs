@@ -1,3 +1,5 @@
+#[cfg(feature = "zstd")]
+use std::task::ready;
 use std::{
     pin::Pin,
     task::{Context, Poll},
@@ -5,14 +7,18 @@ use std::{
 
 // AsyncWrite is imported below together with AsyncWriteExt
 use lzma_rust2::{
-    Lzma2Writer, Lzma2WriterMt,
+    Lzma2Writer, Lzma2WriterMt, LzmaWriter,
     filter::{bcj::BcjWriter, delta::DeltaWriter},
 };
 
 #[cfg(feature = "brotli")]
 use crate::codec::brotli::BrotliEncoder;
+#[cfg(feature = "bzip2")]
+use crate::codec::bzip2::Bzip2Encoder;
 #[cfg(feature = "lz4")]
 use crate::codec::lz4::Lz4Encoder;
+#[cfg(feature = "zstd")]
+use crate::codec::zstd::ZstdEncoder;
 #[cfg(feature = "brotli")]
 use crate::encoder_options::BrotliOptions;
 #[cfg(feature = "bzip2")]
@@ -30,25 +36,22 @@ use crate::encryption::Aes256Sha256Encoder;
 use crate::{
     Error,
     archive::{EncoderConfiguration, EncoderMethod},
-    encoder_options::{DeltaOptions, EncoderOptions, Lzma2Options, LzmaOptions},
+    block::bcj_alignment,
+    encoder_options::{BcjOptions, DeltaOptions, EncoderOptions, Lzma2Options, LzmaOptions},
+    encryption::KeyCache,
     writer::CountingWriter,
 };
-#[cfg(any(feature = "deflate", feature = "bzip2", feature = "zstd"))]
+#[cfg(feature = "deflate")]
 use async_compression::Level;
-#[cfg(any(feature = "deflate", feature = "bzip2", feature = "zstd"))]
-use async_compression::futures::write::BzEncoder as AsyncBzip2Encoder;
 #[cfg(feature = "deflate")]
 use async_compression::futures::write::DeflateEncoder as AsyncDeflateEncoder;
-use async_compression::futures::write::LzmaEncoder as AsyncLzmaEncoder;
-#[cfg(feature = "zstd")]
-use async_compression::futures::write::ZstdEncoder as AsyncZstdEncoder;
 use futures_lite::io::{AsyncWrite, AsyncWriteExt};
 
 pub(crate) enum Encoder<W: AsyncWrite + Unpin> {
     Copy(CountingWriter<W>),
     Bcj(Option<Box<BcjWriter<CountingWriter<W>>>>),
     Delta(Box<DeltaWriter<CountingWriter<W>>>),
-    Lzma(Option<Box<LzmaEnc<W>>>),
+    Lzma(Option<Box<LzmaWriter<CountingWriter<W>>>>),
     Lzma2(Option<Box<Lzma2Writer<CountingWriter<W>>>>),
     Lzma2Mt(Option<Box<Lzma2WriterMt<CountingWriter<W>>>>),
     #[cfg(feature = "ppmd")]
@@ -56,60 +59,16 @@ pub(crate) enum Encoder<W: AsyncWrite + Unpin> {
     #[cfg(feature = "brotli")]
     Brotli(Box<BrotliEncoder<CountingWriter<W>>>),
     #[cfg(feature = "bzip2")]
-    Bzip2(Option<Box<AsyncBzip2Encoder<CountingWriter<W>>>>),
+    Bzip2(Option<Box<Bzip2Encoder<CountingWriter<W>>>>),
     #[cfg(feature = "deflate")]
     Deflate(Option<Box<AsyncDeflateEncoder<CountingWriter<W>>>>),
     #[cfg(feature = "lz4")]
     Lz4(Option<Box<Lz4Encoder<CountingWriter<W>>>>),
     #[cfg(feature = "zstd")]
-    Zstd(Option<Box<AsyncZstdEncoder<CountingWriter<W>>>>),
+    Zstd(Option<Box<ZstdEncoder<CountingWriter<W>>>>),
     #[cfg(feature = "aes256")]
     Aes(Box<Aes256Sha256Encoder<CountingWriter<W>>>),
 }
-type LzmaEnc<W> = AsyncLzmaEncoder<StripLzmaHeaderWrite<CountingWriter<W>>>;
-
-pub(crate) struct StripLzmaHeaderWrite<W> {
-    inner: W,
-    offset: usize,
-}
-
-impl<W> StripLzmaHeaderWrite<W> {
-    fn new(inner: W) -> Self {
-        Self { inner, offset: 0 }
-    }
-    fn into_inner(self) -> W {
-        self.inner
-    }
-}
-
-impl<W: AsyncWrite + Unpin> AsyncWrite for StripLzmaHeaderWrite<W> {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<std::io::Result<usize>> {
-        let mut to_send = buf;
-        if self.offset < 13 {
-            let skip = 13 - self.offset;
-            if to_send.len() <= skip {
-                self.offset += to_send.len();
-                return Poll::Ready(Ok(to_send.len()));
-            } else {
-                to_send = &to_send[skip..];
-                self.offset = 13;
-            }
-        }
-        Pin::new(&mut self.inner).poll_write(cx, to_send)
-    }
-
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-        Pin::new(&mut self.inner).poll_flush(cx)
-    }
-
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-        Pin::new(&mut self.inner).poll_close(cx)
-    }
-}
 
 impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
     fn poll_write(
@@ -131,19 +90,12 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
             },
             Encoder::Lzma(w) => match buf.is_empty() {
                 true => {
-                    let mut writer = w.take().unwrap();
-                    match Pin::new(&mut *writer).as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let strip = writer.into_inner();
-                            let mut inner = strip.into_inner();
-                            let _ = Pin::new(&mut inner).poll_write(cx, buf);
-                            Poll::Ready(Ok(0))
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
-                    }
+                    let writer = w.take().unwrap();
+                    let mut inner = writer.finish()?;
+                    let _ = Pin::new(&mut inner).poll_write(cx, buf);
+                    Poll::Ready(Ok(0))
                 }
-                false => Pin::new(w.as_mut().unwrap().as_mut()).poll_write(cx, buf),
+                false => Poll::Ready(std::io::Write::write(w.as_mut().unwrap().as_mut(), buf)),
             },
             Encoder::Lzma2(w) => match buf.is_empty() {
                 true => {
@@ -220,16 +172,12 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
             #[cfg(feature = "zstd")]
             Encoder::Zstd(w) => match buf.is_empty() {
                 true => {
-                    let mut writer = w.take().unwrap();
-                    match Pin::new(&mut *writer).as_mut().poll_close(cx) {
-                        Poll::Ready(Ok(())) => {
-                            let mut inner = writer.into_inner();
-                            let _ = Pin::new(&mut inner).poll_write(cx, buf);
-                            Poll::Ready(Ok(0))
-                        }
-                        Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-                        Poll::Pending => Poll::Pending,
+                    if let Some(writer) = w.as_mut() {
+                        ready!(writer.poll_finish(cx))?;
+                        let mut inner = w.take().unwrap().into_inner();
+                        let _ = Pin::new(&mut inner).poll_write(cx, buf);
                     }
+                    Poll::Ready(Ok(0))
                 }
                 false => Pin::new(w.as_mut().unwrap().as_mut()).poll_write(cx, buf),
             },
@@ -243,7 +191,7 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
             Encoder::Copy(w) => Pin::new(w).poll_flush(cx),
             Encoder::Bcj(w) => Poll::Ready(std::io::Write::flush(w.as_mut().unwrap().as_mut())),
             Encoder::Delta(w) => Poll::Ready(std::io::Write::flush(w.as_mut())),
-            Encoder::Lzma(w) => Pin::new(w.as_mut().unwrap().as_mut()).poll_flush(cx),
+            Encoder::Lzma(w) => Poll::Ready(std::io::Write::flush(w.as_mut().unwrap().as_mut())),
             Encoder::Lzma2(w) => Poll::Ready(std::io::Write::flush(w.as_mut().unwrap().as_mut())),
             Encoder::Lzma2Mt(w) => Poll::Ready(std::io::Write::flush(w.as_mut().unwrap().as_mut())),
             #[cfg(feature = "brotli")]
@@ -273,7 +221,11 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
                 let _ = Pin::new(&mut inner).poll_write(cx, &[]);
                 Poll::Ready(Ok(()))
             }
-            Encoder::Lzma(w) => Pin::new(w.as_mut().unwrap().as_mut()).poll_close(cx),
+            Encoder::Lzma(w) => {
+                let writer = w.take().unwrap();
+                let _inner = writer.finish()?;
+                Poll::Ready(Ok(()))
+            }
             Encoder::Lzma2(w) => {
                 let writer = w.take().unwrap();
                 let _inner = writer.finish()?;
@@ -303,7 +255,13 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
                 Poll::Ready(Ok(()))
             }
             #[cfg(feature = "zstd")]
-            Encoder::Zstd(w) => Pin::new(w.as_mut().unwrap().as_mut()).poll_close(cx),
+            Encoder::Zstd(w) => {
+                if let Some(writer) = w.as_mut() {
+                    ready!(writer.poll_finish(cx))?;
+                    w.take();
+                }
+                Poll::Ready(Ok(()))
+            }
             #[cfg(feature = "aes256")]
             Encoder::Aes(w) => std::pin::Pin::new(w.as_mut()).poll_close(cx),
         }
@@ -313,6 +271,7 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for Encoder<W> {
 pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
     input: CountingWriter<W>,
     method_config: &EncoderConfiguration,
+    _keys: &KeyCache,
 ) -> Result<Encoder<W>, Error> {
     let method = method_config.method;
 
@@ -326,32 +285,28 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
             let dw = DeltaWriter::new(input, options.0 as usize);
             Ok(Encoder::Delta(Box::new(dw)))
         }
-        EncoderMethod::ID_BCJ_X86 => Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_x86(input, 0))))),
-        EncoderMethod::ID_BCJ_ARM => Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_arm(input, 0))))),
-        EncoderMethod::ID_BCJ_ARM_THUMB => Ok(Encoder::Bcj(Some(Box::new(
-            BcjWriter::new_arm_thumb(input, 0),
-        )))),
-        EncoderMethod::ID_BCJ_ARM64 => {
-            Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_arm64(input, 0)))))
-        }
-        EncoderMethod::ID_BCJ_IA64 => {
-            Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_ia64(input, 0)))))
-        }
-        EncoderMethod::ID_BCJ_SPARC => {
-            Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_sparc(input, 0)))))
-        }
-        EncoderMethod::ID_BCJ_PPC => Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_ppc(input, 0))))),
-        EncoderMethod::ID_BCJ_RISCV => {
-            Ok(Encoder::Bcj(Some(Box::new(BcjWriter::new_riscv(input, 0)))))
+        id if bcj_alignment(id).is_some() => {
+            let start_offset = bcj_start_offset(method_config)? as usize;
+            let bcj = match id {
+                EncoderMethod::ID_BCJ_X86 => BcjWriter::new_x86(input, start_offset),
+                EncoderMethod::ID_BCJ_ARM => BcjWriter::new_arm(input, start_offset),
+                EncoderMethod::ID_BCJ_ARM_THUMB => BcjWriter::new_arm_thumb(input, start_offset),
+                EncoderMethod::ID_BCJ_ARM64 => BcjWriter::new_arm64(input, start_offset),
+                EncoderMethod::ID_BCJ_IA64 => BcjWriter::new_ia64(input, start_offset),
+                EncoderMethod::ID_BCJ_SPARC => BcjWriter::new_sparc(input, start_offset),
+                EncoderMethod::ID_BCJ_PPC => BcjWriter::new_ppc(input, start_offset),
+                _ => BcjWriter::new_riscv(input, start_offset),
+            };
+            Ok(Encoder::Bcj(Some(Box::new(bcj))))
         }
         EncoderMethod::ID_LZMA => {
-            let _options = match &method_config.options {
+            let options = match &method_config.options {
                 Some(EncoderOptions::Lzma(options)) => options.clone(),
                 _ => LzmaOptions::default(),
             };
-            let strip = StripLzmaHeaderWrite::new(input);
-            let enc = AsyncLzmaEncoder::new(strip);
-            Ok(Encoder::Lzma(Some(Box::new(enc))))
+            // 7z stores the unpack size, so no end marker is written.
+            let lzma_writer = LzmaWriter::new_no_header(input, &options.0, false)?;
+            Ok(Encoder::Lzma(Some(Box::new(lzma_writer))))
         }
         EncoderMethod::ID_LZMA2 => {
             let lzma2_options = match &method_config.options {
@@ -396,11 +351,7 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
                 _ => BrotliOptions::default(),
             };
 
-            let brotli_encoder = BrotliEncoder::new(
-                input,
-                options.quality,
-                options.skippable_frame_size as usize,
-            )?;
+            let brotli_encoder = BrotliEncoder::new(input, &options)?;
 
             Ok(Encoder::Brotli(Box::new(brotli_encoder)))
         }
@@ -410,8 +361,7 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
                 Some(EncoderOptions::Bzip2(options)) => options,
                 _ => Bzip2Options::default(),
             };
-            let level = Level::Precise(options.0 as i32);
-            let bzip2_encoder = AsyncBzip2Encoder::with_quality(input, level);
+            let bzip2_encoder = Bzip2Encoder::new(input, &options);
             Ok(Encoder::Bzip2(Some(Box::new(bzip2_encoder))))
         }
         #[cfg(feature = "deflate")]
@@ -431,7 +381,7 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
                 _ => Lz4Options::default(),
             };
 
-            let lz4_encoder = Lz4Encoder::new(input, options.skippable_frame_size as usize)?;
+            let lz4_encoder = Lz4Encoder::new(input, &options)?;
 
             Ok(Encoder::Lz4(Some(Box::new(lz4_encoder))))
         }
@@ -441,8 +391,7 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
                 Some(EncoderOptions::Zstd(options)) => *options,
                 _ => ZstandardOptions::default(),
             };
-            let level = Level::Precise(options.0 as i32);
-            let zstd_encoder = AsyncZstdEncoder::with_quality(input, level);
+            let zstd_encoder = ZstdEncoder::new(input, &options)?;
             Ok(Encoder::Zstd(Some(Box::new(zstd_encoder))))
         }
         #[cfg(feature = "aes256")]
@@ -452,7 +401,7 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
                 _ => return Err(Error::PasswordRequired),
             };
             Ok(Encoder::Aes(Box::new(Aes256Sha256Encoder::new(
-                input, options,
+                input, options, _keys,
             )?)))
         }
         _ => Err(Error::UnsupportedCompressionMethod(
@@ -461,6 +410,22 @@ pub(crate) fn add_encoder<W: AsyncWrite + Unpin>(
     }
 }
 
+/// Returns the configured start offset of a BCJ filter, checking that it is aligned to the
+/// filter's instructions.
+fn bcj_start_offset(method_config: &EncoderConfiguration) -> Result<u32, Error> {
+    let start_offset = match method_config.options {
+        Some(EncoderOptions::Bcj(options)) => options.0,
+        _ => BcjOptions::default().0,
+    };
+    let alignment = bcj_alignment(method_config.method.id()).unwrap_or(1);
+    if start_offset % alignment != 0 {
+        return Err(Error::other(format!(
+            "BCJ start offset {start_offset} is not a multiple of {alignment}"
+        )));
+    }
+    Ok(start_offset)
+}
+
 pub(crate) fn get_options_as_properties<'a>(
     method: EncoderMethod,
     options: Option<&EncoderOptions>,
@@ -476,6 +441,14 @@ pub(crate) fn get_options_as_properties<'a>(
             out[0] = options.0.saturating_sub(1) as u8;
             &out[0..1]
         }
+        id if bcj_alignment(id).is_some() => match options {
+            // 7-Zip leaves the properties empty for the default start offset of 0.
+            Some(EncoderOptions::Bcj(options)) if options.0 != 0 => {
+                out[0..4].copy_from_slice(&options.0.to_le_bytes());
+                &out[0..4]
+            }
+            _ => &[],
+        },
         EncoderMethod::ID_LZMA2 => {
             let options = match options {
                 Some(EncoderOptions::Lzma2(options)) => options,
@@ -525,11 +498,14 @@ pub(crate) fn get_options_as_properties<'a>(
         }
         #[cfg(feature = "lz4")]
         EncoderMethod::ID_LZ4 => {
-            // Since we use lz4_flex, we only support one compression level
-            // and set the version to 1.0 for best compatibility.
+            let options = match options {
+                Some(EncoderOptions::Lz4(options)) => *options,
+                _ => Lz4Options::default(),
+            };
+
             out[0] = 1; // Major version
             out[1] = 0; // Minor version
-            out[2] = 3; // Fast compression
+            out[2] = options.level as u8;
             &out[0..3]
         }
         #[cfg(feature = "zstd")]
@@ -541,7 +517,7 @@ pub(crate) fn get_options_as_properties<'a>(
 
             out[0] = 1; // Zstd major version
             out[1] = 0; // Zstd minor version
-            out[2] = options.0 as u8;
+            out[2] = options.level as u8;
             &out[0..3]
         }
         #[cfg(feature = "aes256")]
@@ -28,6 +28,25 @@ impl LzmaOptions {
     pub fn from_level(level: u32) -> Self {
         Self(lzma_rust2::LzmaOptions::with_preset(level))
     }
+
+    /// Sets the dictionary size used when encoding.
+    ///
+    /// Will be clamped between 4096..=4294967280.
+    pub fn set_dictionary_size(&mut self, dict_size: u32) {
+        self.0.dict_size = dict_size.clamp(lzma_rust2::DICT_SIZE_MIN, lzma_rust2::DICT_SIZE_MAX);
+    }
+
+    /// Sets the literal context bits, literal position bits and position bits.
+    ///
+    /// # Arguments
+    /// * `lc` - Literal context bits (0-8, clamped to this range)
+    /// * `lp` - Literal position bits (0-4, clamped to this range)
+    /// * `pb` - Position bits (0-4, clamped to this range)
+    pub fn set_lc_lp_pb(&mut self, lc: u32, lp: u32, pb: u32) {
+        self.0.lc = lc.min(8);
+        self.0.lp = lp.min(4);
+        self.0.pb = pb.min(4);
+    }
 }
 
 #[cfg(feature = "compress")]
@@ -91,37 +110,76 @@ impl Lzma2Options {
 #[cfg(feature = "bzip2")]
 #[derive(Debug, Copy, Clone)]
 /// Options for BZIP2 compression.
-pub struct Bzip2Options(pub(crate) u32);
+pub struct Bzip2Options {
+    pub(crate) level: u32,
+    pub(crate) threads: u32,
+}
 
 #[cfg(feature = "bzip2")]
 impl Bzip2Options {
     /// Creates BZIP2 options with the specified compression level.
+    /// Encoded using a single thread.
     ///
     /// # Arguments
-    /// * `level` - Compression level (typically 1-9)
+    /// * `level` - Compression level (1-9, clamped to this range). The level sets the block size
+    ///   in units of 100 KB.
     pub const fn from_level(level: u32) -> Self {
-        Self(level)
+        let level = if level < 1 {
+            1
+        } else if level > 9 {
+            9
+        } else {
+            level
+        };
+        Self { level, threads: 1 }
+    }
+
+    /// Sets the count of threads used to compress blocks in parallel. The blocks are still
+    /// written as a single bzip2 stream, so the output stays readable by every decoder.
+    ///
+    /// The count will be clamped to 1..=256.
+    pub fn with_threads(mut self, threads: u32) -> Self {
+        self.threads = threads.clamp(1, 256);
+        self
     }
 }
 
 #[cfg(feature = "bzip2")]
 impl Default for Bzip2Options {
     fn default() -> Self {
-        Self(6)
+        Self::from_level(6)
     }
 }
 
-#[cfg(any(feature = "brotli", feature = "lz4"))]
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
 const MINIMAL_SKIPPABLE_FRAME_SIZE: u32 = 64 * 1024;
-#[cfg(feature = "brotli")]
+#[cfg(any(feature = "brotli", feature = "lz4"))]
 const DEFAULT_SKIPPABLE_FRAME_SIZE: u32 = 128 * 1024;
+#[cfg(feature = "zstd")]
+const DEFAULT_ZSTD_FRAME_SIZE: u32 = 1024 * 1024;
+
+#[cfg(feature = "brotli")]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+/// Kind of input Brotli optimizes its compression for.
+pub enum BrotliMode {
+    /// No assumptions about the input.
+    #[default]
+    Generic,
+    /// UTF-8 formatted text.
+    Text,
+    /// WOFF 2.0 fonts.
+    Font,
+}
 
 #[cfg(feature = "brotli")]
 #[derive(Debug, Copy, Clone)]
 /// Options for Brotli compression.
 pub struct BrotliOptions {
     pub(crate) quality: u32,
+    pub(crate) window_size: u32,
+    pub(crate) mode: BrotliMode,
     pub(crate) skippable_frame_size: u32,
+    pub(crate) threads: u32,
 }
 
 #[cfg(feature = "brotli")]
@@ -134,10 +192,27 @@ impl BrotliOptions {
         let quality = if quality > 11 { 11 } else { quality };
         Self {
             quality,
+            window_size: 22,
+            mode: BrotliMode::Generic,
             skippable_frame_size: DEFAULT_SKIPPABLE_FRAME_SIZE,
+            threads: 1,
         }
     }
 
+    /// Sets the window size as a power of two (lgwin). Defaults to 22, which is a window of 4 MiB.
+    ///
+    /// Will be clamped between 10..=24.
+    pub fn with_window_size(mut self, window_size: u32) -> Self {
+        self.window_size = window_size.clamp(10, 24);
+        self
+    }
+
+    /// Sets the kind of input the compression is optimized for.
+    pub fn with_mode(mut self, mode: BrotliMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
     /// Set's the skippable frame size. The size is defined as the size of uncompressed data a frame
     /// contains. A value of 0 deactivates skippable frames and uses the native brotli bitstream.
     /// If a value is set, then a similar skippable frame format used by LZ4 and ZSTD is used.
@@ -155,15 +230,25 @@ impl BrotliOptions {
 
         self
     }
+
+    /// Sets the count of threads used to compress the skippable frames in parallel.
+    ///
+    /// Multi-threading needs skippable frames, so if they are deactivated they are activated with
+    /// a frame size of 128 KiB. The count will be clamped to 1..=256.
+    pub fn with_threads(mut self, threads: u32) -> Self {
+        self.threads = threads.clamp(1, 256);
+        if self.threads > 1 && self.skippable_frame_size == 0 {
+            self.skippable_frame_size = DEFAULT_SKIPPABLE_FRAME_SIZE;
+        }
+
+        self
+    }
 }
 
 #[cfg(feature = "brotli")]
 impl Default for BrotliOptions {
     fn default() -> Self {
-        Self {
-            quality: 11,
-            skippable_frame_size: DEFAULT_SKIPPABLE_FRAME_SIZE,
-        }
+        Self::from_quality(11)
     }
 }
 
@@ -197,6 +282,27 @@ impl Default for DeltaOptions {
     }
 }
 
+#[cfg(feature = "compress")]
+#[derive(Debug, Copy, Clone, Default)]
+/// Options for the BCJ filters of x86, ARM, ARM64, ARM Thumb, PPC, SPARC, IA64 and RISC-V.
+///
+/// The options apply to whichever BCJ filter method they are configured with, e.g.
+/// `EncoderConfiguration::new(EncoderMethod::BCJ_ARM64).with_options(options.into())`.
+pub struct BcjOptions(pub(crate) u32);
+
+#[cfg(feature = "compress")]
+impl BcjOptions {
+    /// Creates BCJ options with the specified start offset.
+    ///
+    /// # Arguments
+    /// * `start_offset` - Address the filtered data is assumed to start at. It has to be a
+    ///   multiple of the filter's instruction alignment: 4 for ARM, ARM64, PPC and SPARC,
+    ///   2 for ARM Thumb and RISC-V, 16 for IA64 and any value for x86.
+    pub const fn from_start_offset(start_offset: u32) -> Self {
+        Self(start_offset)
+    }
+}
+
 #[cfg(feature = "deflate")]
 #[derive(Debug, Copy, Clone)]
 /// Options for Deflate compression.
@@ -222,14 +328,68 @@ impl Default for DeflateOptions {
 }
 
 #[cfg(feature = "lz4")]
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+/// Maximal size of the blocks a LZ4 frame is split into.
+pub enum Lz4BlockSize {
+    /// Blocks of up to 64 KiB.
+    #[default]
+    Max64KB,
+    /// Blocks of up to 256 KiB.
+    Max256KB,
+    /// Blocks of up to 1 MiB.
+    Max1MB,
+    /// Blocks of up to 4 MiB.
+    Max4MB,
+}
+
+#[cfg(feature = "lz4")]
+#[derive(Debug, Copy, Clone)]
 /// Options for LZ4 compression.
 pub struct Lz4Options {
+    pub(crate) level: u32,
+    pub(crate) block_size: Lz4BlockSize,
     pub(crate) skippable_frame_size: u32,
+    pub(crate) threads: u32,
+}
+
+#[cfg(feature = "lz4")]
+impl Default for Lz4Options {
+    fn default() -> Self {
+        Self::from_level(1)
+    }
 }
 
 #[cfg(feature = "lz4")]
 impl Lz4Options {
+    /// Creates LZ4 options with the specified compression level.
+    ///
+    /// Levels 1 and 2 use the fast compressor, levels 3 to 12 the high compression (HC)
+    /// compressor.
+    ///
+    /// # Arguments
+    /// * `level` - Compression level (1-12, clamped to this range)
+    pub const fn from_level(level: u32) -> Self {
+        let level = if level > 12 {
+            12
+        } else if level < 1 {
+            1
+        } else {
+            level
+        };
+        Self {
+            level,
+            block_size: Lz4BlockSize::Max64KB,
+            skippable_frame_size: 0,
+            threads: 1,
+        }
+    }
+
+    /// Sets the maximal size of the blocks. Larger blocks compress better, but need more memory.
+    pub fn with_block_size(mut self, block_size: Lz4BlockSize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
     /// Set's the skippable frame size. The size is defined as the size of uncompressed data a frame
     /// contains. A value of 0 deactivates skippable frames and uses the native LZ4 bitstream.
     /// If a value is set, then the similar skippable frame format is used.
@@ -250,6 +410,19 @@ impl Lz4Options {
 
         self
     }
+
+    /// Sets the count of threads used to compress the skippable frames in parallel.
+    ///
+    /// Multi-threading needs skippable frames, so if they are deactivated they are activated with
+    /// a frame size of 128 KiB. The count will be clamped to 1..=256.
+    pub fn with_threads(mut self, threads: u32) -> Self {
+        self.threads = threads.clamp(1, 256);
+        if self.threads > 1 && self.skippable_frame_size == 0 {
+            self.skippable_frame_size = DEFAULT_SKIPPABLE_FRAME_SIZE;
+        }
+
+        self
+    }
 }
 
 #[cfg(feature = "ppmd")]
@@ -307,10 +480,41 @@ impl Default for PpmdOptions {
     }
 }
 
+#[cfg(feature = "zstd")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Match finding strategy of Zstandard, ordered from the fastest to the strongest.
+pub enum ZstdStrategy {
+    /// Fast hash based matching.
+    Fast,
+    /// Double fast hash based matching.
+    DFast,
+    /// Greedy parsing.
+    Greedy,
+    /// Lazy parsing.
+    Lazy,
+    /// Lazy parsing looking two positions ahead.
+    Lazy2,
+    /// Lazy parsing with a binary tree match finder.
+    BtLazy2,
+    /// Optimal parsing.
+    BtOpt,
+    /// Optimal parsing with a more thorough search.
+    BtUltra,
+    /// Optimal parsing with the most thorough search.
+    BtUltra2,
+}
+
 #[cfg(feature = "zstd")]
 #[derive(Debug, Copy, Clone)]
 /// Options for Zstandard compression.
-pub struct ZstandardOptions(pub(crate) u32);
+pub struct ZstandardOptions {
+    pub(crate) level: u32,
+    pub(crate) window_log: u32,
+    pub(crate) long_distance_matching: bool,
+    pub(crate) strategy: Option<ZstdStrategy>,
+    pub(crate) skippable_frame_size: u32,
+    pub(crate) threads: u32,
+}
 
 #[cfg(feature = "zstd")]
 impl ZstandardOptions {
@@ -320,14 +524,87 @@ impl ZstandardOptions {
     /// * `level` - Compression level (typically 1-22)
     pub const fn from_level(level: u32) -> Self {
         let level = if level > 22 { 22 } else { level };
-        Self(level)
+        Self {
+            level,
+            window_log: 0,
+            long_distance_matching: false,
+            strategy: None,
+            skippable_frame_size: 0,
+            threads: 1,
+        }
+    }
+
+    /// Sets the window size as a power of two. A value of 0 uses the window size of the level.
+    ///
+    /// With skippable frames, which [`with_threads`](Self::with_threads) enables, every frame is
+    /// compressed on its own, so a window larger than the frame size has no effect.
+    ///
+    /// Will be clamped between 10..=31.
+    pub fn with_window_log(mut self, window_log: u32) -> Self {
+        self.window_log = if window_log == 0 {
+            0
+        } else {
+            window_log.clamp(10, 31)
+        };
+        self
+    }
+
+    /// Enables long distance matching, which finds matches far back in large windows. If no
+    /// window size is set, a window of 128 MiB is used.
+    ///
+    /// Matches can't reach into earlier frames, so with skippable frames of the default 1 MiB
+    /// this has little effect.
+    pub fn with_long_distance_matching(mut self, long_distance_matching: bool) -> Self {
+        self.long_distance_matching = long_distance_matching;
+        self
+    }
+
+    /// Sets the match finding strategy instead of the one of the level.
+    pub fn with_strategy(mut self, strategy: ZstdStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Set's the skippable frame size. The size is defined as the size of uncompressed data a frame
+    /// contains. A value of 0 deactivates skippable frames and writes a single zstd frame.
+    /// If a value is set, then every zstd frame is preceded by a skippable frame holding its
+    /// compressed size.
+    ///
+    /// Af value between 1..=64KiB will be set to 64KiB.
+    ///
+    /// This was first implemented by zstdmt. Defaults to not use skippable frames.
+    pub fn with_skippable_frame_size(mut self, skippable_frame_size: u32) -> Self {
+        if skippable_frame_size == 0 {
+            self.skippable_frame_size = 0;
+        } else {
+            self.skippable_frame_size =
+                u32::max(skippable_frame_size, MINIMAL_SKIPPABLE_FRAME_SIZE);
+        }
+
+        self
+    }
+
+    /// Sets the count of threads used to compress the skippable frames in parallel.
+    ///
+    /// Multi-threading needs skippable frames, so if they are deactivated they are activated with
+    /// a frame size of 1 MiB. The frames are compressed independently, which limits
+    /// [`with_window_log`](Self::with_window_log) and
+    /// [`with_long_distance_matching`](Self::with_long_distance_matching) to the frame size.
+    /// The count will be clamped to 1..=256.
+    pub fn with_threads(mut self, threads: u32) -> Self {
+        self.threads = threads.clamp(1, 256);
+        if self.threads > 1 && self.skippable_frame_size == 0 {
+            self.skippable_frame_size = DEFAULT_ZSTD_FRAME_SIZE;
+        }
+
+        self
     }
 }
 
 #[cfg(feature = "zstd")]
 impl Default for ZstandardOptions {
     fn default() -> Self {
-        Self(3)
+        Self::from_level(3)
     }
 }
 
@@ -335,8 +612,8 @@ impl Default for ZstandardOptions {
 #[derive(Debug, Clone)]
 /// Options for AES256 encryption.
 pub struct AesEncoderOptions {
-    /// Password for encryption.
-    pub password: Password,
+    /// Password for encryption, only set through [`AesEncoderOptions::new`].
+    pub(crate) password: Password,
     /// Initialization vector for encryption.
     pub iv: [u8; 16],
     /// Salt for key derivation.
@@ -391,6 +668,9 @@ pub enum EncoderOptions {
     /// Delta filter options.
     Delta(DeltaOptions),
     #[cfg(feature = "compress")]
+    /// BCJ filter options.
+    Bcj(BcjOptions),
+    #[cfg(feature = "compress")]
     /// LZMA compression options.
     Lzma(LzmaOptions),
     #[cfg(feature = "compress")]
@@ -440,6 +720,13 @@ impl From<DeltaOptions> for EncoderConfiguration {
     }
 }
 
+#[cfg(feature = "compress")]
+impl From<LzmaOptions> for EncoderConfiguration {
+    fn from(options: LzmaOptions) -> Self {
+        Self::new(crate::EncoderMethod::LZMA).with_options(EncoderOptions::Lzma(options))
+    }
+}
+
 #[cfg(feature = "compress")]
 impl From<Lzma2Options> for EncoderConfiguration {
     fn from(options: Lzma2Options) -> Self {
@@ -496,6 +783,20 @@ impl From<DeltaOptions> for EncoderOptions {
     }
 }
 
+#[cfg(feature = "compress")]
+impl From<BcjOptions> for EncoderOptions {
+    fn from(o: BcjOptions) -> Self {
+        Self::Bcj(o)
+    }
+}
+
+#[cfg(feature = "compress")]
+impl From<LzmaOptions> for EncoderOptions {
+    fn from(o: LzmaOptions) -> Self {
+        Self::Lzma(o)
+    }
+}
+
 #[cfg(feature = "compress")]
 impl From<Lzma2Options> for EncoderOptions {
     fn from(o: Lzma2Options) -> Self {
@@ -1,7 +1,12 @@
 #[cfg(feature = "aes256")]
 mod aes;
+mod info;
+mod key_cache;
 mod password;
 
 #[cfg(feature = "aes256")]
 pub(crate) use aes::*;
+pub use info::*;
+pub use key_cache::DEFAULT_MAX_NUM_CYCLES_POWER;
+pub(crate) use key_cache::KeyCache;
 pub use password::*;
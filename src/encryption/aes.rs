@@ -10,7 +10,9 @@ use aes::{
     cipher::{BlockDecryptMut, KeyIvInit, generic_array::GenericArray},
 };
 use sha2::Digest;
+use zeroize::Zeroizing;
 
+use super::KeyCache;
 use crate::Password;
 #[cfg(feature = "compress")]
 use crate::encoder_options::AesEncoderOptions;
@@ -31,12 +33,25 @@ pub(crate) struct Aes256Sha256Decoder<R> {
 }
 
 impl<R: AsyncRead + Unpin> Aes256Sha256Decoder<R> {
-    pub(crate) fn new(
+    /// Creates a decoder after checking the key-derivation cost against the limit of `keys`.
+    ///
+    /// The key is derived on the blocking thread pool, since it can take seconds of CPU time.
+    pub(crate) async fn new(
         input: R,
         properties: &[u8],
         password: &Password,
+        keys: &KeyCache,
     ) -> Result<Self, crate::Error> {
-        let cipher = Cipher::from_properties(properties, password.as_slice())?;
+        keys.check_cost(properties.first().map_or(0, |b0| b0 & 63))?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let (aes_key, iv) = {
+            let (properties, password, keys) =
+                (properties.to_vec(), password.clone(), keys.clone());
+            blocking::unblock(move || get_aes_key(&properties, &password, &keys)).await?
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (aes_key, iv) = get_aes_key(properties, password, keys)?;
+        let cipher = Cipher::new(&aes_key, iv);
         Ok(Self {
             input,
             cipher,
@@ -108,7 +123,11 @@ impl<R: AsyncRead + Unpin> AsyncRead for Aes256Sha256Decoder<R> {
     }
 }
 
-fn get_aes_key(properties: &[u8], password: &[u8]) -> Result<([u8; 32], [u8; 16]), crate::Error> {
+fn get_aes_key(
+    properties: &[u8],
+    password: &Password,
+    keys: &KeyCache,
+) -> Result<(Zeroizing<[u8; 32]>, [u8; 16]), crate::Error> {
     if properties.len() < 2 {
         return Err(crate::Error::other("AES256 properties too shart"));
     }
@@ -128,26 +147,41 @@ fn get_aes_key(properties: &[u8], password: &[u8]) -> Result<([u8; 32], [u8; 16]
         return Err(crate::Error::PasswordRequired);
     }
     let aes_key = if num_cycles_power == 0x3F {
-        let mut aes_key = [0u8; 32];
-        aes_key.copy_from_slice(&salt[..salt_size]);
+        let mut aes_key = Zeroizing::new([0u8; 32]);
+        aes_key[..salt_size].copy_from_slice(&salt[..salt_size]);
+        let password = password.as_slice();
         let n = password.len().min(aes_key.len() - salt_size);
         aes_key[salt_size..n + salt_size].copy_from_slice(&password[0..n]);
         aes_key
     } else {
-        let mut sha = sha2::Sha256::default();
-        let mut extra = [0u8; 8];
-        for _ in 0..(1u32 << num_cycles_power) {
-            sha.update(&salt);
-            sha.update(password);
-            sha.update(extra);
-            for item in &mut extra {
-                *item = item.wrapping_add(1);
-                if *item != 0 {
-                    break;
+        keys.get_or_derive(password, &salt, num_cycles_power, || {
+            let password = password.as_slice();
+            let round_len = salt.len() + password.len() + 8;
+            let rounds = 1u64 << num_cycles_power;
+            // Rounds are hashed in batches of 64 from a buffer that is zeroized afterwards. A
+            // batch spans whole SHA-256 blocks, so no password bytes are left in the block
+            // buffer of the hasher, which is not zeroized on drop.
+            let batch = rounds.min(64) as usize;
+            let mut buf = Zeroizing::new(Vec::with_capacity(round_len * batch));
+            for _ in 0..batch {
+                buf.extend_from_slice(&salt);
+                buf.extend_from_slice(password);
+                buf.extend_from_slice(&[0; 8]);
+            }
+            let mut sha = sha2::Sha256::default();
+            let mut round = 0u64;
+            while round < rounds {
+                if round & 0xFFFF == 0 && keys.is_cancelled() {
+                    return Err(crate::Error::Cancelled);
                 }
+                for (i, chunk) in buf.chunks_exact_mut(round_len).enumerate() {
+                    chunk[round_len - 8..].copy_from_slice(&(round + i as u64).to_le_bytes());
+                }
+                sha.update(buf.as_slice());
+                round += batch as u64;
             }
-        }
-        sha.finalize().into()
+            Ok(Zeroizing::new(sha.finalize().into()))
+        })?
     };
     Ok((aes_key, iv))
 }
@@ -158,12 +192,11 @@ struct Cipher {
 }
 
 impl Cipher {
-    fn from_properties(properties: &[u8], password: &[u8]) -> Result<Self, crate::Error> {
-        let (aes_key, iv) = get_aes_key(properties, password)?;
-        Ok(Self {
-            dec: Aes256CbcDec::new(&GenericArray::from(aes_key), &iv.into()),
+    fn new(aes_key: &[u8; 32], iv: [u8; 16]) -> Self {
+        Self {
+            dec: Aes256CbcDec::new(GenericArray::from_slice(aes_key), &iv.into()),
             buf: Default::default(),
-        })
+        }
     }
 
     fn update(&mut self, mut data: &mut [u8], output: &mut Vec<u8>) -> std::io::Result<usize> {
@@ -221,15 +254,17 @@ pub(crate) struct Aes256Sha256Encoder<W> {
 
 #[cfg(feature = "compress")]
 impl<W> Aes256Sha256Encoder<W> {
-    pub(crate) fn new(output: W, options: &AesEncoderOptions) -> Result<Self, crate::Error> {
-        let (key, iv) = crate::encryption::aes::get_aes_key(
-            &options.properties(),
-            options.password.as_slice(),
-        )?;
+    pub(crate) fn new(
+        output: W,
+        options: &AesEncoderOptions,
+        keys: &KeyCache,
+    ) -> Result<Self, crate::Error> {
+        let (key, iv) =
+            crate::encryption::aes::get_aes_key(&options.properties(), &options.password, keys)?;
 
         Ok(Self {
             output,
-            enc: Aes256CbcEnc::new(&GenericArray::from(key), &iv.into()),
+            enc: Aes256CbcEnc::new(GenericArray::from_slice(key.as_slice()), &iv.into()),
             buffer: Default::default(),
             out_buf: Default::default(),
             out_pos: 0,
@@ -443,13 +478,16 @@ mod tests {
         let writer = Cursor::new(&mut encoded);
         let password: Password = "1234".into();
         let options = AesEncoderOptions::new(password.clone());
-        let mut enc = Aes256Sha256Encoder::new(writer, &options).unwrap();
+        let keys = KeyCache::default();
+        let mut enc = Aes256Sha256Encoder::new(writer, &options, &keys).unwrap();
         let original = include_bytes!("aes.rs");
         AsyncWriteExt::write_all(&mut enc, original).await.unwrap();
         let _ = AsyncWriteExt::write(&mut enc, &[]).await.unwrap();
 
         let cursor = Cursor::new(&encoded[..]);
-        let mut dec = Aes256Sha256Decoder::new(cursor, &options.properties(), &password).unwrap();
+        let mut dec = Aes256Sha256Decoder::new(cursor, &options.properties(), &password, &keys)
+            .await
+            .unwrap();
 
         let mut decoded = vec![];
         AsyncReadExt::read_to_end(&mut dec, &mut decoded)
@@ -457,4 +495,22 @@ mod tests {
             .unwrap();
         assert_eq!(&decoded[..original.len()], &original[..]);
     }
+
+    #[tokio::test]
+    async fn test_cancel_key_derivation() {
+        let password: Password = "1234".into();
+        let options = AesEncoderOptions::new(password.clone());
+        let token = crate::CancellationToken::new();
+        token.cancel();
+        let mut keys = KeyCache::default();
+        keys.set_cancellation_token(Some(token));
+        let result = Aes256Sha256Decoder::new(
+            Cursor::new(&[][..]),
+            &options.properties(),
+            &password,
+            &keys,
+        )
+        .await;
+        assert!(matches!(result, Err(crate::Error::Cancelled)));
+    }
 }
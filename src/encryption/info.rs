@@ -0,0 +1,67 @@
+use crate::Archive;
+
+/// Encryption of an archive, available without a password.
+///
+/// See [`Archive::inspect_encryption`] and [`Archive::encryption_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionInfo {
+    /// Key-derivation cost of the encrypted header, `None` if the header is not encrypted.
+    ///
+    /// Key derivation runs `2^num_cycles_power` SHA-256 rounds.
+    pub header_num_cycles_power: Option<u8>,
+    /// The encrypted blocks, `None` if they are unknown because the header is encrypted and was
+    /// not decoded.
+    pub blocks: Option<Vec<EncryptedBlock>>,
+}
+
+/// An encrypted block, see [`EncryptionInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptedBlock {
+    /// Index of the block in [`Archive::blocks`].
+    pub index: usize,
+    /// Key derivation runs `2^num_cycles_power` SHA-256 rounds, `0` if the AES properties are
+    /// missing.
+    pub num_cycles_power: u8,
+}
+
+impl EncryptionInfo {
+    /// Returns `true` if the header is encrypted, so entry names are only known with the
+    /// password.
+    pub fn is_header_encrypted(&self) -> bool {
+        self.header_num_cycles_power.is_some()
+    }
+
+    /// Returns `true` if the header or any block is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.is_header_encrypted() || self.blocks.as_ref().is_some_and(|b| !b.is_empty())
+    }
+
+    /// Returns the highest key-derivation cost of the header and all known blocks.
+    pub fn max_num_cycles_power(&self) -> Option<u8> {
+        let blocks = self.blocks.iter().flatten().map(|b| b.num_cycles_power);
+        self.header_num_cycles_power.into_iter().chain(blocks).max()
+    }
+}
+
+impl Archive {
+    /// Returns the encryption of the header and the blocks of this archive.
+    pub fn encryption_info(&self) -> EncryptionInfo {
+        EncryptionInfo {
+            header_num_cycles_power: self.header_num_cycles_power,
+            blocks: Some(
+                self.blocks
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, block)| {
+                        block
+                            .aes_num_cycles_power()
+                            .map(|num_cycles_power| EncryptedBlock {
+                                index,
+                                num_cycles_power,
+                            })
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use zeroize::Zeroizing;
+
+use crate::{CancellationToken, Error, Password};
+
+/// Highest key-derivation cost accepted by default when decoding, the same limit as 7-Zip.
+pub const DEFAULT_MAX_NUM_CYCLES_POWER: u8 = 24;
+
+#[derive(PartialEq, Eq)]
+struct KeyId {
+    password: Password,
+    salt: Vec<u8>,
+    num_cycles_power: u8,
+}
+
+impl Hash for KeyId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.password.as_slice().hash(state);
+        self.salt.hash(state);
+        self.num_cycles_power.hash(state);
+    }
+}
+
+/// The AES keys derived during a reader or writer session.
+///
+/// Key derivation runs up to `2^num_cycles_power` SHA-256 rounds, while 7z archives usually use
+/// the same password, salt and cost for the header and every block. Clones share the same keys,
+/// which are overwritten with zeros once the last clone is dropped.
+#[derive(Clone)]
+pub(crate) struct KeyCache {
+    keys: Arc<Mutex<HashMap<KeyId, Zeroizing<[u8; 32]>>>>,
+    max_num_cycles_power: u8,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self {
+            keys: Default::default(),
+            max_num_cycles_power: DEFAULT_MAX_NUM_CYCLES_POWER,
+            cancellation: None,
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "aes256"), allow(dead_code))]
+impl KeyCache {
+    /// Sets the highest key-derivation cost accepted by [`check_cost`](Self::check_cost).
+    pub(crate) fn set_max_num_cycles_power(&mut self, max_num_cycles_power: u8) {
+        self.max_num_cycles_power = max_num_cycles_power;
+    }
+
+    /// Sets a token that stops key derivations with [`Error::Cancelled`].
+    pub(crate) fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// Fails if deriving a key with the given cost is not allowed. A cost of `0x3F` means that
+    /// the key is not derived at all.
+    pub(crate) fn check_cost(&self, num_cycles_power: u8) -> Result<(), Error> {
+        if num_cycles_power != 0x3F && num_cycles_power > self.max_num_cycles_power {
+            return Err(Error::KeyDerivationTooExpensive {
+                num_cycles_power,
+                max_num_cycles_power: self.max_num_cycles_power,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if a running key derivation should stop.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Returns the key for the given parameters and runs `derive` only if it was not derived
+    /// before.
+    pub(crate) fn get_or_derive(
+        &self,
+        password: &Password,
+        salt: &[u8],
+        num_cycles_power: u8,
+        derive: impl FnOnce() -> Result<Zeroizing<[u8; 32]>, Error>,
+    ) -> Result<Zeroizing<[u8; 32]>, Error> {
+        let id = KeyId {
+            password: password.clone(),
+            salt: salt.to_vec(),
+            num_cycles_power,
+        };
+        if let Some(key) = self.keys.lock().unwrap().get(&id) {
+            return Ok(key.clone());
+        }
+        // The lock is not held while deriving, so other blocks are not blocked by it.
+        let key = derive()?;
+        self.keys.lock().unwrap().insert(id, key.clone());
+        Ok(key)
+    }
+}
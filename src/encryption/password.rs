@@ -1,3 +1,9 @@
+use std::{fmt, sync::Arc};
+
+use zeroize::Zeroizing;
+
+use super::KeyCache;
+
 /// A password used for password protected, encrypted files.
 ///
 /// Use [`Password::empty()`] to create an empty password when no
@@ -10,8 +16,11 @@
 ///
 /// let password: Password = "a password string".into();
 /// ```
-#[derive(Debug, Default, Clone, PartialEq)]
-pub struct Password(Vec<u8>);
+///
+/// The bytes are overwritten with zeros once the last clone of a password is dropped. Clones
+/// share the same bytes instead of copying them, and [`Debug`] does not print them.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Password(Arc<Zeroizing<Vec<u8>>>);
 
 impl Password {
     /// Creates a new [`Password`] from the given password string.
@@ -23,7 +32,7 @@ impl Password {
 
     /// Creates a new [`Password`] from the given raw bytes.
     pub fn from_raw(bytes: &[u8]) -> Self {
-        Self(bytes.to_vec())
+        Self::from_bytes(bytes.to_vec())
     }
 
     /// Creates an empty password.
@@ -31,6 +40,10 @@ impl Password {
         Self(Default::default())
     }
 
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(Arc::new(Zeroizing::new(bytes)))
+    }
+
     /// Returns the byte representation of the password.
     pub fn as_slice(&self) -> &[u8] {
         &self.0
@@ -42,6 +55,12 @@ impl Password {
     }
 }
 
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Password").field(&"<redacted>").finish()
+    }
+}
+
 impl AsRef<[u8]> for Password {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -50,10 +69,132 @@ impl AsRef<[u8]> for Password {
 
 impl From<&str> for Password {
     fn from(s: &str) -> Self {
+        // Reserve enough up front, so the bytes are never moved to a larger allocation and left
+        // behind in the old one.
         let mut result = Vec::with_capacity(s.len() * 2);
         for u in s.encode_utf16() {
             result.extend_from_slice(&u.to_le_bytes());
         }
-        Self(result)
+        Self::from_bytes(result)
+    }
+}
+
+/// Supplies passwords on demand while reading an encrypted archive.
+///
+/// The provider is only asked once an encrypted header or an AES coder is actually met, and
+/// asked again whenever the previous password turned out to be wrong. Any
+/// `Fn(usize) -> Option<Password>` closure can be used as a provider:
+///
+/// ```rust
+/// use async_sevenz::{Password, PasswordProvider};
+///
+/// fn provider() -> impl PasswordProvider {
+///     let candidates = ["first guess", "second guess"];
+///     move |attempt: usize| candidates.get(attempt).map(|p| Password::from(*p))
+/// }
+/// ```
+pub trait PasswordProvider: Send + Sync {
+    /// Returns the password to try next, or `None` to give up.
+    ///
+    /// `attempt` is `0` for the first request and increases by one after every wrong password.
+    fn password(&self, attempt: usize) -> Option<Password>;
+}
+
+impl<F: Fn(usize) -> Option<Password> + Send + Sync> PasswordProvider for F {
+    fn password(&self, attempt: usize) -> Option<Password> {
+        self(attempt)
+    }
+}
+
+/// The password used to decode an archive, either fixed up front or requested from a
+/// [`PasswordProvider`] until one is confirmed to work.
+pub(crate) struct PasswordSource {
+    password: Password,
+    provider: Option<Box<dyn PasswordProvider>>,
+    attempts: usize,
+    confirmed: bool,
+    keys: KeyCache,
+}
+
+impl PasswordSource {
+    pub(crate) fn fixed(password: Password) -> Self {
+        Self {
+            password,
+            provider: None,
+            attempts: 0,
+            confirmed: true,
+            keys: KeyCache::default(),
+        }
+    }
+
+    pub(crate) fn provider(provider: impl PasswordProvider + 'static) -> Self {
+        Self {
+            password: Password::empty(),
+            provider: Some(Box::new(provider)),
+            attempts: 0,
+            confirmed: false,
+            keys: KeyCache::default(),
+        }
+    }
+
+    /// Returns the password to use for the next decoding attempt.
+    pub(crate) fn current(&self) -> &Password {
+        &self.password
+    }
+
+    /// Returns the keys derived from the passwords of this source.
+    pub(crate) fn keys(&self) -> &KeyCache {
+        &self.keys
+    }
+
+    pub(crate) fn keys_mut(&mut self) -> &mut KeyCache {
+        &mut self.keys
+    }
+
+    /// Returns `true` if the current password does not need to be verified before use.
+    pub(crate) fn is_confirmed(&self) -> bool {
+        self.confirmed
+    }
+
+    /// Marks the current password as working, so it is used for all following blocks.
+    pub(crate) fn confirm(&mut self) {
+        self.confirmed = true;
+    }
+
+    /// Requests the next password from the provider after a decoding attempt failed with
+    /// `error`.
+    ///
+    /// Returns `error` if it is not caused by a missing or wrong password, if there is no
+    /// provider, or if the provider gave up.
+    pub(crate) fn retry(&mut self, error: crate::Error) -> Result<(), crate::Error> {
+        if !matches!(
+            error,
+            crate::Error::MaybeBadPassword(_) | crate::Error::PasswordRequired
+        ) {
+            return Err(error);
+        }
+        let Some(password) = self
+            .provider
+            .as_ref()
+            .and_then(|provider| provider.password(self.attempts))
+        else {
+            return Err(error);
+        };
+        self.attempts += 1;
+        self.password = password;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_is_shared_and_redacted() {
+        let password = Password::from("secret");
+        let clone = password.clone();
+        assert_eq!(password.as_slice().as_ptr(), clone.as_slice().as_ptr());
+        assert_eq!(format!("{password:?}"), r#"Password("<redacted>")"#);
     }
 }
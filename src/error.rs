@@ -1,7 +1,12 @@
 use std::{borrow::Cow, fmt::Display};
 
 /// The error type of the crate.
+///
+/// Errors that happen while reading or writing a specific part of an archive are wrapped in
+/// [`Error::Context`], which records the entry, block and archive offset involved. Use
+/// [`without_context`](Self::without_context) to match on the underlying error.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Invalid 7z signature found in file header.
     BadSignature([u8; 6]),
@@ -51,6 +56,72 @@ pub enum Error {
     MaybeBadPassword(std::io::Error),
     /// File not found.
     FileNotFound,
+    /// The operation was cancelled through a [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
+    /// Deriving the AES key would take more SHA-256 rounds than allowed, see
+    /// [`ArchiveReader::set_max_num_cycles_power`](crate::ArchiveReader::set_max_num_cycles_power).
+    KeyDerivationTooExpensive {
+        /// The key derivation runs `2^num_cycles_power` rounds.
+        num_cycles_power: u8,
+        /// The highest allowed `num_cycles_power`.
+        max_num_cycles_power: u8,
+    },
+    /// An error together with the location in the archive it refers to.
+    ///
+    /// Cancellation, password and missing file errors are never wrapped, since they are not
+    /// caused by a specific part of the archive.
+    Context {
+        /// The underlying error, never an [`Error::Context`] itself.
+        error: Box<Error>,
+        /// Where the error happened.
+        context: Box<ErrorContext>,
+    },
+}
+
+/// Location in the archive an [`Error`] refers to.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ErrorContext {
+    /// Name of the entry that was read or written.
+    pub entry: Option<String>,
+    /// Index of the block that was decoded.
+    pub block_index: Option<usize>,
+    /// Names of the coder methods of the block, from the packed data to the unpacked data.
+    pub methods: Vec<String>,
+    /// Offset in the archive of the data that was read, e.g. the first pack stream of a block or
+    /// the header.
+    pub offset: Option<u64>,
+}
+
+impl ErrorContext {
+    /// Fills the fields that are not set yet from `other`.
+    fn merge(&mut self, other: ErrorContext) {
+        self.entry = self.entry.take().or(other.entry);
+        self.block_index = self.block_index.or(other.block_index);
+        if self.methods.is_empty() {
+            self.methods = other.methods;
+        }
+        self.offset = self.offset.or(other.offset);
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(entry) = &self.entry {
+            parts.push(format!("entry '{entry}'"));
+        }
+        if let Some(block_index) = self.block_index {
+            parts.push(format!("block {block_index}"));
+        }
+        if !self.methods.is_empty() {
+            parts.push(format!("methods {}", self.methods.join(" -> ")));
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("offset {offset:#x}"));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -60,6 +131,63 @@ impl From<std::io::Error> for Error {
 }
 
 impl Error {
+    /// Returns the location in the archive this error refers to, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Self::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error without its [`ErrorContext`].
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Self::Context { error, .. } => error,
+            _ => self,
+        }
+    }
+
+    /// Attaches `context` to this error, keeping fields that are already set.
+    pub(crate) fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            Self::Cancelled
+            | Self::PasswordRequired
+            | Self::MaybeBadPassword(_)
+            | Self::KeyDerivationTooExpensive { .. }
+            | Self::FileNotFound => self,
+            Self::Context {
+                error,
+                context: mut current,
+            } => {
+                current.merge(context);
+                Self::Context {
+                    error,
+                    context: current,
+                }
+            }
+            error => Self::Context {
+                error: Box::new(error),
+                context: Box::new(context),
+            },
+        }
+    }
+
+    /// Attaches the name of the entry that was read or written.
+    pub(crate) fn with_entry(self, name: impl Into<String>) -> Self {
+        self.with_context(ErrorContext {
+            entry: Some(name.into()),
+            ..Default::default()
+        })
+    }
+
+    /// Attaches the archive offset of the data that was read.
+    pub(crate) fn with_offset(self, offset: u64) -> Self {
+        self.with_context(ErrorContext {
+            offset: Some(offset),
+            ..Default::default()
+        })
+    }
+
     #[inline]
     pub(crate) fn other<S: Into<Cow<'static, str>>>(s: S) -> Self {
         Self::Other(s.into())
@@ -70,9 +198,17 @@ impl Error {
         Self::Unsupported(s.into())
     }
 
+    /// Wraps an I/O error. Errors of this crate that were passed through an [`std::io::Error`]
+    /// by a reader or writer are unwrapped if there is no message to add.
     #[inline]
     pub(crate) fn io_msg(e: std::io::Error, msg: impl Into<Cow<'static, str>>) -> Self {
-        Self::Io(e, msg.into())
+        let msg = msg.into();
+        let inner = e.get_ref().and_then(|inner| inner.downcast_ref::<Error>());
+        match inner {
+            Some(Error::Cancelled) => Self::Cancelled,
+            Some(_) if msg.is_empty() => *e.into_inner().unwrap().downcast::<Error>().unwrap(),
+            _ => Self::Io(e, msg),
+        }
     }
 
     pub(crate) fn bad_password(e: std::io::Error, encryped: bool) -> Self {
@@ -95,6 +231,16 @@ impl Error {
         }
         match self {
             Self::Io(e, s) if s.is_empty() => Self::MaybeBadPassword(e),
+            Self::ChecksumVerificationFailed => {
+                Self::MaybeBadPassword(std::io::Error::other(Self::ChecksumVerificationFailed))
+            }
+            Self::Context { error, context } => match error.maybe_bad_password(true) {
+                error @ Self::MaybeBadPassword(_) => error,
+                error => Self::Context {
+                    error: Box::new(error),
+                    context,
+                },
+            },
             _ => self,
         }
     }
@@ -102,8 +248,65 @@ impl Error {
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Debug::fmt(&self, f)
+        match self {
+            Self::BadSignature(signature) => {
+                write!(f, "not a 7z archive, bad signature {signature:02x?}")
+            }
+            Self::UnsupportedVersion { major, minor } => {
+                write!(f, "unsupported 7z format version {major}.{minor}")
+            }
+            Self::ChecksumVerificationFailed => write!(f, "checksum verification failed"),
+            Self::NextHeaderCrcMismatch => write!(f, "CRC of the archive header does not match"),
+            Self::Io(e, msg) if msg.is_empty() => write!(f, "I/O error: {e}"),
+            Self::Io(_, msg) => write!(f, "I/O error: {msg}"),
+            Self::FileOpen(_, filename) => write!(f, "cannot open file '{filename}'"),
+            Self::Other(msg) => write!(f, "{msg}"),
+            Self::BadTerminatedStreamsInfo(nid) => {
+                write!(
+                    f,
+                    "bad terminated streams info, unexpected property {nid:#x}"
+                )
+            }
+            Self::BadTerminatedUnpackInfo => write!(f, "bad terminated unpack info"),
+            Self::BadTerminatedPackInfo(nid) => {
+                write!(f, "bad terminated pack info, unexpected property {nid:#x}")
+            }
+            Self::BadTerminatedSubStreamsInfo => write!(f, "bad terminated sub streams info"),
+            Self::BadTerminatedHeader(nid) => {
+                write!(f, "bad terminated header, unexpected property {nid:#x}")
+            }
+            Self::ExternalUnsupported => write!(f, "external archive data is not supported"),
+            Self::UnsupportedCompressionMethod(method) => {
+                write!(f, "unsupported compression method {method}")
+            }
+            Self::MaxMemLimited { max_kb, actaul_kb } => write!(
+                f,
+                "decoding needs {actaul_kb} KiB of memory, more than the limit of {max_kb} KiB"
+            ),
+            Self::PasswordRequired => write!(f, "a password is required"),
+            Self::Unsupported(msg) => write!(f, "unsupported: {msg}"),
+            Self::MaybeBadPassword(_) => write!(f, "wrong password or corrupted data"),
+            Self::FileNotFound => write!(f, "file not found in archive"),
+            Self::Cancelled => write!(f, "operation cancelled"),
+            Self::KeyDerivationTooExpensive {
+                num_cycles_power,
+                max_num_cycles_power,
+            } => write!(
+                f,
+                "AES key derivation needs 2^{num_cycles_power} rounds, more than the limit of \
+                 2^{max_num_cycles_power}"
+            ),
+            Self::Context { error, context } => write!(f, "{error} ({context})"),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e, _) | Self::FileOpen(e, _) | Self::MaybeBadPassword(e) => Some(e),
+            Self::Context { error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
@@ -12,6 +12,7 @@
 //! | BROTLI (*)     | ✓             | ✓           |
 //! | BZIP2          | ✓             | ✓           |
 //! | DEFLATE (*)    | ✓             | ✓           |
+//! | DEFLATE64 (*)  | ✓             |             |
 //! | PPMD           | ✓             | ✓           |
 //! | LZ4 (*)        | ✓             | ✓           |
 //! | ZSTD (*)       | ✓             | ✓           |
@@ -28,7 +29,7 @@
 //! | BCJ PPC       | ✓             | ✓           |
 //! | BCJ SPARC     | ✓             | ✓           |
 //! | BCJ IA64      | ✓             | ✓           |
-//! | BCJ2          | ✓             |             |
+//! | BCJ2          | ✓             | ✓           |
 //! | DELTA         | ✓             | ✓           |
 //!
 //! # Usage
@@ -165,6 +166,7 @@
 #[cfg(target_arch = "wasm32")]
 extern crate wasm_bindgen;
 
+mod cancellation;
 #[cfg(feature = "compress")]
 mod encoder;
 /// Encoding options when compressing.
@@ -172,6 +174,9 @@ mod encoder;
 pub mod encoder_options;
 mod encryption;
 mod error;
+mod listing;
+mod progress;
+mod read_at;
 mod reader;
 
 #[cfg(feature = "compress")]
@@ -184,16 +189,26 @@ mod codec;
 pub(crate) mod decoder;
 
 mod time;
+#[cfg(feature = "tokio")]
+mod tokio_compat;
 mod util;
 
 use std::ops::{Deref, DerefMut};
 
 pub use archive::*;
 pub use block::*;
-pub use encryption::Password;
-pub use error::Error;
-pub use reader::{ArchiveReader, BlockDecoder};
+pub use cancellation::CancellationToken;
+pub use encryption::{
+    DEFAULT_MAX_NUM_CYCLES_POWER, EncryptedBlock, EncryptionInfo, Password, PasswordProvider,
+};
+pub use error::{Error, ErrorContext};
+pub use listing::{BlockDetails, EntryDetails, MethodDetails};
+pub use progress::{ProgressEvent, ProgressObserver, ProgressTotals};
+pub use read_at::{ReadAt, ReadAtReader};
+pub use reader::{ArchiveReader, BlockDecoder, Entries, EntryReader, ReaderOptions, TreeNode};
 pub use time::NtTime;
+#[cfg(feature = "tokio")]
+pub use tokio_compat::TokioCompat;
 #[cfg(all(feature = "compress", not(target_arch = "wasm32")))]
 pub use util::compress::*;
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,123 @@
+use crate::{Archive, ArchiveEntry, CoderProperties, EncoderMethod};
+
+/// A coder of a block with its decoded parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDetails {
+    /// The method, `None` if the method ID is not known.
+    pub method: Option<EncoderMethod>,
+    /// The raw method ID.
+    pub id: Vec<u8>,
+    /// The decoded parameters of the coder.
+    pub properties: CoderProperties,
+}
+
+impl MethodDetails {
+    /// Returns the name of the method, or a hex representation of its ID if it is not known.
+    pub fn name(&self) -> String {
+        match &self.method {
+            Some(method) => method.name().to_string(),
+            None => self.id.iter().map(|b| format!("{b:02X}")).collect(),
+        }
+    }
+}
+
+/// Details of a block, see [`Archive::block_details`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDetails {
+    /// Index of the block in [`Archive::blocks`].
+    pub index: usize,
+    /// The coders of the block in the order they are stored in the archive.
+    pub methods: Vec<MethodDetails>,
+    /// Total size of the packed streams of the block.
+    pub packed_size: u64,
+    /// Size of the decoded block.
+    pub unpacked_size: u64,
+    /// Number of entries stored in the block.
+    pub entry_count: usize,
+    /// Whether the block is encrypted.
+    pub encrypted: bool,
+}
+
+impl BlockDetails {
+    /// Returns `true` if more than one entry is stored in the block.
+    pub fn is_solid(&self) -> bool {
+        self.entry_count > 1
+    }
+}
+
+/// Details of an entry, see [`Archive::entry_details`].
+#[derive(Debug, Clone, Copy)]
+pub struct EntryDetails<'a> {
+    /// The entry.
+    pub entry: &'a ArchiveEntry,
+    /// Index of the block that stores the data of the entry, `None` for entries without data.
+    pub block_index: Option<usize>,
+    /// Size of the packed data of the entry, only known if its block stores no other entry.
+    pub packed_size: Option<u64>,
+    /// Whether the entry shares its block with other entries.
+    pub solid: bool,
+    /// Whether the data of the entry is encrypted.
+    pub encrypted: bool,
+}
+
+impl Archive {
+    /// Returns the details of all blocks: their methods with decoded parameters, sizes and
+    /// encryption.
+    pub fn block_details(&self) -> Vec<BlockDetails> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| {
+                let first_pack_stream = self.stream_map.block_first_pack_stream_index[index];
+                let packed_size = self.pack_sizes
+                    [first_pack_stream..first_pack_stream + block.packed_streams.len()]
+                    .iter()
+                    .sum();
+                let methods: Vec<_> = block
+                    .coders
+                    .iter()
+                    .map(|coder| MethodDetails {
+                        method: coder.method(),
+                        id: coder.encoder_method_id().to_vec(),
+                        properties: coder.decoded_properties(),
+                    })
+                    .collect();
+                let encrypted = methods
+                    .iter()
+                    .any(|m| m.id == EncoderMethod::ID_AES256_SHA256);
+                BlockDetails {
+                    index,
+                    methods,
+                    packed_size,
+                    unpacked_size: block.get_unpack_size(),
+                    entry_count: block.num_unpack_sub_streams,
+                    encrypted,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the details of all entries: their block, packed size, solid block membership and
+    /// encryption.
+    ///
+    /// This is the equivalent of `7z l -slt`. Combine it with [`block_details`](Self::block_details)
+    /// for the methods used by an entry.
+    pub fn entry_details(&self) -> Vec<EntryDetails<'_>> {
+        let blocks = self.block_details();
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(file_index, entry)| {
+                let block_index = self.stream_map.file_block_index[file_index];
+                let block = block_index.map(|index| &blocks[index]);
+                EntryDetails {
+                    entry,
+                    block_index,
+                    packed_size: block.filter(|b| !b.is_solid()).map(|b| b.packed_size),
+                    solid: block.is_some_and(BlockDetails::is_solid),
+                    encrypted: block.is_some_and(|b| b.encrypted),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_lite::io::{AsyncRead, AsyncSeek, SeekFrom};
+
+use crate::{Archive, ArchiveEntry};
+
+/// Totals of a compression or extraction, reported once before any data is processed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressTotals {
+    /// Number of entries that will be processed.
+    pub entries: usize,
+    /// Total uncompressed size of all entries in bytes.
+    ///
+    /// When extracting, this is the size of the data that will be decoded. It includes entries
+    /// that are decoded and discarded because they precede a selected entry in a solid block.
+    pub unpacked_size: u64,
+    /// Total size of the packed streams in bytes, `0` if it is not known up front.
+    ///
+    /// When extracting, only the packed streams of blocks that will be decoded are counted.
+    pub packed_size: u64,
+}
+
+impl ProgressTotals {
+    /// Returns the totals of all entries and packed streams of the given archive.
+    pub fn from_archive(archive: &Archive) -> Self {
+        Self {
+            entries: archive.files.len(),
+            unpacked_size: archive.files.iter().map(|file| file.size).sum(),
+            packed_size: archive.pack_sizes.iter().sum(),
+        }
+    }
+
+    /// Returns the totals of extracting the entries of `archive` whose flag in `selected` is
+    /// set.
+    ///
+    /// Blocks without a selected entry are skipped and a block is decoded up to its last
+    /// selected entry, so only that data is counted.
+    pub(crate) fn from_selection(archive: &Archive, selected: &[bool]) -> Self {
+        let mut totals = Self {
+            entries: selected.iter().filter(|&&s| s).count(),
+            ..Self::default()
+        };
+        for (block_index, block) in archive.blocks.iter().enumerate() {
+            let start = archive.stream_map.block_first_file_index[block_index];
+            let block_selected = &selected[start..start + block.num_unpack_sub_streams];
+            let Some(last_selected) = block_selected.iter().rposition(|&s| s) else {
+                continue;
+            };
+            totals.unpacked_size += archive.files[start..=start + last_selected]
+                .iter()
+                .filter(|file| file.has_stream)
+                .map(|file| file.size)
+                .sum::<u64>();
+            let first_pack_stream = archive.stream_map.block_first_pack_stream_index[block_index];
+            totals.packed_size += archive.pack_sizes
+                [first_pack_stream..first_pack_stream + block.packed_streams.len()]
+                .iter()
+                .sum::<u64>();
+        }
+        totals
+    }
+}
+
+/// An event reported to a [`ProgressObserver`].
+#[derive(Debug, Clone, Copy)]
+pub enum ProgressEvent<'a> {
+    /// The totals of the operation are known.
+    Totals(ProgressTotals),
+    /// Processing of the block with the given index started.
+    BlockStarted(usize),
+    /// Processing of an entry started.
+    EntryStarted(&'a ArchiveEntry),
+    /// Processing of an entry finished.
+    EntryFinished(&'a ArchiveEntry),
+    /// Bytes were consumed: packed data when extracting, source data when compressing.
+    BytesIn(u64),
+    /// Bytes were produced: decoded data when extracting, packed data when compressing.
+    BytesOut(u64),
+}
+
+/// Receives progress events from [`ArchiveReader`](crate::ArchiveReader),
+/// [`ArchiveWriter`](crate::ArchiveWriter) and the compression and decompression helpers.
+///
+/// Any `Fn(ProgressEvent)` closure can be used as an observer:
+///
+/// ```rust
+/// use async_sevenz::{ProgressEvent, ProgressObserver};
+///
+/// fn observer() -> impl ProgressObserver {
+///     |event: ProgressEvent<'_>| {
+///         if let ProgressEvent::EntryStarted(entry) = event {
+///             println!("processing {}", entry.name());
+///         }
+///     }
+/// }
+/// ```
+pub trait ProgressObserver: Send + Sync {
+    /// Called for every progress event.
+    fn on_progress(&self, event: ProgressEvent<'_>);
+}
+
+impl<F: Fn(ProgressEvent<'_>) + Send + Sync> ProgressObserver for F {
+    fn on_progress(&self, event: ProgressEvent<'_>) {
+        self(event)
+    }
+}
+
+pub(crate) type SharedProgress = Option<Arc<dyn ProgressObserver>>;
+
+#[inline]
+pub(crate) fn report(progress: &SharedProgress, event: ProgressEvent<'_>) {
+    if let Some(progress) = progress {
+        progress.on_progress(event);
+    }
+}
+
+/// Reports every successful read of the inner reader as a byte count event.
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    progress: SharedProgress,
+    event: fn(u64) -> ProgressEvent<'static>,
+}
+
+impl<R> ProgressReader<R> {
+    pub(crate) fn bytes_in(inner: R, progress: SharedProgress) -> Self {
+        Self {
+            inner,
+            progress,
+            event: ProgressEvent::BytesIn,
+        }
+    }
+
+    pub(crate) fn bytes_out(inner: R, progress: SharedProgress) -> Self {
+        Self {
+            inner,
+            progress,
+            event: ProgressEvent::BytesOut,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = &poll {
+            if *n > 0 {
+                report(&self.progress, (self.event)(*n as u64));
+            }
+        }
+        poll
+    }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for ProgressReader<R> {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_seek(cx, pos)
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    future::Future,
+    io::{self, SeekFrom},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use futures_lite::io::{AsyncRead, AsyncSeek};
+
+use crate::{ArchiveReader, Error, Password, ReaderOptions};
+
+/// Size of the ranges fetched from a [`ReadAt`] source.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// A source that reads bytes at a given offset, without a cursor.
+///
+/// Implement this for HTTP-range or object-store clients to back an [`ArchiveReader`] with them,
+/// see [`ArchiveReader::from_read_at`]. Reads do not depend on each other, so pack streams of a
+/// block are fetched concurrently.
+///
+/// Implementations for in-memory data and shared files are provided.
+pub trait ReadAt: Send + Sync {
+    /// Reads up to `len` bytes starting at `offset`.
+    ///
+    /// Fewer bytes may only be returned if the end of the source is reached.
+    fn read_at(&self, offset: u64, len: usize) -> impl Future<Output = io::Result<Vec<u8>>> + Send;
+
+    /// Returns the total size of the source in bytes.
+    fn size(&self) -> impl Future<Output = io::Result<u64>> + Send;
+}
+
+impl ReadAt for Vec<u8> {
+    async fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.len());
+        let end = start.saturating_add(len).min(self.len());
+        Ok(self[start..end].to_vec())
+    }
+
+    async fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+#[cfg(all(any(unix, windows), not(target_arch = "wasm32")))]
+impl ReadAt for Arc<std::fs::File> {
+    async fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let file = Arc::clone(self);
+        blocking::unblock(move || {
+            let mut buf = vec![0; len];
+            let mut filled = 0;
+            while filled < len {
+                let pos = offset + filled as u64;
+                #[cfg(unix)]
+                let read = std::os::unix::fs::FileExt::read_at(&*file, &mut buf[filled..], pos);
+                #[cfg(windows)]
+                let read =
+                    std::os::windows::fs::FileExt::seek_read(&*file, &mut buf[filled..], pos);
+                match read {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            buf.truncate(filled);
+            Ok(buf)
+        })
+        .await
+    }
+
+    async fn size(&self) -> io::Result<u64> {
+        let file = Arc::clone(self);
+        blocking::unblock(move || Ok(file.metadata()?.len())).await
+    }
+}
+
+type Fetch = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send>>;
+
+/// Opens an independent reader of `len` bytes at `offset` of an archive source.
+pub(crate) type OpenRange =
+    Arc<dyn Fn(u64, u64) -> Box<dyn AsyncRead + Unpin + Send> + Send + Sync>;
+
+/// Reads the bytes up to `end` of a [`ReadAt`] source in ranges of up to 1 MiB, with a cursor
+/// of its own.
+struct RangeReader<T> {
+    source: Arc<T>,
+    pos: u64,
+    end: u64,
+    fetched_offset: u64,
+    fetched: Vec<u8>,
+    pending: Option<(u64, Fetch)>,
+}
+
+impl<T: ReadAt + 'static> RangeReader<T> {
+    fn new(source: Arc<T>, pos: u64, end: u64) -> Self {
+        Self {
+            source,
+            pos,
+            end,
+            fetched_offset: 0,
+            fetched: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Copies fetched data at the current position into `buf`, returns `None` if there is none.
+    fn read_fetched(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let start = self.pos.checked_sub(self.fetched_offset)?;
+        let start = usize::try_from(start)
+            .ok()
+            .filter(|&start| start < self.fetched.len())?;
+        let size = buf.len().min(self.fetched.len() - start);
+        buf[..size].copy_from_slice(&self.fetched[start..start + size]);
+        self.pos += size as u64;
+        Some(size)
+    }
+}
+
+impl<T: ReadAt + 'static> AsyncRead for RangeReader<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.pos >= this.end || buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let remaining = usize::try_from(this.end - this.pos).unwrap_or(usize::MAX);
+        let bound = remaining.min(buf.len());
+        let buf = &mut buf[..bound];
+        if let Some(size) = this.read_fetched(buf) {
+            return Poll::Ready(Ok(size));
+        }
+        let pos = this.pos;
+        // A fetch for another position is left over from before a seek.
+        if this
+            .pending
+            .as_ref()
+            .is_none_or(|(offset, _)| *offset != pos)
+        {
+            let source = Arc::clone(&this.source);
+            let len = CHUNK_SIZE.min(remaining);
+            this.pending = Some((pos, Box::pin(async move { source.read_at(pos, len).await })));
+        }
+        let (_, fetch) = this.pending.as_mut().expect("fetch was started");
+        let result = ready!(fetch.as_mut().poll(cx));
+        this.pending = None;
+        this.fetched = result?;
+        this.fetched_offset = pos;
+        Poll::Ready(Ok(this.read_fetched(buf).unwrap_or_default()))
+    }
+}
+
+/// Reads a [`ReadAt`] source through the `AsyncRead` and `AsyncSeek` traits.
+///
+/// Data is fetched in ranges of 1 MiB. The pack streams of a block opened with
+/// [`ArchiveReader::from_read_at`] do not go through this reader: every pack stream gets an
+/// independent cursor over the source, so their fetches neither wait for each other nor seek.
+pub struct ReadAtReader<T> {
+    inner: RangeReader<T>,
+    size: u64,
+}
+
+impl<T: ReadAt + 'static> ReadAtReader<T> {
+    /// Creates a reader starting at offset `0` of `source`.
+    pub async fn new(source: T) -> io::Result<Self> {
+        let size = source.size().await?;
+        Ok(Self {
+            inner: RangeReader::new(Arc::new(source), 0, size),
+            size,
+        })
+    }
+
+    /// Returns the source of this reader.
+    pub fn get_ref(&self) -> &T {
+        &self.inner.source
+    }
+
+    /// Returns a function that opens independent readers of ranges of the source.
+    pub(crate) fn range_opener(&self) -> OpenRange {
+        let source = Arc::clone(&self.inner.source);
+        Arc::new(move |offset, len| {
+            Box::new(RangeReader::new(
+                Arc::clone(&source),
+                offset,
+                offset.saturating_add(len),
+            ))
+        })
+    }
+}
+
+impl<T: ReadAt + 'static> AsyncRead for ReadAtReader<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: ReadAt + 'static> AsyncSeek for ReadAtReader<T> {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.inner.pos.checked_add_signed(delta),
+        };
+        let Some(pos) = pos else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )));
+        };
+        self.inner.pos = pos;
+        Poll::Ready(Ok(pos))
+    }
+}
+
+impl<T: ReadAt + 'static> ArchiveReader<ReadAtReader<T>> {
+    /// Opens a 7z archive from a positional [`ReadAt`] source, e.g. an HTTP-range client.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::sync::Arc;
+    ///
+    /// use async_sevenz::{ArchiveReader, Password};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let file = Arc::new(std::fs::File::open("example.7z")?);
+    ///     let mut reader = ArchiveReader::from_read_at(file, Password::empty()).await?;
+    ///     let data = reader.read_file("example.txt").await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn from_read_at(source: T, password: Password) -> Result<Self, Error> {
+        Self::from_read_at_with_options(source, ReaderOptions::default().with_password(password))
+            .await
+    }
+
+    /// Opens a 7z archive from a positional [`ReadAt`] source with the given `options`, which
+    /// already apply while the header is read.
+    pub async fn from_read_at_with_options(
+        source: T,
+        options: ReaderOptions,
+    ) -> Result<Self, Error> {
+        let source = ReadAtReader::new(source).await?;
+        let open_range = source.range_opener();
+        let mut reader = Self::with_options(source, options).await?;
+        reader.open_range = Some(open_range);
+        Ok(reader)
+    }
+}
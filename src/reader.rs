@@ -1,3 +1,10 @@
+mod entries;
+mod options;
+mod password;
+#[cfg(all(feature = "aes256", feature = "compress"))]
+mod reencrypt;
+mod tree;
+
 #[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 use std::{
@@ -6,7 +13,10 @@ use std::{
     io,
     num::NonZeroUsize,
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
     task::{Context, Poll},
 };
 
@@ -14,17 +24,29 @@ use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, Cursor,
 
 use async_fs as afs;
 use crc32fast::Hasher;
-use lzma_rust2::filter::bcj2::Bcj2Reader;
 
+pub use self::options::ReaderOptions;
 use crate::{
     Password,
     archive::*,
     bitset::BitSet,
     block::*,
+    cancellation::{CancellableReader, CancellationToken, check_cancelled},
+    codec::bcj2::Bcj2Decoder,
     decoder::{AsyncStdRead, add_decoder},
-    error::Error,
+    encryption::{EncryptionInfo, KeyCache, PasswordProvider, PasswordSource},
+    error::{Error, ErrorContext},
+    progress::{
+        ProgressEvent, ProgressObserver, ProgressReader, ProgressTotals, SharedProgress, report,
+    },
+    read_at::OpenRange,
 };
 
+pub use self::entries::{Entries, EntryReader};
+use self::password::{confirm_verified_password, retry_block_password, verify_block_password};
+use self::tree::ArchiveTree;
+pub use self::tree::TreeNode;
+
 const MAX_MEM_LIMIT_KB: usize = usize::MAX / 1024;
 
 pub struct BoundedReader<R: AsyncRead + Unpin> {
@@ -62,13 +84,13 @@ impl<R: AsyncRead + Unpin> AsyncRead for BoundedReader<R> {
 /// A special reader that shares it's inner reader with other instances and
 /// needs to re-seek every read operation.
 #[derive(Debug)]
-pub(crate) struct SharedBoundedReader<'a, R> {
-    inner: Arc<Mutex<&'a mut R>>,
+pub(crate) struct SharedBoundedReader<R> {
+    inner: Arc<Mutex<R>>,
     cur: u64,
     bounds: (u64, u64),
 }
 
-impl<'a, R> Clone for SharedBoundedReader<'a, R> {
+impl<R> Clone for SharedBoundedReader<R> {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
@@ -78,7 +100,7 @@ impl<'a, R> Clone for SharedBoundedReader<'a, R> {
     }
 }
 
-impl<'a, R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SharedBoundedReader<'a, R> {
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SharedBoundedReader<R> {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -108,8 +130,8 @@ impl<'a, R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SharedBoundedReader<'a,
     }
 }
 
-impl<'a, R: AsyncRead + AsyncSeek + Unpin> SharedBoundedReader<'a, R> {
-    fn new(inner: Arc<Mutex<&'a mut R>>, bounds: (u64, u64)) -> Self {
+impl<R: AsyncRead + AsyncSeek + Unpin> SharedBoundedReader<R> {
+    fn new(inner: Arc<Mutex<R>>, bounds: (u64, u64)) -> Self {
         Self {
             inner,
             cur: bounds.0,
@@ -123,6 +145,7 @@ struct Crc32VerifyingReader<R> {
     crc_digest: Hasher,
     expected_value: u64,
     remaining: i64,
+    verified: Option<Arc<AtomicBool>>,
 }
 
 impl<R> Crc32VerifyingReader<R> {
@@ -132,8 +155,15 @@ impl<R> Crc32VerifyingReader<R> {
             crc_digest: Hasher::new(),
             expected_value,
             remaining: remaining as i64,
+            verified: None,
         }
     }
+
+    /// Sets `verified` once the whole data was read and matched the CRC.
+    fn with_verified_flag(mut self, verified: Option<Arc<AtomicBool>>) -> Self {
+        self.verified = verified;
+        self
+    }
 }
 
 // synchronous Read impl removed to prefer async pipeline
@@ -160,6 +190,9 @@ impl<R: AsyncRead + Unpin> AsyncRead for Crc32VerifyingReader<R> {
                         Error::ChecksumVerificationFailed,
                     )));
                 }
+                if let Some(verified) = &self.verified {
+                    verified.store(true, Ordering::Relaxed);
+                }
             }
         }
         poll
@@ -178,7 +211,7 @@ impl Archive {
             .await
             .map_err(|e| Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
         let mut cursor = Cursor::new(data);
-        Self::read(&mut cursor, &Password::empty()).await
+        Self::read(&mut cursor, &mut PasswordSource::fixed(Password::empty())).await
     }
 
     /// Opens a 7z archive asynchronously from a filesystem path using the given password.
@@ -193,14 +226,14 @@ impl Archive {
             .await
             .map_err(|e| Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
         let mut cursor = Cursor::new(data);
-        Self::read(&mut cursor, password).await
+        Self::read(&mut cursor, &mut PasswordSource::fixed(password.clone())).await
     }
 
     /// Read 7z file archive info use the specified `reader`.
     ///
     /// # Parameters
     /// - `reader`   - the reader of the 7z filr archive
-    /// - `password` - archive password encoded in utf16 little endian
+    /// - `password` - archive password, asked from its provider if the header is encrypted
     ///
     /// # Example
     ///
@@ -219,8 +252,71 @@ impl Archive {
     /// ```
     pub(crate) async fn read<R: AsyncRead + AsyncSeek + Unpin + Send>(
         reader: &mut R,
-        password: &Password,
+        password: &mut PasswordSource,
     ) -> Result<Archive, Error> {
+        let (reader_len, start_header) = Self::read_signature_header(reader).await?;
+        match start_header {
+            Some(start_header) => Self::init_archive(reader, start_header, password, true, 1).await,
+            None => Self::try_to_locale_end_header(reader, reader_len, password, 1).await,
+        }
+    }
+
+    /// Opens a 7z archive from a filesystem path and returns its encryption without needing a
+    /// password.
+    ///
+    /// Only the unencrypted parts of the header are read, so nothing is decrypted. If the header
+    /// is encrypted, the encrypted blocks are unknown.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use async_sevenz::Archive;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let info = Archive::inspect_encryption("upload.7z").await?;
+    ///     if info.is_encrypted() {
+    ///         println!("encrypted, key derivation cost 2^{:?}", info.max_num_cycles_power());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn inspect_encryption(path: impl AsRef<Path>) -> Result<EncryptionInfo, Error> {
+        let data = afs::read(path.as_ref())
+            .await
+            .map_err(|e| Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
+        Self::read_encryption_info(&mut Cursor::new(data)).await
+    }
+
+    /// Returns the encryption of a 7z archive in memory without needing a password.
+    ///
+    /// See [`inspect_encryption`](Self::inspect_encryption) for details.
+    pub async fn inspect_encryption_from_bytes(data: &[u8]) -> Result<EncryptionInfo, Error> {
+        Self::read_encryption_info(&mut Cursor::new(data)).await
+    }
+
+    pub(crate) async fn read_encryption_info<R: AsyncRead + AsyncSeek + Unpin + Send>(
+        reader: &mut R,
+    ) -> Result<EncryptionInfo, Error> {
+        let (_, start_header) = Self::read_signature_header(reader).await?;
+        if let Some(start_header) = start_header {
+            let header = Self::read_next_header(reader, &start_header, true).await?;
+            if let Some(num_cycles_power) = Self::header_encryption(&header).await? {
+                return Ok(EncryptionInfo {
+                    header_num_cycles_power: Some(num_cycles_power),
+                    blocks: None,
+                });
+            }
+        }
+        let mut password = PasswordSource::fixed(Password::empty());
+        Ok(Self::read(reader, &mut password).await?.encryption_info())
+    }
+
+    /// Reads the signature header and returns the length of the archive and the start header,
+    /// `None` if the start header was not written.
+    async fn read_signature_header<R: AsyncRead + AsyncSeek + Unpin>(
+        reader: &mut R,
+    ) -> Result<(u64, Option<StartHeader>), Error> {
         let reader_len = AsyncSeekExt::seek(reader, SeekFrom::End(0)).await?;
         AsyncSeekExt::seek(reader, SeekFrom::Start(0)).await?;
 
@@ -255,12 +351,12 @@ impl Archive {
         } else {
             true
         };
-        if header_valid {
-            let start_header = Self::read_start_header(reader, start_header_crc).await?;
-            Self::init_archive(reader, start_header, password, true, 1).await
+        let start_header = if header_valid {
+            Some(Self::read_start_header(reader, start_header_crc).await?)
         } else {
-            Self::try_to_locale_end_header(reader, reader_len, password, 1).await
-        }
+            None
+        };
+        Ok((reader_len, start_header))
     }
 
     async fn read_start_header<R: AsyncRead + Unpin>(
@@ -350,7 +446,7 @@ impl Archive {
     async fn try_to_locale_end_header<R: AsyncRead + AsyncSeek + Unpin + Send>(
         reader: &mut R,
         reader_len: u64,
-        password: &Password,
+        password: &mut PasswordSource,
         thread_count: u32,
     ) -> Result<Self, Error> {
         let search_limit = 1024 * 1024;
@@ -394,10 +490,79 @@ impl Archive {
     async fn init_archive<R: AsyncRead + AsyncSeek + Unpin + Send>(
         reader: &mut R,
         start_header: StartHeader,
-        password: &Password,
+        password: &mut PasswordSource,
         verify_crc: bool,
         thread_count: u32,
     ) -> Result<Self, Error> {
+        let header_offset = SIGNATURE_HEADER_SIZE + start_header.next_header_offset;
+        let mut buf = Self::read_next_header(reader, &start_header, verify_crc)
+            .await
+            .map_err(|e| e.with_offset(header_offset))?;
+
+        let mut archive = Archive::default();
+        let header_num_cycles_power = Self::header_encryption(&buf)
+            .await
+            .map_err(|e| e.with_offset(header_offset))?;
+        if buf.first() == Some(&K_ENCODED_HEADER) {
+            buf = loop {
+                let result = if header_num_cycles_power.is_some() && password.current().is_empty() {
+                    Err(Error::PasswordRequired)
+                } else {
+                    Self::decode_encoded_header(
+                        &buf[1..],
+                        reader,
+                        password.current(),
+                        password.keys(),
+                        thread_count,
+                    )
+                    .await
+                };
+                match result {
+                    Ok(header) => break header,
+                    Err(e) => password.retry(e)?,
+                }
+            };
+            if header_num_cycles_power.is_some() {
+                password.confirm();
+            }
+        }
+        let nid = buf.first().copied().unwrap_or(0);
+        if nid == K_HEADER {
+            let mut header = Cursor::new(&buf[1..]);
+            Self::read_header(&mut header, &mut archive)
+                .await
+                .map_err(|e| e.with_offset(header_offset))?;
+        } else {
+            return Err(
+                Error::other("Broken or unsupported archive: no Header").with_offset(header_offset)
+            );
+        }
+
+        archive.is_solid = archive
+            .blocks
+            .iter()
+            .any(|block| block.num_unpack_sub_streams > 1);
+        archive.header_num_cycles_power = header_num_cycles_power;
+
+        Ok(archive)
+    }
+
+    /// Returns the key-derivation cost of the encryption of an encoded header, `None` if the
+    /// header is not encrypted.
+    async fn header_encryption(header: &[u8]) -> Result<Option<u8>, Error> {
+        if header.first() != Some(&K_ENCODED_HEADER) {
+            return Ok(None);
+        }
+        let mut archive = Archive::default();
+        Self::read_streams_info(&mut Cursor::new(&header[1..]), &mut archive).await?;
+        Ok(archive.blocks.first().and_then(Block::aes_num_cycles_power))
+    }
+
+    async fn read_next_header<R: AsyncRead + AsyncSeek + Unpin>(
+        reader: &mut R,
+        start_header: &StartHeader,
+        verify_crc: bool,
+    ) -> Result<Vec<u8>, Error> {
         if start_header.next_header_size > usize::MAX as u64 {
             return Err(Error::other(format!(
                 "Cannot handle next_header_size {}",
@@ -418,40 +583,31 @@ impl Archive {
         if verify_crc && crc32fast::hash(&buf) as u64 != start_header.next_header_crc {
             return Err(Error::NextHeaderCrcMismatch);
         }
+        Ok(buf)
+    }
 
-        let mut archive = Archive::default();
-        let nid = buf.first().copied().unwrap_or(0);
-        if nid == K_ENCODED_HEADER {
-            let mut cursor = Cursor::new(&buf[1..]);
-            let (mut out_reader, buf_size) = Self::read_encoded_header(
-                &mut cursor,
-                reader,
-                &mut archive,
-                password,
-                thread_count,
-            )
-            .await?;
-            buf.clear();
-            buf.resize(buf_size, 0);
-            AsyncReadExt::read_exact(&mut out_reader, &mut buf)
-                .await
-                .map_err(|e| Error::bad_password(e, !password.is_empty()))?;
-            archive = Archive::default();
-        }
-        let nid = buf.first().copied().unwrap_or(0);
-        if nid == K_HEADER {
-            let mut header = Cursor::new(&buf[1..]);
-            Self::read_header(&mut header, &mut archive).await?;
-        } else {
-            return Err(Error::other("Broken or unsupported archive: no Header"));
-        }
-
-        archive.is_solid = archive
-            .blocks
-            .iter()
-            .any(|block| block.num_unpack_sub_streams > 1);
-
-        Ok(archive)
+    async fn decode_encoded_header<R: AsyncRead + AsyncSeek + Unpin + Send>(
+        encoded: &[u8],
+        reader: &mut R,
+        password: &Password,
+        keys: &KeyCache,
+        thread_count: u32,
+    ) -> Result<Vec<u8>, Error> {
+        let mut cursor = Cursor::new(encoded);
+        let (mut out_reader, buf_size) = Self::read_encoded_header(
+            &mut cursor,
+            reader,
+            &mut Archive::default(),
+            password,
+            keys,
+            thread_count,
+        )
+        .await?;
+        let mut buf = vec![0; buf_size];
+        AsyncReadExt::read_exact(&mut out_reader, &mut buf)
+            .await
+            .map_err(|e| Error::bad_password(e, !password.is_empty()))?;
+        Ok(buf)
     }
 
     async fn read_encoded_header<'r, RI: 'r + AsyncRead + AsyncSeek + Unpin + Send>(
@@ -459,6 +615,7 @@ impl Archive {
         reader: &'r mut RI,
         archive: &mut Archive,
         password: &Password,
+        keys: &KeyCache,
         thread_count: u32,
     ) -> Result<(Box<dyn AsyncRead + Unpin + Send + 'r>, usize), Error> {
         Self::read_streams_info(header, archive).await?;
@@ -466,40 +623,15 @@ impl Archive {
             .blocks
             .first()
             .ok_or(Error::other("no blocks, can't read encoded header"))?;
-        let first_pack_stream_index = 0;
         let block_offset = SIGNATURE_HEADER_SIZE + archive.pack_pos;
         if archive.pack_sizes.is_empty() {
             return Err(Error::other("no packed streams, can't read encoded header"));
         }
 
-        AsyncSeekExt::seek(reader, SeekFrom::Start(block_offset)).await?;
-        let coder_len = block.coders.len();
         let unpack_size = block.get_unpack_size() as usize;
-        let pack_size = archive.pack_sizes[first_pack_stream_index] as usize;
-        let mut decoder: Box<dyn AsyncRead + Unpin + Send> =
-            Box::new(BoundedReader::new(reader, pack_size));
-        let mut decoder = if coder_len > 0 {
-            for (index, coder) in block.ordered_coder_iter() {
-                if coder.num_in_streams != 1 || coder.num_out_streams != 1 {
-                    return Err(Error::other(
-                        "Multi input/output stream coders are not yet supported",
-                    ));
-                }
-                let next = add_decoder(
-                    decoder,
-                    block.get_unpack_size_at_index(index) as usize,
-                    coder,
-                    password,
-                    MAX_MEM_LIMIT_KB,
-                    thread_count,
-                )
-                .await?;
-                decoder = Box::new(next);
-            }
-            decoder
-        } else {
-            decoder
-        };
+        let packed =
+            open_pack_streams(reader, None, block, &archive.pack_sizes, block_offset).await?;
+        let mut decoder = decode_coders(block, packed, password, keys, thread_count).await?;
         if block.has_crc {
             decoder = Box::new(Crc32VerifyingReader::new(decoder, unpack_size, block.crc));
         }
@@ -1172,6 +1304,208 @@ impl Archive {
     }
 }
 
+/// Opens readers for the pack streams of `block`, which are stored one after another starting
+/// at `offset`.
+///
+/// With `open_range`, every pack stream gets an independent reader of the source. Otherwise a
+/// single pack stream reads `source` directly, and multiple pack streams share it and seek to
+/// their own position before every read.
+async fn open_pack_streams<'r, S: AsyncRead + AsyncSeek + Unpin + Send + 'r>(
+    mut source: S,
+    open_range: Option<&OpenRange>,
+    block: &Block,
+    pack_sizes: &[u64],
+    offset: u64,
+) -> Result<Vec<Option<Box<dyn AsyncRead + Unpin + Send + 'r>>>, Error> {
+    let count = block.packed_streams.len();
+    if pack_sizes.len() < count {
+        return Err(Error::other("Too few pack streams for block"));
+    }
+    if let Some(open_range) = open_range {
+        let mut pack_pos = offset;
+        let mut packed = Vec::with_capacity(count);
+        for &pack_size in &pack_sizes[..count] {
+            let reader: Box<dyn AsyncRead + Unpin + Send + 'r> = open_range(pack_pos, pack_size);
+            packed.push(Some(reader));
+            pack_pos += pack_size;
+        }
+        return Ok(packed);
+    }
+    if count == 1 {
+        AsyncSeekExt::seek(&mut source, SeekFrom::Start(offset)).await?;
+        let reader = BoundedReader::new(source, pack_sizes[0] as usize);
+        return Ok(vec![Some(Box::new(reader))]);
+    }
+    let shared_source = Arc::new(Mutex::new(source));
+    let mut pack_pos = offset;
+    let mut packed = Vec::with_capacity(count);
+    for &pack_size in &pack_sizes[..count] {
+        let reader =
+            SharedBoundedReader::new(Arc::clone(&shared_source), (pack_pos, pack_pos + pack_size));
+        packed.push(Some(
+            Box::new(reader) as Box<dyn AsyncRead + Unpin + Send + 'r>
+        ));
+        pack_pos += pack_size;
+    }
+    Ok(packed)
+}
+
+/// Reports the data read through the readers opened by `open_range` as consumed bytes.
+fn open_range_with_progress(open_range: OpenRange, progress: &SharedProgress) -> OpenRange {
+    let progress = progress.clone();
+    Arc::new(move |offset, len| {
+        Box::new(ProgressReader::bytes_in(
+            open_range(offset, len),
+            progress.clone(),
+        ))
+    })
+}
+
+/// Connects the coders of `block` along its bind pairs and returns the reader of the block's
+/// unpacked data.
+///
+/// `packed` holds the readers of the pack streams in the order of `block.packed_streams`.
+/// Every coder is fed by pack streams or by the outputs of other coders, so the coders form a
+/// tree below the coder whose output is not bound. The tree is built bottom-up.
+async fn decode_coders<'r>(
+    block: &Block,
+    mut packed: Vec<Option<Box<dyn AsyncRead + Unpin + Send + 'r>>>,
+    password: &Password,
+    keys: &KeyCache,
+    thread_count: u32,
+) -> Result<Box<dyn AsyncRead + Unpin + Send + 'r>, Error> {
+    const MAX_CODER_COUNT: usize = 32;
+    let coder_count = block.coders.len();
+    if coder_count > MAX_CODER_COUNT {
+        return Err(Error::unsupported(format!(
+            "Too many coders: {coder_count}"
+        )));
+    }
+    if coder_count == 0 {
+        return packed
+            .first_mut()
+            .and_then(Option::take)
+            .ok_or_else(|| Error::other("Block without coders and pack streams"));
+    }
+    // With a single output per coder, output stream indices are coder indices.
+    if block.coders.iter().any(|coder| coder.num_out_streams != 1) {
+        return Err(Error::unsupported(
+            "Coders with multiple output streams are not supported",
+        ));
+    }
+    let mut first_in_stream = Vec::with_capacity(coder_count);
+    let mut in_streams = 0;
+    for coder in &block.coders {
+        first_in_stream.push(in_streams);
+        in_streams += coder.num_in_streams;
+    }
+    let in_stream_range = |coder_index: usize| {
+        let first = first_in_stream[coder_index];
+        first..first + block.coders[coder_index].num_in_streams
+    };
+
+    let main_coder_index = (0..coder_count)
+        .find(|&index| block.find_bind_pair_for_out_stream(index as u64).is_none())
+        .ok_or_else(|| Error::other("Block has no unbound output stream"))?;
+
+    // Order the coders so that every coder comes after the coders feeding it.
+    let mut order = Vec::with_capacity(coder_count);
+    let mut visited = [false; MAX_CODER_COUNT];
+    let mut pending = vec![main_coder_index];
+    while let Some(coder_index) = pending.pop() {
+        if std::mem::replace(&mut visited[coder_index], true) {
+            return Err(Error::other("Coders of block form a cycle"));
+        }
+        order.push(coder_index);
+        for in_index in in_stream_range(coder_index) {
+            if let Some(bp) = block.find_bind_pair_for_in_stream(in_index) {
+                let out_index = bp.out_index as usize;
+                if out_index >= coder_count {
+                    return Err(Error::other(format!(
+                        "Bind pair refers to missing output stream {out_index}"
+                    )));
+                }
+                pending.push(out_index);
+            }
+        }
+    }
+
+    let mut outputs: Vec<Option<Box<dyn AsyncRead + Unpin + Send + 'r>>> =
+        (0..coder_count).map(|_| None).collect();
+    for &coder_index in order.iter().rev() {
+        let coder = &block.coders[coder_index];
+        let mut inputs = Vec::with_capacity(coder.num_in_streams as usize);
+        for in_index in in_stream_range(coder_index) {
+            let input = match block.find_bind_pair_for_in_stream(in_index) {
+                Some(bp) => outputs[bp.out_index as usize].take(),
+                None => block
+                    .packed_streams
+                    .iter()
+                    .position(|&packed_index| packed_index == in_index)
+                    .and_then(|index| packed.get_mut(index)?.take()),
+            };
+            inputs.push(input.ok_or_else(|| {
+                Error::other(format!("Input stream {in_index} of block is not connected"))
+            })?);
+        }
+        let unpack_size = block.get_unpack_size_at_index(coder_index);
+        let output: Box<dyn AsyncRead + Unpin + Send + 'r> =
+            if coder.encoder_method_id() == EncoderMethod::ID_BCJ2 {
+                let Ok(inputs) = <[_; 4]>::try_from(inputs) else {
+                    return Err(Error::other("BCJ2 needs 4 input streams"));
+                };
+                Box::new(Bcj2Decoder::new(inputs, unpack_size))
+            } else if let [_] = inputs.as_slice() {
+                Box::new(
+                    add_decoder(
+                        inputs.pop().unwrap(),
+                        unpack_size as usize,
+                        coder,
+                        password,
+                        keys,
+                        MAX_MEM_LIMIT_KB,
+                        thread_count,
+                    )
+                    .await?,
+                )
+            } else {
+                let method = coder.method().map_or_else(
+                    || format!("{:?}", coder.encoder_method_id()),
+                    |m| m.name().to_string(),
+                );
+                return Err(Error::unsupported(format!(
+                    "{method} with {} input streams",
+                    inputs.len()
+                )));
+            };
+        outputs[coder_index] = Some(output);
+    }
+    Ok(outputs[main_coder_index].take().unwrap())
+}
+
+/// Returns the location of a block for error messages.
+pub(crate) fn block_context(archive: &Archive, block_index: usize) -> ErrorContext {
+    let block = &archive.blocks[block_index];
+    let offset = archive
+        .stream_map
+        .block_first_pack_stream_index
+        .get(block_index)
+        .and_then(|&index| archive.stream_map.pack_stream_offsets.get(index))
+        .map(|offset| SIGNATURE_HEADER_SIZE + archive.pack_pos + offset);
+    ErrorContext {
+        block_index: Some(block_index),
+        methods: block
+            .ordered_coder_iter()
+            .map(|(_, coder)| match coder.method() {
+                Some(method) => method.name().to_string(),
+                None => format!("{:02x?}", coder.encoder_method_id()),
+            })
+            .collect(),
+        offset,
+        ..Default::default()
+    }
+}
+
 #[inline]
 async fn read_variable_usize<R: AsyncRead + Unpin>(
     reader: &mut R,
@@ -1248,19 +1582,19 @@ async fn read_bits<R: AsyncRead + Unpin>(header: &mut R, size: usize) -> io::Res
     Ok(bits)
 }
 
-#[derive(Copy, Clone)]
-struct IndexEntry {
-    block_index: Option<usize>,
-    file_index: usize,
-}
-
 /// Reads a 7z archive file.
 pub struct ArchiveReader<R: AsyncRead + AsyncSeek + Unpin> {
     source: R,
     archive: Archive,
-    password: Password,
+    password: PasswordSource,
     thread_count: u32,
-    index: HashMap<String, IndexEntry>,
+    index: HashMap<String, usize>,
+    tree: ArchiveTree,
+    case_insensitive: bool,
+    pub(crate) progress: SharedProgress,
+    pub(crate) cancellation: Option<CancellationToken>,
+    /// Opens independent readers of pack streams, if the source supports positional reads.
+    pub(crate) open_range: Option<OpenRange>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -1274,25 +1608,106 @@ impl ArchiveReader<Cursor<Vec<u8>>> {
         Self::new(cursor, password).await
     }
 
+    /// Opens a 7z archive file asynchronously and asks `provider` for a password only when
+    /// encrypted data is met.
+    ///
+    /// The provider is asked again after every wrong password, so candidates can be tried or
+    /// the user can be prompted without reopening the archive. The first encrypted block is
+    /// decoded once to verify a password, unless it was already verified on an encrypted
+    /// header.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use async_sevenz::{ArchiveReader, Password};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let mut reader = ArchiveReader::open_with_password_provider("example.7z", |attempt| {
+    ///         (attempt < 3).then(|| Password::from("prompted password"))
+    ///     })
+    ///     .await?;
+    ///     let data = reader.read_file("secret.txt").await?;
+    ///     println!("{} bytes", data.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn open_with_password_provider(
+        path: impl AsRef<Path>,
+        provider: impl PasswordProvider + 'static,
+    ) -> Result<Self, Error> {
+        let data = afs::read(path.as_ref())
+            .await
+            .map_err(|e| Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
+        let cursor = Cursor::new(data);
+        Self::with_options(cursor, PasswordSource::provider(provider).into()).await
+    }
+
+    /// Opens a 7z archive file asynchronously with the given `options`, which already apply
+    /// while the header is read.
+    ///
+    /// See [`ReaderOptions`] for an example.
+    pub async fn open_with_options(
+        path: impl AsRef<Path>,
+        options: ReaderOptions,
+    ) -> Result<Self, Error> {
+        let data = afs::read(path.as_ref())
+            .await
+            .map_err(|e| Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
+        Self::with_options(Cursor::new(data), options).await
+    }
+
     /// Opens a 7z archive from in-memory bytes asynchronously.
     pub async fn open_from_bytes(data: Vec<u8>, password: Password) -> Result<Self, Error> {
         let cursor = Cursor::new(data);
         Self::new(cursor, password).await
     }
+
+    /// Opens a 7z archive from in-memory bytes asynchronously and asks `provider` for a
+    /// password only when encrypted data is met.
+    ///
+    /// See [`open_with_password_provider`](Self::open_with_password_provider) for details.
+    pub async fn open_from_bytes_with_password_provider(
+        data: Vec<u8>,
+        provider: impl PasswordProvider + 'static,
+    ) -> Result<Self, Error> {
+        let cursor = Cursor::new(data);
+        Self::with_options(cursor, PasswordSource::provider(provider).into()).await
+    }
+
+    /// Opens a 7z archive from in-memory bytes asynchronously with the given `options`.
+    ///
+    /// See [`open_with_options`](Self::open_with_options) for details.
+    pub async fn open_from_bytes_with_options(
+        data: Vec<u8>,
+        options: ReaderOptions,
+    ) -> Result<Self, Error> {
+        Self::with_options(Cursor::new(data), options).await
+    }
 }
 
 impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
     /// Creates a [`ArchiveReader`] to read a 7z archive file from the given `source` reader.
     #[inline]
-    pub(crate) async fn new(mut source: R, password: Password) -> Result<Self, Error> {
-        let archive = Archive::read(&mut source, &password).await?;
+    pub(crate) async fn new(source: R, password: Password) -> Result<Self, Error> {
+        Self::with_options(source, PasswordSource::fixed(password).into()).await
+    }
+
+    pub(crate) async fn with_options(mut source: R, options: ReaderOptions) -> Result<Self, Error> {
+        let (mut password, cancellation) = options.into_parts();
+        check_cancelled(&cancellation)?;
+        let archive = Archive::read(&mut source, &mut password).await?;
 
         let mut reader = Self {
             source,
+            tree: ArchiveTree::new(&archive),
             archive,
             password,
             thread_count: 1,
             index: HashMap::default(),
+            case_insensitive: false,
+            progress: None,
+            cancellation,
+            open_range: None,
         };
 
         reader.fill_index();
@@ -1317,10 +1732,15 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
     pub fn from_archive(archive: Archive, source: R, password: Password) -> Self {
         let mut reader = Self {
             source,
+            tree: ArchiveTree::new(&archive),
             archive,
-            password,
+            password: PasswordSource::fixed(password),
             thread_count: 1,
             index: HashMap::default(),
+            case_insensitive: false,
+            progress: None,
+            cancellation: None,
+            open_range: None,
         };
 
         reader.fill_index();
@@ -1340,17 +1760,40 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
         self.thread_count = thread_count.clamp(1, 256);
     }
 
+    /// Sets an observer that is notified about the progress of extracting entries.
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Some(Arc::new(observer));
+    }
+
+    /// Sets a token to cancel extraction. Once it is cancelled, reading entries stops with
+    /// [`Error::Cancelled`].
+    ///
+    /// Running key derivations are stopped as well. Use
+    /// [`ReaderOptions::with_cancellation_token`] to cancel opening the archive.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.password
+            .keys_mut()
+            .set_cancellation_token(Some(token.clone()));
+        self.cancellation = Some(token);
+    }
+
+    /// Sets the highest accepted key-derivation cost of AES coders, which run
+    /// `2^num_cycles_power` SHA-256 rounds. Decoding a block with a higher cost fails with
+    /// [`Error::KeyDerivationTooExpensive`].
+    ///
+    /// Defaults to [`DEFAULT_MAX_NUM_CYCLES_POWER`](crate::DEFAULT_MAX_NUM_CYCLES_POWER). 7-Zip
+    /// writes archives with a cost of `19`. The header is already decoded once the reader
+    /// exists, so use [`ReaderOptions::with_max_num_cycles_power`] to limit the cost of an
+    /// encrypted header as well.
+    pub fn set_max_num_cycles_power(&mut self, max_num_cycles_power: u8) {
+        self.password
+            .keys_mut()
+            .set_max_num_cycles_power(max_num_cycles_power);
+    }
+
     fn fill_index(&mut self) {
         for (file_index, file) in self.archive.files.iter().enumerate() {
-            let block_index = self.archive.stream_map.file_block_index[file_index];
-
-            self.index.insert(
-                file.name.clone(),
-                IndexEntry {
-                    block_index,
-                    file_index,
-                },
-            );
+            self.index.insert(file.name.clone(), file_index);
         }
     }
 
@@ -1363,17 +1806,16 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
         &self.archive
     }
 
-    async fn build_decode_stack<'r>(
-        source: &'r mut R,
+    async fn build_decode_stack<'r, S: AsyncRead + AsyncSeek + Unpin + Send + 'r>(
+        source: S,
+        open_range: Option<&OpenRange>,
         archive: &Archive,
         block_index: usize,
         password: &Password,
+        keys: &KeyCache,
         thread_count: u32,
     ) -> Result<(Box<dyn AsyncRead + Unpin + Send + 'r>, usize), Error> {
         let block = &archive.blocks[block_index];
-        if block.total_input_streams > block.total_output_streams {
-            return Self::build_decode_stack2(source, archive, block_index, password, thread_count);
-        }
         let first_pack_stream_index = archive.stream_map.block_first_pack_stream_index[block_index];
         let block_offset = SIGNATURE_HEADER_SIZE
             + archive.pack_pos
@@ -1398,29 +1840,9 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
             }
         }
 
-        AsyncSeekExt::seek(source, SeekFrom::Start(block_offset)).await?;
-        let pack_size = archive.pack_sizes[first_pack_stream_index] as usize;
-
-        let mut decoder: Box<dyn AsyncRead + Unpin + Send> =
-            Box::new(BoundedReader::new(source, pack_size));
-        let block = &archive.blocks[block_index];
-        for (index, coder) in block.ordered_coder_iter() {
-            if coder.num_in_streams != 1 || coder.num_out_streams != 1 {
-                return Err(Error::unsupported(
-                    "Multi input/output stream coders are not supported",
-                ));
-            }
-            let next = add_decoder(
-                decoder,
-                block.get_unpack_size_at_index(index) as usize,
-                coder,
-                password,
-                MAX_MEM_LIMIT_KB,
-                thread_count,
-            )
-            .await?;
-            decoder = Box::new(next);
-        }
+        let pack_sizes = &archive.pack_sizes[first_pack_stream_index..];
+        let packed = open_pack_streams(source, open_range, block, pack_sizes, block_offset).await?;
+        let mut decoder = decode_coders(block, packed, password, keys, thread_count).await?;
         if has_crc {
             decoder = Box::new(Crc32VerifyingReader::new(
                 decoder,
@@ -1429,219 +1851,110 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
             ));
         }
 
-        Ok((decoder, pack_size))
-    }
-
-    fn build_decode_stack2<'r>(
-        source: &'r mut R,
-        archive: &Archive,
-        block_index: usize,
-        password: &Password,
-        thread_count: u32,
-    ) -> Result<(Box<dyn AsyncRead + Unpin + Send + 'r>, usize), Error> {
-        const MAX_CODER_COUNT: usize = 32;
-        let block = &archive.blocks[block_index];
-        if block.coders.len() > MAX_CODER_COUNT {
-            return Err(Error::unsupported(format!(
-                "Too many coders: {}",
-                block.coders.len()
-            )));
-        }
-
-        assert!(block.total_input_streams > block.total_output_streams);
-        let shared_source = Arc::new(Mutex::new(source));
-        let first_pack_stream_index = archive.stream_map.block_first_pack_stream_index[block_index];
-        let start_pos = SIGNATURE_HEADER_SIZE + archive.pack_pos;
-        let offsets = &archive.stream_map.pack_stream_offsets[first_pack_stream_index..];
-
-        let mut sources = Vec::with_capacity(block.packed_streams.len());
-
-        for (i, offset) in offsets[..block.packed_streams.len()].iter().enumerate() {
-            let pack_pos = start_pos + offset;
-            let pack_size = archive.pack_sizes[first_pack_stream_index + i];
-
-            let pack_reader = SharedBoundedReader::new(
-                Arc::clone(&shared_source),
-                (pack_pos, pack_pos + pack_size),
-            );
-
-            sources.push(pack_reader);
-        }
-
-        let mut coder_to_stream_map = [usize::MAX; MAX_CODER_COUNT];
-
-        let mut si = 0;
-        for (i, coder) in block.coders.iter().enumerate() {
-            coder_to_stream_map[i] = si;
-            si += coder.num_in_streams as usize;
-        }
-
-        let main_coder_index = {
-            let mut coder_used = [false; MAX_CODER_COUNT];
-            for bp in block.bind_pairs.iter() {
-                coder_used[bp.out_index as usize] = true;
-            }
-            let mut mci = 0;
-            for (i, used) in coder_used[..block.coders.len()].iter().enumerate() {
-                if !used {
-                    mci = i;
-                    break;
-                }
-            }
-            mci
-        };
-
-        let id = block.coders[main_coder_index].encoder_method_id();
-        if id != EncoderMethod::ID_BCJ2 {
-            return Err(Error::unsupported(format!("Unsupported method: {id:?}")));
-        }
-
-        let num_in_streams = block.coders[main_coder_index].num_in_streams as usize;
-        let mut inputs: Vec<Box<dyn AsyncRead + Unpin + Send>> = Vec::with_capacity(num_in_streams);
-        let start_i = coder_to_stream_map[main_coder_index];
-        for i in start_i..num_in_streams + start_i {
-            inputs.push(Self::get_in_stream(
-                block,
-                &sources,
-                &coder_to_stream_map,
-                password,
-                i,
-                thread_count,
-            )?);
-        }
-        let inputs_std = inputs
-            .into_iter()
-            .map(crate::util::decompress::AsyncReadSeekAsStd::new)
-            .collect::<Vec<_>>();
-        let mut decoder: Box<dyn AsyncRead + Unpin + Send> = Box::new(AsyncStdRead::new(
-            Bcj2Reader::new(inputs_std, block.get_unpack_size()),
-        ));
-        if block.has_crc {
-            decoder = Box::new(Crc32VerifyingReader::new(
-                decoder,
-                block.get_unpack_size() as usize,
-                block.crc,
-            ));
-        }
-        Ok((
-            decoder,
-            archive.pack_sizes[first_pack_stream_index] as usize,
-        ))
-    }
-
-    fn get_in_stream<'r>(
-        block: &Block,
-        sources: &[SharedBoundedReader<'r, R>],
-        coder_to_stream_map: &[usize],
-        password: &Password,
-        in_stream_index: usize,
-        thread_count: u32,
-    ) -> Result<Box<dyn AsyncRead + Unpin + Send + 'r>, Error>
-    where
-        R: 'r,
-    {
-        let index = block
-            .packed_streams
-            .iter()
-            .position(|&i| i == in_stream_index as u64);
-        if let Some(index) = index {
-            return Ok(Box::new(sources[index].clone()));
-        }
-
-        let bp = block
-            .find_bind_pair_for_in_stream(in_stream_index as u64)
-            .ok_or_else(|| {
-                Error::other(format!(
-                    "Couldn't find bind pair for stream {in_stream_index}"
-                ))
-            })?;
-        let index = bp.out_index as usize;
-
-        Self::get_in_stream2(
-            block,
-            sources,
-            coder_to_stream_map,
-            password,
-            index,
-            thread_count,
-        )
+        Ok((decoder, pack_sizes[0] as usize))
     }
 
-    fn get_in_stream2<'r>(
-        block: &Block,
-        sources: &[SharedBoundedReader<'r, R>],
-        coder_to_stream_map: &[usize],
-        password: &Password,
-        in_stream_index: usize,
-        thread_count: u32,
-    ) -> Result<Box<dyn AsyncRead + Unpin + Send + 'r>, Error>
-    where
-        R: 'r,
-    {
-        let coder = &block.coders[in_stream_index];
-        let start_index = coder_to_stream_map[in_stream_index];
-        if start_index == usize::MAX {
-            return Err(Error::other("in_stream_index out of range"));
-        }
-        let uncompressed_len = block.unpack_sizes[in_stream_index] as usize;
-        if coder.num_in_streams == 1 {
-            let input = Self::get_in_stream(
-                block,
-                sources,
-                coder_to_stream_map,
-                password,
-                start_index,
-                thread_count,
-            )?;
-
-            let decoder = async_io::block_on(add_decoder(
-                input,
-                uncompressed_len,
-                coder,
-                password,
-                MAX_MEM_LIMIT_KB,
-                thread_count,
-            ))?;
-            return Ok(Box::new(decoder));
-        }
-        Err(Error::unsupported(
-            "Multi input stream coders are not yet supported",
-        ))
+    pub(crate) async fn for_each_entries<
+        F: for<'a> FnMut(
+            &'a ArchiveEntry,
+            &'a mut (dyn AsyncRead + Unpin + Send + 'a),
+        ) -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send + 'a>>,
+    >(
+        &mut self,
+        each: F,
+    ) -> Result<(), Error> {
+        self.for_each_selected_entries(|_| true, each).await
     }
 
-    pub(crate) async fn for_each_entries<
+    /// Decodes only the entries accepted by `select` and passes them to `each`.
+    ///
+    /// Blocks without any selected entry are skipped without reading their packed data, and
+    /// decoding of a block stops after its last selected entry.
+    pub(crate) async fn for_each_selected_entries<
+        S: FnMut(&ArchiveEntry) -> bool,
         F: for<'a> FnMut(
             &'a ArchiveEntry,
             &'a mut (dyn AsyncRead + Unpin + Send + 'a),
         ) -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send + 'a>>,
     >(
         &mut self,
+        mut select: S,
         mut each: F,
     ) -> Result<(), Error> {
+        let selected: Vec<bool> = self.archive.files.iter().map(&mut select).collect();
+        report(
+            &self.progress,
+            ProgressEvent::Totals(ProgressTotals::from_selection(&self.archive, &selected)),
+        );
         let block_count = self.archive.blocks.len();
         for block_index in 0..block_count {
-            let forder_dec = BlockDecoder::new(
-                self.thread_count,
-                block_index,
-                &self.archive,
-                &self.password,
-                &mut self.source,
-            );
-            if !forder_dec
-                .for_each_entries(&mut each)
-                .await
-                .map_err(|e| e.maybe_bad_password(!self.password.is_empty()))?
-            {
-                return Ok(());
+            let start = self.archive.stream_map.block_first_file_index[block_index];
+            let count = self.archive.blocks[block_index].num_unpack_sub_streams;
+            let block_selected = &selected[start..start + count];
+            loop {
+                if block_selected.iter().any(|&s| s) {
+                    verify_block_password(
+                        &mut self.source,
+                        self.open_range.as_ref(),
+                        &self.archive,
+                        block_index,
+                        &mut self.password,
+                        self.thread_count,
+                        &self.cancellation,
+                    )
+                    .await
+                    .map_err(|e| e.with_context(block_context(&self.archive, block_index)))?;
+                }
+                let verified = Arc::new(AtomicBool::new(false));
+                let mut forder_dec = BlockDecoder::new(
+                    self.thread_count,
+                    block_index,
+                    &self.archive,
+                    self.password.current(),
+                    &mut self.source,
+                );
+                forder_dec.progress = self.progress.clone();
+                forder_dec.cancellation = self.cancellation.clone();
+                forder_dec.keys = self.password.keys().clone();
+                forder_dec.crc_verified = Some(verified.clone());
+                forder_dec.open_range = self.open_range.clone();
+                // The block decoder asks for the entries of its block in order.
+                let mut block_selected = block_selected.iter().copied();
+                let result = forder_dec
+                    .for_each_selected_entries(
+                        &mut |_| block_selected.next().unwrap_or(false),
+                        &mut each,
+                    )
+                    .await
+                    .map_err(|e| e.maybe_bad_password(!self.password.current().is_empty()));
+                let verified = verified.load(Ordering::Relaxed);
+                match result {
+                    Ok(cont) => {
+                        confirm_verified_password(&mut self.password, verified);
+                        if !cont {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                    Err(e) => retry_block_password(&mut self.password, e, verified)?,
+                }
             }
         }
         // decode empty files
-        for file_index in 0..self.archive.files.len() {
+        for (file_index, file) in self.archive.files.iter().enumerate() {
             let block_index = self.archive.stream_map.file_block_index[file_index];
             if block_index.is_none() {
-                let file = &self.archive.files[file_index];
+                if !selected[file_index] {
+                    continue;
+                }
+                check_cancelled(&self.cancellation)?;
+                report(&self.progress, ProgressEvent::EntryStarted(file));
                 let mut empty_reader = AsyncStdRead::new([0u8; 0].as_slice());
-                if !each(file, &mut empty_reader).await? {
+                let cont = each(file, &mut empty_reader)
+                    .await
+                    .map_err(|e| e.with_entry(file.name()))?;
+                report(&self.progress, ProgressEvent::EntryFinished(file));
+                if !cont {
                     return Ok(());
                 }
             }
@@ -1651,86 +1964,97 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
 
     /// Returns the data of a file with the given path inside the archive.
     ///
+    /// The path is resolved like in [`lookup`](Self::lookup) if there is no entry with exactly
+    /// the given name.
+    ///
     /// # Notice
     /// This function is very inefficient when used with solid archives, since
     /// it needs to decode all data before the actual file.
     pub async fn read_file(&mut self, name: &str) -> Result<Vec<u8>, Error> {
-        let index_entry = *self.index.get(name).ok_or(Error::FileNotFound)?;
-        let file = &self.archive.files[index_entry.file_index];
+        let file_index = self.resolve_file_index(name).ok_or(Error::FileNotFound)?;
+        let file = &self.archive.files[file_index];
 
         if !file.has_stream {
             return Ok(Vec::new());
         }
 
-        let block_index = index_entry
-            .block_index
+        let block_index = self.archive.stream_map.file_block_index[file_index]
             .ok_or_else(|| Error::other("File has no associated block"))?;
 
-        match self.archive.is_solid {
-            true => {
-                use std::sync::{Arc, Mutex};
-                let result_cell = Arc::new(Mutex::new(None));
-                let target_name = name.to_string();
+        verify_block_password(
+            &mut self.source,
+            self.open_range.as_ref(),
+            &self.archive,
+            block_index,
+            &mut self.password,
+            self.thread_count,
+            &self.cancellation,
+        )
+        .await
+        .map_err(|e| {
+            e.with_context(block_context(&self.archive, block_index))
+                .with_entry(file.name())
+        })?;
 
-                BlockDecoder::new(
-                    self.thread_count,
-                    block_index,
-                    &self.archive,
-                    &self.password,
-                    &mut self.source,
-                )
-                .for_each_entries(&mut |archive_entry, reader| {
-                    let result_cell = Arc::clone(&result_cell);
-                    let target_name = target_name.clone();
-                    Box::pin(async move {
-                        let mut data = Vec::with_capacity(archive_entry.size as usize);
-                        AsyncReadExt::read_to_end(reader, &mut data).await?;
-                        if archive_entry.name == target_name {
+        let result_cell = Arc::new(Mutex::new(None));
+        let size = file.size as usize;
+
+        loop {
+            let verified = Arc::new(AtomicBool::new(false));
+            let mut block_decoder = BlockDecoder::new(
+                self.thread_count,
+                block_index,
+                &self.archive,
+                self.password.current(),
+                &mut self.source,
+            );
+            block_decoder.progress = self.progress.clone();
+            block_decoder.cancellation = self.cancellation.clone();
+            block_decoder.keys = self.password.keys().clone();
+            block_decoder.open_range = self.open_range.clone();
+            block_decoder.crc_verified = Some(verified.clone());
+            let result = block_decoder
+                .for_each_selected_entries(
+                    &mut |entry| std::ptr::eq(entry, file),
+                    &mut |_, reader| {
+                        let result_cell = Arc::clone(&result_cell);
+                        Box::pin(async move {
+                            let mut data = Vec::with_capacity(size);
+                            AsyncReadExt::read_to_end(reader, &mut data).await?;
                             *result_cell.lock().unwrap() = Some(data);
                             Ok(false)
-                        } else {
-                            Ok(true)
-                        }
-                    })
-                })
-                .await?;
-
-                let mut guard = result_cell.lock().unwrap();
-                guard.take().ok_or(Error::FileNotFound)
-            }
-            false => {
-                let pack_index = self.archive.stream_map.block_first_pack_stream_index[block_index];
-                let pack_offset = self.archive.stream_map.pack_stream_offsets[pack_index];
-                let block_offset = SIGNATURE_HEADER_SIZE + self.archive.pack_pos + pack_offset;
-
-                AsyncSeekExt::seek(&mut self.source, SeekFrom::Start(block_offset)).await?;
-
-                let (mut block_reader, _size) = Self::build_decode_stack(
-                    &mut self.source,
-                    &self.archive,
-                    block_index,
-                    &self.password,
-                    self.thread_count,
+                        })
+                    },
                 )
-                .await?;
-
-                let mut data = Vec::with_capacity(file.size as usize);
-                let mut decoder: Box<dyn AsyncRead + Unpin + Send> =
-                    Box::new(BoundedReader::new(&mut block_reader, file.size as usize));
-
-                if file.has_crc {
-                    decoder = Box::new(Crc32VerifyingReader::new(
-                        decoder,
-                        file.size as usize,
-                        file.crc,
-                    ));
+                .await;
+            let verified = verified.load(Ordering::Relaxed);
+            match result {
+                Ok(_) => {
+                    confirm_verified_password(&mut self.password, verified);
+                    break;
+                }
+                Err(e) => {
+                    retry_block_password(&mut self.password, e, verified)?;
+                    verify_block_password(
+                        &mut self.source,
+                        self.open_range.as_ref(),
+                        &self.archive,
+                        block_index,
+                        &mut self.password,
+                        self.thread_count,
+                        &self.cancellation,
+                    )
+                    .await
+                    .map_err(|e| {
+                        e.with_context(block_context(&self.archive, block_index))
+                            .with_entry(file.name())
+                    })?;
                 }
-
-                AsyncReadExt::read_to_end(&mut decoder, &mut data).await?;
-
-                Ok(data)
             }
         }
+
+        let mut guard = result_cell.lock().unwrap();
+        guard.take().ok_or(Error::FileNotFound)
     }
 
     /// Get the compression method(s) used for a specific file in the archive.
@@ -1739,15 +2063,16 @@ impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
         file_name: &str,
         methods: &mut Vec<EncoderMethod>,
     ) -> Result<(), Error> {
-        let index_entry = self.index.get(file_name).ok_or(Error::FileNotFound)?;
-        let file = &self.archive.files[index_entry.file_index];
+        let file_index = self
+            .resolve_file_index(file_name)
+            .ok_or(Error::FileNotFound)?;
+        let file = &self.archive.files[file_index];
 
         if !file.has_stream {
             return Ok(());
         }
 
-        let block_index = index_entry
-            .block_index
+        let block_index = self.archive.stream_map.file_block_index[file_index]
             .ok_or_else(|| Error::other("File has no associated block"))?;
 
         let block = self
@@ -1778,6 +2103,12 @@ pub struct BlockDecoder<'a, R: AsyncRead + AsyncSeek + Unpin> {
     archive: &'a Archive,
     password: &'a Password,
     source: &'a mut R,
+    progress: SharedProgress,
+    cancellation: Option<CancellationToken>,
+    keys: KeyCache,
+    /// Set once an entry was read completely and matched its CRC.
+    crc_verified: Option<Arc<AtomicBool>>,
+    open_range: Option<OpenRange>,
 }
 
 impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> BlockDecoder<'a, R> {
@@ -1803,9 +2134,31 @@ impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> BlockDecoder<'a, R> {
             archive,
             password,
             source,
+            progress: None,
+            cancellation: None,
+            keys: KeyCache::default(),
+            crc_verified: None,
+            open_range: None,
         }
     }
 
+    /// Sets an observer that is notified about the progress of decoding this block.
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Some(Arc::new(observer));
+    }
+
+    /// Sets a token to cancel decoding of this block with [`Error::Cancelled`].
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.keys.set_cancellation_token(Some(token.clone()));
+        self.cancellation = Some(token);
+    }
+
+    /// Sets the highest accepted key-derivation cost of AES coders, see
+    /// [`ArchiveReader::set_max_num_cycles_power`].
+    pub fn set_max_num_cycles_power(&mut self, max_num_cycles_power: u8) {
+        self.keys.set_max_num_cycles_power(max_num_cycles_power);
+    }
+
     /// Sets the thread count to use when multi-threading is supported by the de-compression
     /// (currently only LZMA2 if encoded with MT support).
     pub fn set_thread_count(&mut self, thread_count: u32) {
@@ -1840,6 +2193,26 @@ impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> BlockDecoder<'a, R> {
     >(
         self,
         each: &mut F,
+    ) -> Result<bool, Error> {
+        self.for_each_selected_entries(&mut |_| true, each).await
+    }
+
+    /// Takes a closure to decode only the files in this block accepted by `select`.
+    ///
+    /// If no entry of this block is selected, the packed data of the block is not read at all.
+    /// Otherwise decoding stops right after the last selected entry. Entries in front of it
+    /// that are not selected still need to be decoded, but their data is discarded and they
+    /// are not passed to `each`.
+    pub async fn for_each_selected_entries<
+        S: FnMut(&ArchiveEntry) -> bool,
+        F: for<'b> FnMut(
+            &'b ArchiveEntry,
+            &'b mut (dyn AsyncRead + Unpin + Send + 'b),
+        ) -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send + 'b>>,
+    >(
+        self,
+        select: &mut S,
+        each: &mut F,
     ) -> Result<bool, Error> {
         let Self {
             thread_count,
@@ -1847,36 +2220,84 @@ impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> BlockDecoder<'a, R> {
             archive,
             password,
             source,
+            progress,
+            cancellation,
+            keys,
+            crc_verified,
+            open_range,
         } = self;
-        let (mut block_reader, _size) =
-            ArchiveReader::build_decode_stack(source, archive, block_index, password, thread_count)
-                .await?;
         let start = archive.stream_map.block_first_file_index[block_index];
         let file_count = archive.blocks[block_index].num_unpack_sub_streams;
+        let selected: Vec<bool> = archive.files[start..(file_count + start)]
+            .iter()
+            .map(&mut *select)
+            .collect();
+        let Some(last_selected) = selected.iter().rposition(|&s| s) else {
+            return Ok(true);
+        };
 
-        for file_index in start..(file_count + start) {
+        check_cancelled(&cancellation)?;
+        report(&progress, ProgressEvent::BlockStarted(block_index));
+        let mut source = ProgressReader::bytes_in(source, progress.clone());
+        let open_range =
+            open_range.map(|open_range| open_range_with_progress(open_range, &progress));
+        let (block_reader, _size) = ArchiveReader::<R>::build_decode_stack(
+            &mut source,
+            open_range.as_ref(),
+            archive,
+            block_index,
+            password,
+            &keys,
+            thread_count,
+        )
+        .await
+        .map_err(|e| {
+            // Decoders behind the AES coders read their headers while the stack is built.
+            e.maybe_bad_password(!password.is_empty())
+                .with_context(block_context(archive, block_index))
+        })?;
+        let block_reader = ProgressReader::bytes_out(block_reader, progress.clone());
+        let mut block_reader = CancellableReader::new(block_reader, cancellation.clone());
+
+        for (file_index, is_selected) in
+            (start..=(start + last_selected)).zip(selected.iter().copied())
+        {
+            check_cancelled(&cancellation)?;
             let file = &archive.files[file_index];
             if file.has_stream && file.size > 0 {
                 let mut decoder: Box<dyn AsyncRead + Unpin + Send> =
                     Box::new(BoundedReader::new(&mut block_reader, file.size as usize));
                 if file.has_crc {
-                    decoder = Box::new(Crc32VerifyingReader::new(
-                        decoder,
-                        file.size as usize,
-                        file.crc,
-                    ));
+                    decoder = Box::new(
+                        Crc32VerifyingReader::new(decoder, file.size as usize, file.crc)
+                            .with_verified_flag(crc_verified.clone()),
+                    );
                 }
-                {
-                    let cont = each(file, &mut decoder)
+                let entry_error = |e: Error| {
+                    e.maybe_bad_password(!password.is_empty())
+                        .with_context(block_context(archive, block_index))
+                        .with_entry(file.name())
+                };
+                if !is_selected {
+                    futures_lite::io::copy(&mut decoder, &mut futures_lite::io::sink())
                         .await
-                        .map_err(|e| e.maybe_bad_password(!password.is_empty()))?;
-                    if !cont {
-                        return Ok(false);
-                    }
+                        .map_err(|e| entry_error(e.into()))?;
+                    continue;
                 }
-            } else {
+                report(&progress, ProgressEvent::EntryStarted(file));
+                let cont = each(file, &mut decoder).await.map_err(entry_error)?;
+                report(&progress, ProgressEvent::EntryFinished(file));
+                if !cont {
+                    return Ok(false);
+                }
+            } else if is_selected {
+                report(&progress, ProgressEvent::EntryStarted(file));
                 let mut empty_reader = AsyncStdRead::new([0u8; 0].as_slice());
-                if !each(file, &mut empty_reader).await? {
+                let cont = each(file, &mut empty_reader)
+                    .await
+                    .map_err(|e| e.with_entry(file.name()))?;
+                report(&progress, ProgressEvent::EntryFinished(file));
+                if !cont {
                     return Ok(false);
                 }
             }
@@ -1884,3 +2305,218 @@ impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> BlockDecoder<'a, R> {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coder(id: &[u8], properties: Vec<u8>) -> Coder {
+        let mut coder = Coder::copy();
+        coder.id_size = id.len();
+        coder.decompression_method_id_mut().copy_from_slice(id);
+        coder.properties = properties;
+        coder
+    }
+
+    #[tokio::test]
+    async fn decode_coders_listed_after_their_consumer() {
+        // The delta coder comes first, but reads the output of the copy coder behind it.
+        let block = Block {
+            coders: vec![coder(EncoderMethod::ID_DELTA, vec![0]), Coder::copy()],
+            total_input_streams: 2,
+            total_output_streams: 2,
+            bind_pairs: vec![BindPair {
+                in_index: 0,
+                out_index: 1,
+            }],
+            packed_streams: vec![1],
+            unpack_sizes: vec![4, 4],
+            ..Default::default()
+        };
+        let packed: Box<dyn AsyncRead + Unpin + Send> = Box::new(Cursor::new(vec![1u8, 1, 1, 1]));
+        let mut decoder = decode_coders(
+            &block,
+            vec![Some(packed)],
+            &Password::empty(),
+            &KeyCache::default(),
+            1,
+        )
+        .await
+        .unwrap();
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn decode_coders_rejects_unconnected_input() {
+        let block = Block {
+            coders: vec![Coder::copy()],
+            total_input_streams: 1,
+            total_output_streams: 1,
+            packed_streams: vec![],
+            unpack_sizes: vec![4],
+            ..Default::default()
+        };
+        let result = decode_coders(
+            &block,
+            Vec::new(),
+            &Password::empty(),
+            &KeyCache::default(),
+            1,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn decode_bcj2_with_filtered_pack_streams() {
+        let path = "tests/resources/7za433_7zip_lzma2_bcj2.7z";
+        let data = std::fs::read(path).unwrap();
+        let mut archive = Archive::read(
+            &mut Cursor::new(&data),
+            &mut PasswordSource::fixed(Password::empty()),
+        )
+        .await
+        .unwrap();
+        let block_index = archive
+            .blocks
+            .iter()
+            .position(|block| {
+                block
+                    .coders
+                    .iter()
+                    .any(|coder| coder.encoder_method_id() == EncoderMethod::ID_BCJ2)
+            })
+            .unwrap();
+        let name = archive.files[archive.stream_map.block_first_file_index[block_index]]
+            .name
+            .clone();
+        let expected = ArchiveReader::from_archive(
+            archive.clone(),
+            Cursor::new(data.clone()),
+            Password::empty(),
+        )
+        .read_file(&name)
+        .await
+        .unwrap();
+
+        // Feed every pack stream through an extra copy coder appended to the block.
+        let first_pack_stream_index = archive.stream_map.block_first_pack_stream_index[block_index];
+        let pack_sizes = archive.pack_sizes[first_pack_stream_index..].to_vec();
+        let block = &mut archive.blocks[block_index];
+        assert!(block.packed_streams.len() > 1);
+        for (i, packed) in block.packed_streams.clone().into_iter().enumerate() {
+            block.coders.push(Coder::copy());
+            block.packed_streams[i] = block.total_input_streams as u64;
+            block.bind_pairs.push(BindPair {
+                in_index: packed,
+                out_index: block.total_output_streams as u64,
+            });
+            block.unpack_sizes.push(pack_sizes[i]);
+            block.total_input_streams += 1;
+            block.total_output_streams += 1;
+        }
+
+        let mut reader = ArchiveReader::from_archive(archive, Cursor::new(data), Password::empty());
+        assert_eq!(reader.read_file(&name).await.unwrap(), expected);
+    }
+
+    /// Extracts `target` with a progress observer and returns the totals together with the
+    /// decoded and the read bytes.
+    async fn selected_progress(path: &str, target: &str) -> (ProgressTotals, u64, u64) {
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new((None, 0, 0)));
+        let mut reader = ArchiveReader::open(path, Password::empty()).await.unwrap();
+        let observer_seen = seen.clone();
+        reader.set_progress_observer(move |event: ProgressEvent<'_>| {
+            let mut seen = observer_seen.lock().unwrap();
+            match event {
+                ProgressEvent::Totals(totals) => seen.0 = Some(totals),
+                ProgressEvent::BytesIn(n) => seen.2 += n,
+                ProgressEvent::BytesOut(n) => seen.1 += n,
+                _ => {}
+            }
+        });
+        reader
+            .for_each_selected_entries(
+                |entry| entry.name() == target,
+                |_, data| {
+                    Box::pin(async move {
+                        futures_lite::io::copy(data, &mut futures_lite::io::sink()).await?;
+                        Ok(true)
+                    })
+                },
+            )
+            .await
+            .unwrap();
+        let (totals, bytes_out, bytes_in) = *seen.lock().unwrap();
+        (totals.expect("totals reported"), bytes_out, bytes_in)
+    }
+
+    #[tokio::test]
+    async fn progress_totals_of_selected_entries() {
+        let archive = Archive::open("tests/resources/non_solid.7z").await.unwrap();
+        let size = |name: &str| {
+            archive
+                .files
+                .iter()
+                .find(|f| f.name() == name)
+                .unwrap()
+                .size
+        };
+        let (totals, bytes_out, bytes_in) =
+            selected_progress("tests/resources/non_solid.7z", "test/test2.txt").await;
+        assert_eq!(totals.entries, 1);
+        assert_eq!(totals.unpacked_size, size("test/test2.txt"));
+        assert_eq!(bytes_out, totals.unpacked_size);
+        assert!(totals.packed_size < archive.pack_sizes.iter().sum::<u64>());
+        assert!(bytes_in > 0 && bytes_in <= totals.packed_size);
+
+        // Entries in front of the selected one in a solid block are decoded too.
+        let (totals, bytes_out, _) =
+            selected_progress("tests/resources/solid.7z", "test/test2.txt").await;
+        assert_eq!(totals.entries, 1);
+        assert_eq!(
+            totals.unpacked_size,
+            size("test/test1.txt") + size("test/test2.txt")
+        );
+        assert_eq!(bytes_out, totals.unpacked_size);
+    }
+
+    #[cfg(feature = "compress")]
+    #[tokio::test]
+    async fn raw_block_keeps_undefined_crcs() {
+        let mut writer = crate::ArchiveWriter::create_in_memory().await.unwrap();
+        writer
+            .push_packed_stream(b"abcdef".as_slice(), None)
+            .await
+            .unwrap();
+        let block = Block {
+            coders: vec![Coder::copy()],
+            total_input_streams: 1,
+            total_output_streams: 1,
+            packed_streams: vec![0],
+            unpack_sizes: vec![6],
+            ..Default::default()
+        };
+        writer.push_raw_block(block, vec![3, 3], vec![Some(crc32fast::hash(b"abc")), None]);
+        let entries = ["a.txt", "b.txt"].map(|name| ArchiveEntry {
+            has_stream: true,
+            size: 3,
+            ..ArchiveEntry::new_file(name)
+        });
+        writer.push_raw_entries(entries);
+        let data = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+            .await
+            .unwrap();
+        let files = &reader.archive().files;
+        assert!(files[0].has_crc);
+        assert_eq!(files[0].crc, crc32fast::hash(b"abc") as u64);
+        assert!(!files[1].has_crc);
+        assert_eq!(reader.read_file("b.txt").await.unwrap(), b"def");
+    }
+}
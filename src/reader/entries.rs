@@ -0,0 +1,313 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use crc32fast::Hasher;
+use futures_lite::io::{AsyncRead, AsyncSeek, SeekFrom};
+
+use super::{
+    ArchiveReader, block_context, open_range_with_progress, password::verify_block_password,
+};
+use crate::{
+    Archive, ArchiveEntry, Error,
+    cancellation::{CancellableReader, CancellationToken, check_cancelled},
+    encryption::PasswordSource,
+    progress::{ProgressEvent, ProgressReader, ProgressTotals, SharedProgress, report},
+    read_at::OpenRange,
+};
+
+/// Hands out the borrowed archive source to the decode stack of one block at a time.
+struct SharedSource<'a, R>(Arc<Mutex<&'a mut R>>);
+
+impl<R> Clone for SharedSource<'_, R> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SharedSource<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut inner = self.0.lock().unwrap();
+        Pin::new(&mut **inner).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for SharedSource<'_, R> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<std::io::Result<u64>> {
+        let mut inner = self.0.lock().unwrap();
+        Pin::new(&mut **inner).poll_seek(cx, pos)
+    }
+}
+
+type BlockReader<'a> = Box<dyn AsyncRead + Unpin + Send + 'a>;
+
+/// Iterates over the entries of an archive together with their data.
+///
+/// Created by [`ArchiveReader::entries`]. Entries with data are returned block by block, in the
+/// order they are stored in their block, so every block is decoded only once. Entries without
+/// data, such as directories and empty files, follow after all blocks in the order of
+/// [`Archive::files`]. The data of an entry can be read from the returned [`EntryReader`] until
+/// [`next`](Self::next) is called again; data that was not read is skipped.
+pub struct Entries<'a, R> {
+    archive: &'a Archive,
+    password: &'a mut PasswordSource,
+    source: SharedSource<'a, R>,
+    thread_count: u32,
+    progress: SharedProgress,
+    cancellation: Option<CancellationToken>,
+    open_range: Option<OpenRange>,
+    block_index: usize,
+    index_in_block: usize,
+    empty_file_index: usize,
+    block_reader: Option<BlockReader<'a>>,
+    current: Option<usize>,
+    remaining: u64,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
+    /// Returns an iterator over all entries of the archive and their data.
+    ///
+    /// This is the async equivalent of a lending iterator: the [`EntryReader`] returned with an
+    /// entry borrows the iterator and must be dropped before the next entry is requested.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use async_sevenz::{ArchiveReader, Password};
+    /// use futures_lite::io::AsyncReadExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let mut reader = ArchiveReader::open("example.7z", Password::empty()).await?;
+    ///     let mut entries = reader.entries();
+    ///     while let Some((entry, mut data)) = entries.next().await? {
+    ///         if entry.name().ends_with(".txt") {
+    ///             let mut content = String::new();
+    ///             data.read_to_string(&mut content).await?;
+    ///             println!("{}: {content}", entry.name());
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn entries(&mut self) -> Entries<'_, R> {
+        report(
+            &self.progress,
+            ProgressEvent::Totals(ProgressTotals::from_selection(
+                &self.archive,
+                &vec![true; self.archive.files.len()],
+            )),
+        );
+        Entries {
+            archive: &self.archive,
+            password: &mut self.password,
+            source: SharedSource(Arc::new(Mutex::new(&mut self.source))),
+            thread_count: self.thread_count,
+            progress: self.progress.clone(),
+            cancellation: self.cancellation.clone(),
+            open_range: self.open_range.clone(),
+            block_index: 0,
+            index_in_block: 0,
+            empty_file_index: 0,
+            block_reader: None,
+            current: None,
+            remaining: 0,
+        }
+    }
+}
+
+impl<'a, R: AsyncRead + AsyncSeek + Unpin + Send> Entries<'a, R> {
+    /// Advances to the next entry and returns it together with a reader for its data.
+    ///
+    /// Returns `Ok(None)` once all entries were returned. Data of the previous entry that was
+    /// not read is skipped: if the previous entry was the last one of its block, the block is
+    /// not decoded any further, otherwise the remaining data is decoded and discarded.
+    pub async fn next(&mut self) -> Result<Option<(ArchiveEntry, EntryReader<'_>)>, Error> {
+        self.skip_current().await?;
+        let archive = self.archive;
+        while self.block_index < archive.blocks.len() {
+            let start = archive.stream_map.block_first_file_index[self.block_index];
+            let count = archive.blocks[self.block_index].num_unpack_sub_streams;
+            if self.index_in_block >= count {
+                self.block_reader = None;
+                self.block_index += 1;
+                self.index_in_block = 0;
+                continue;
+            }
+            check_cancelled(&self.cancellation)?;
+            let file_index = start + self.index_in_block;
+            self.index_in_block += 1;
+            let file = &archive.files[file_index];
+            if file.has_stream && file.size > 0 && self.block_reader.is_none() {
+                let block_reader = self.build_block_reader().await.map_err(|e| {
+                    e.with_context(block_context(archive, self.block_index))
+                        .with_entry(file.name())
+                })?;
+                self.block_reader = Some(block_reader);
+            }
+            return Ok(Some(self.start_entry(file_index)));
+        }
+        while self.empty_file_index < archive.files.len() {
+            let file_index = self.empty_file_index;
+            self.empty_file_index += 1;
+            if archive.stream_map.file_block_index[file_index].is_none() {
+                check_cancelled(&self.cancellation)?;
+                return Ok(Some(self.start_entry(file_index)));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn build_block_reader(&mut self) -> Result<BlockReader<'a>, Error> {
+        report(
+            &self.progress,
+            ProgressEvent::BlockStarted(self.block_index),
+        );
+        verify_block_password(
+            &mut self.source.clone(),
+            self.open_range.as_ref(),
+            self.archive,
+            self.block_index,
+            self.password,
+            self.thread_count,
+            &self.cancellation,
+        )
+        .await?;
+        let source = ProgressReader::bytes_in(self.source.clone(), self.progress.clone());
+        let open_range = (self.open_range.clone())
+            .map(|open_range| open_range_with_progress(open_range, &self.progress));
+        let (block_reader, _size) = ArchiveReader::<R>::build_decode_stack(
+            source,
+            open_range.as_ref(),
+            self.archive,
+            self.block_index,
+            self.password.current(),
+            self.password.keys(),
+            self.thread_count,
+        )
+        .await
+        .map_err(|e| e.maybe_bad_password(!self.password.current().is_empty()))?;
+        let block_reader = ProgressReader::bytes_out(block_reader, self.progress.clone());
+        Ok(Box::new(CancellableReader::new(
+            block_reader,
+            self.cancellation.clone(),
+        )))
+    }
+
+    fn start_entry(&mut self, file_index: usize) -> (ArchiveEntry, EntryReader<'_>) {
+        let file = &self.archive.files[file_index];
+        report(&self.progress, ProgressEvent::EntryStarted(file));
+        self.current = Some(file_index);
+        let has_data = file.has_stream && file.size > 0;
+        self.remaining = if has_data { file.size } else { 0 };
+        let reader = EntryReader {
+            inner: self
+                .block_reader
+                .as_deref_mut()
+                .filter(|_| has_data)
+                .map(|r| r as &mut (dyn AsyncRead + Unpin + Send)),
+            remaining: &mut self.remaining,
+            crc: (has_data && file.has_crc).then(|| (Hasher::new(), file.crc)),
+            name: file.name(),
+        };
+        (file.clone(), reader)
+    }
+
+    async fn skip_current(&mut self) -> Result<(), Error> {
+        let Some(file_index) = self.current.take() else {
+            return Ok(());
+        };
+        if self.remaining > 0 {
+            let count = self
+                .archive
+                .blocks
+                .get(self.block_index)
+                .map_or(0, |block| block.num_unpack_sub_streams);
+            if self.index_in_block >= count {
+                // Nothing else is needed from this block, so stop decoding it.
+                self.block_reader = None;
+            } else if let Some(block_reader) = self.block_reader.as_mut() {
+                let mut rest = futures_lite::io::AsyncReadExt::take(block_reader, self.remaining);
+                futures_lite::io::copy(&mut rest, &mut futures_lite::io::sink())
+                    .await
+                    .map_err(|e| {
+                        Error::from(e)
+                            .maybe_bad_password(!self.password.current().is_empty())
+                            .with_context(block_context(self.archive, self.block_index))
+                            .with_entry(self.archive.files[file_index].name())
+                    })?;
+            }
+            self.remaining = 0;
+        }
+        report(
+            &self.progress,
+            ProgressEvent::EntryFinished(&self.archive.files[file_index]),
+        );
+        Ok(())
+    }
+}
+
+/// Reader for the data of a single entry returned by [`Entries::next`].
+///
+/// The CRC of the entry is verified once all of its data was read.
+pub struct EntryReader<'e> {
+    inner: Option<&'e mut (dyn AsyncRead + Unpin + Send)>,
+    remaining: &'e mut u64,
+    crc: Option<(Hasher, u64)>,
+    name: &'e str,
+}
+
+impl EntryReader<'_> {
+    /// Returns the number of bytes of the entry that were not read yet.
+    pub fn remaining(&self) -> u64 {
+        *self.remaining
+    }
+}
+
+impl AsyncRead for EntryReader<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let Some(inner) = this.inner.as_mut() else {
+            return Poll::Ready(Ok(0));
+        };
+        if *this.remaining == 0 {
+            return Poll::Ready(Ok(0));
+        }
+        let bound = buf
+            .len()
+            .min(usize::try_from(*this.remaining).unwrap_or(usize::MAX));
+        let size = match Pin::new(&mut **inner).poll_read(cx, &mut buf[..bound]) {
+            Poll::Ready(Ok(size)) => size,
+            poll => return poll,
+        };
+        if size == 0 {
+            return Poll::Ready(Err(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        *this.remaining -= size as u64;
+        if let Some((hasher, expected)) = this.crc.as_mut() {
+            hasher.update(&buf[..size]);
+            if *this.remaining == 0 {
+                let crc = std::mem::replace(hasher, Hasher::new()).finalize();
+                if crc as u64 != *expected {
+                    return Poll::Ready(Err(std::io::Error::other(
+                        Error::ChecksumVerificationFailed.with_entry(this.name),
+                    )));
+                }
+            }
+        }
+        Poll::Ready(Ok(size))
+    }
+}
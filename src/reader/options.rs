@@ -0,0 +1,88 @@
+use crate::{
+    CancellationToken, DEFAULT_MAX_NUM_CYCLES_POWER, Password,
+    encryption::{PasswordProvider, PasswordSource},
+};
+
+/// Options of an [`ArchiveReader`](crate::ArchiveReader) that already apply while the archive
+/// is opened, before an encrypted header is decoded.
+///
+/// # Example
+/// ```no_run
+/// use async_sevenz::{ArchiveReader, CancellationToken, ReaderOptions};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), async_sevenz::Error> {
+///     let options = ReaderOptions::default()
+///         .with_password("secret".into())
+///         .with_max_num_cycles_power(20)
+///         .with_cancellation_token(CancellationToken::new());
+///     let mut reader = ArchiveReader::open_with_options("upload.7z", options).await?;
+///     let data = reader.read_file("example.txt").await?;
+///     Ok(())
+/// }
+/// ```
+pub struct ReaderOptions {
+    password: PasswordSource,
+    max_num_cycles_power: u8,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            password: PasswordSource::fixed(Password::empty()),
+            max_num_cycles_power: DEFAULT_MAX_NUM_CYCLES_POWER,
+            cancellation: None,
+        }
+    }
+}
+
+impl From<PasswordSource> for ReaderOptions {
+    fn from(password: PasswordSource) -> Self {
+        Self {
+            password,
+            ..Default::default()
+        }
+    }
+}
+
+impl ReaderOptions {
+    /// Sets the password of the archive. Defaults to [`Password::empty`].
+    pub fn with_password(mut self, password: Password) -> Self {
+        self.password = PasswordSource::fixed(password);
+        self
+    }
+
+    /// Asks `provider` for a password only when encrypted data is met, see
+    /// [`ArchiveReader::open_with_password_provider`](crate::ArchiveReader::open_with_password_provider).
+    pub fn with_password_provider(mut self, provider: impl PasswordProvider + 'static) -> Self {
+        self.password = PasswordSource::provider(provider);
+        self
+    }
+
+    /// Sets the highest accepted key-derivation cost of AES coders, including the coder of an
+    /// encrypted header. See
+    /// [`ArchiveReader::set_max_num_cycles_power`](crate::ArchiveReader::set_max_num_cycles_power).
+    pub fn with_max_num_cycles_power(mut self, max_num_cycles_power: u8) -> Self {
+        self.max_num_cycles_power = max_num_cycles_power;
+        self
+    }
+
+    /// Sets a token that cancels opening the archive, including the key derivation of an
+    /// encrypted header, as well as reading entries afterwards. See
+    /// [`ArchiveReader::set_cancellation_token`](crate::ArchiveReader::set_cancellation_token).
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Returns the password source with the key-derivation limits applied, and the
+    /// cancellation token.
+    pub(crate) fn into_parts(self) -> (PasswordSource, Option<CancellationToken>) {
+        let mut password = self.password;
+        let keys = password.keys_mut();
+        keys.set_max_num_cycles_power(self.max_num_cycles_power);
+        keys.set_cancellation_token(self.cancellation.clone());
+        (password, self.cancellation)
+    }
+}
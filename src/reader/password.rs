@@ -0,0 +1,392 @@
+use std::io::Read;
+
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, SeekFrom};
+use lzma_rust2::LzmaReader;
+
+use super::{ArchiveReader, BlockDecoder, BoundedReader, MAX_MEM_LIMIT_KB};
+use crate::{
+    Archive, EncoderMethod, Error, archive::SIGNATURE_HEADER_SIZE, cancellation::CancellationToken,
+    decoder::add_decoder, encryption::PasswordSource, read_at::OpenRange,
+};
+
+/// Number of decrypted bytes needed to judge the header of the codec behind the AES coder.
+const CHECK_SIZE: usize = 32;
+
+/// Output after which the start of a LZMA stream is taken as plausible.
+const LZMA_CHECK_OUTPUT: usize = 1 << 16;
+
+const ZSTD_MAGIC: u32 = 0xFD2FB528;
+const LZ4_MAGIC: u32 = 0x184D2204;
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A50;
+
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
+    /// Checks whether the password is correct without decoding whole blocks.
+    ///
+    /// The key is derived and only the first bytes of the smallest encrypted pack stream are
+    /// decrypted, to check that the header of the codec behind the encryption (for example the
+    /// LZMA2 control byte and LZMA properties) is plausible. Only if no codec with a known header
+    /// is encrypted, the smallest encrypted block is decoded completely.
+    ///
+    /// If the archive was opened with a password provider, the provider is asked until a password
+    /// passes the check. Returns `Ok(false)` if the password is wrong or the provider gave up, and
+    /// `Ok(true)` if the password is plausible or nothing but the header is encrypted, since an
+    /// encrypted header is already verified while opening the archive.
+    ///
+    /// A plausible header doesn't prove the password right. The provider is asked again if
+    /// decoding a block fails before any of its entries matched its CRC.
+    pub async fn check_password(&mut self) -> Result<bool, Error> {
+        let smallest = (0..self.archive.blocks.len())
+            .filter(|&index| self.archive.blocks[index].is_encrypted())
+            .min_by_key(|&index| {
+                self.archive.pack_sizes
+                    [self.archive.stream_map.block_first_pack_stream_index[index]]
+            });
+        let Some(block_index) = smallest else {
+            return Ok(true);
+        };
+        loop {
+            let result = check_block(
+                &mut self.source,
+                self.open_range.as_ref(),
+                &self.archive,
+                block_index,
+                &self.password,
+                self.thread_count,
+                &self.cancellation,
+            )
+            .await;
+            match result {
+                Ok(verified) => {
+                    confirm_verified_password(&mut self.password, verified);
+                    return Ok(true);
+                }
+                Err(e) => match self.password.retry(e) {
+                    Ok(()) => {}
+                    Err(Error::MaybeBadPassword(_) | Error::PasswordRequired) => return Ok(false),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+/// Asks the password provider for passwords until one passes the check of the encrypted block
+/// with the given index, unless a password was already confirmed.
+///
+/// Wrong passwords are rejected early by the header check of
+/// [`check_password`](ArchiveReader::check_password). Only if the codec behind the encryption
+/// has no known header, the whole block is decoded once and its checksums are verified, since a
+/// wrong AES key otherwise only shows up as corrupt data.
+///
+/// A password is only confirmed once a block decoded with matching checksums. Until then, every
+/// encrypted block is checked, and a block that fails to decode is retried with the next password
+/// through [`retry_block_password`].
+pub(crate) async fn verify_block_password<S: AsyncRead + AsyncSeek + Unpin + Send>(
+    source: &mut S,
+    open_range: Option<&OpenRange>,
+    archive: &Archive,
+    block_index: usize,
+    password: &mut PasswordSource,
+    thread_count: u32,
+    cancellation: &Option<CancellationToken>,
+) -> Result<(), Error> {
+    if password.is_confirmed() || !archive.blocks[block_index].is_encrypted() {
+        return Ok(());
+    }
+    loop {
+        let result = check_block(
+            source,
+            open_range,
+            archive,
+            block_index,
+            password,
+            thread_count,
+            cancellation,
+        )
+        .await;
+        match result {
+            Ok(verified) => {
+                confirm_verified_password(password, verified);
+                return Ok(());
+            }
+            Err(e) => password.retry(e)?,
+        }
+    }
+}
+
+/// Checks the password against an encrypted block even if the password was confirmed before.
+///
+/// Used where blocks are decrypted without being decoded, so that a wrong fixed password is
+/// noticed before its garbage output is used. A password from a provider is retried until it
+/// passes the check, a confirmed password fails with [`Error::MaybeBadPassword`].
+#[cfg(all(feature = "aes256", feature = "compress"))]
+pub(crate) async fn recheck_block_password<S: AsyncRead + AsyncSeek + Unpin + Send>(
+    source: &mut S,
+    open_range: Option<&OpenRange>,
+    archive: &Archive,
+    block_index: usize,
+    password: &mut PasswordSource,
+    thread_count: u32,
+    cancellation: &Option<CancellationToken>,
+) -> Result<(), Error> {
+    if !archive.blocks[block_index].is_encrypted() {
+        return Ok(());
+    }
+    loop {
+        let result = check_block(
+            source,
+            open_range,
+            archive,
+            block_index,
+            password,
+            thread_count,
+            cancellation,
+        )
+        .await;
+        match result {
+            Ok(verified) => {
+                confirm_verified_password(password, verified);
+                return Ok(());
+            }
+            Err(e) => retry_block_password(password, e.maybe_bad_password(true), false)?,
+        }
+    }
+}
+
+/// Confirms the current password if a block decoded with it and at least one entry matched its
+/// CRC, so the following blocks are no longer checked.
+pub(crate) fn confirm_verified_password(password: &mut PasswordSource, verified: bool) {
+    if verified {
+        password.confirm();
+    }
+}
+
+/// Requests the next password after decoding a block failed with `error`.
+///
+/// A password that only passed the header check can still be wrong, which shows up as corrupt
+/// data. Unless an entry of the block already matched its CRC, the provider is asked for the next
+/// password so the block can be decoded again. Returns `error` otherwise.
+pub(crate) fn retry_block_password(
+    password: &mut PasswordSource,
+    error: Error,
+    verified: bool,
+) -> Result<(), Error> {
+    if password.is_confirmed() || verified {
+        return Err(error);
+    }
+    password.retry(error)
+}
+
+/// Checks the header of the codec behind the encryption, or decodes the whole block if the
+/// codec has no known header.
+///
+/// Returns `true` if the block was decoded with matching checksums, and `false` if only its
+/// header is plausible.
+pub(crate) async fn check_block<S: AsyncRead + AsyncSeek + Unpin + Send>(
+    source: &mut S,
+    open_range: Option<&OpenRange>,
+    archive: &Archive,
+    block_index: usize,
+    password: &PasswordSource,
+    thread_count: u32,
+    cancellation: &Option<CancellationToken>,
+) -> Result<bool, Error> {
+    match quick_check_block_password(source, archive, block_index, password).await? {
+        Some(true) => Ok(false),
+        Some(false) => Err(wrong_password()),
+        None => {
+            decode_block(
+                source,
+                open_range,
+                archive,
+                block_index,
+                password,
+                thread_count,
+                cancellation,
+            )
+            .await?;
+            Ok(true)
+        }
+    }
+}
+
+fn wrong_password() -> Error {
+    Error::MaybeBadPassword(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "implausible data after decryption",
+    ))
+}
+
+/// Decodes the whole block and verifies its checksums.
+async fn decode_block<S: AsyncRead + AsyncSeek + Unpin + Send>(
+    source: &mut S,
+    open_range: Option<&OpenRange>,
+    archive: &Archive,
+    block_index: usize,
+    password: &PasswordSource,
+    thread_count: u32,
+    cancellation: &Option<CancellationToken>,
+) -> Result<(), Error> {
+    let mut block_decoder = BlockDecoder::new(
+        thread_count,
+        block_index,
+        archive,
+        password.current(),
+        source,
+    );
+    block_decoder.cancellation = cancellation.clone();
+    block_decoder.keys = password.keys().clone();
+    block_decoder.open_range = open_range.cloned();
+    block_decoder
+        .for_each_entries(&mut |_, reader| {
+            Box::pin(async move {
+                futures_lite::io::copy(reader, &mut futures_lite::io::sink()).await?;
+                Ok(true)
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Decrypts the first bytes of the block and checks the header of the codec behind the AES
+/// coder.
+///
+/// Returns `None` if the block layout or the codec does not allow a quick check.
+async fn quick_check_block_password<S: AsyncRead + AsyncSeek + Unpin + Send>(
+    source: &mut S,
+    archive: &Archive,
+    block_index: usize,
+    password: &PasswordSource,
+) -> Result<Option<bool>, Error> {
+    let block = &archive.blocks[block_index];
+    if block.total_input_streams != block.total_output_streams {
+        return Ok(None);
+    }
+    let mut coders = block.ordered_coder_iter();
+    let (Some((aes_index, aes)), Some((next_index, next))) = (coders.next(), coders.next()) else {
+        return Ok(None);
+    };
+    if aes.encoder_method_id() != EncoderMethod::ID_AES256_SHA256 {
+        return Ok(None);
+    }
+    let next_unpack_size = block.get_unpack_size_at_index(next_index);
+    let check: fn(&[u8], u64, &[u8]) -> bool = match next.encoder_method_id() {
+        EncoderMethod::ID_LZMA => lzma_start_plausible,
+        EncoderMethod::ID_LZMA2 => |data, unpack_size, _| lzma2_header_plausible(data, unpack_size),
+        EncoderMethod::ID_BZIP2 => |data, _, _| {
+            data.len() >= 4 && data.starts_with(b"BZh") && matches!(data[3], b'1'..=b'9')
+        },
+        EncoderMethod::ID_ZSTD => |data, _, _| starts_with_magic(data, ZSTD_MAGIC),
+        EncoderMethod::ID_LZ4 => |data, _, _| starts_with_magic(data, LZ4_MAGIC),
+        _ => return Ok(None),
+    };
+    if password.current().is_empty() {
+        return Err(Error::PasswordRequired);
+    }
+
+    let first_pack_stream_index = archive.stream_map.block_first_pack_stream_index[block_index];
+    let block_offset = SIGNATURE_HEADER_SIZE
+        + archive.pack_pos
+        + archive.stream_map.pack_stream_offsets[first_pack_stream_index];
+    AsyncSeekExt::seek(source, SeekFrom::Start(block_offset)).await?;
+    let pack_size = archive.pack_sizes[first_pack_stream_index] as usize;
+    let aes_unpack_size = block.get_unpack_size_at_index(aes_index) as usize;
+    let mut decoder = add_decoder(
+        BoundedReader::new(&mut *source, pack_size),
+        aes_unpack_size,
+        aes,
+        password.current(),
+        password.keys(),
+        MAX_MEM_LIMIT_KB,
+        1,
+    )
+    .await?;
+
+    let mut data = [0u8; CHECK_SIZE];
+    let wanted = CHECK_SIZE.min(aes_unpack_size);
+    let mut filled = 0;
+    while filled < wanted {
+        match AsyncReadExt::read(&mut decoder, &mut data[filled..wanted]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(Some(check(
+        &data[..filled],
+        next_unpack_size,
+        next.properties(),
+    )))
+}
+
+fn starts_with_magic(data: &[u8], magic: u32) -> bool {
+    let Some(head) = data.get(..4) else {
+        return false;
+    };
+    let value = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    value == magic || value & 0xFFFFFFF0 == SKIPPABLE_FRAME_MAGIC
+}
+
+/// LZMA data has no header besides the zero byte the range coder starts with, so the decrypted
+/// bytes are decoded. Random data soon decodes to a match reaching before the start of the
+/// output, while the start of a valid stream only runs out of input.
+fn lzma_start_plausible(data: &[u8], unpack_size: u64, properties: &[u8]) -> bool {
+    let [props, d0, d1, d2, d3, ..] = *properties else {
+        return false;
+    };
+    // Matches can only reach back to the start of the decoded output.
+    let dict_size = u32::from_le_bytes([d0, d1, d2, d3]).min(2 * LZMA_CHECK_OUTPUT as u32);
+    let input = CheckInput {
+        data,
+        exhausted: false,
+    };
+    let Ok(mut reader) = LzmaReader::new_with_props(input, unpack_size, props, dict_size, None)
+    else {
+        return false;
+    };
+    let mut buf = [0u8; 4096];
+    let mut decoded = 0;
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return true,
+            Ok(n) => {
+                decoded += n;
+                if decoded >= LZMA_CHECK_OUTPUT || reader.inner().exhausted {
+                    return true;
+                }
+            }
+            // The decoder reads past the end of the input as filler bytes, which usually ends
+            // in an error too.
+            Err(_) => return reader.inner().exhausted,
+        }
+    }
+}
+
+/// Decrypted bytes for [`lzma_start_plausible`], remembering whether the decoder wanted more.
+struct CheckInput<'a> {
+    data: &'a [u8],
+    exhausted: bool,
+}
+
+impl Read for CheckInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() && !buf.is_empty() {
+            self.exhausted = true;
+        }
+        Read::read(&mut self.data, buf)
+    }
+}
+
+/// The first LZMA2 chunk has to reset the dictionary, so it is either uncompressed or a LZMA
+/// chunk with new properties whose range coder starts with a zero byte.
+fn lzma2_header_plausible(data: &[u8], unpack_size: u64) -> bool {
+    match data {
+        [] | [0x00, ..] => unpack_size == 0,
+        [0x01, hi, lo, ..] => (u16::from_be_bytes([*hi, *lo]) as u64) < unpack_size,
+        [control, u0, u1, _, _, props, first, ..] if *control >= 0xE0 => {
+            let chunk_size = ((*control as u64 & 0x1F) << 16 | (*u0 as u64) << 8 | *u1 as u64) + 1;
+            let (lc, lp) = (props % 9, props / 9 % 5);
+            chunk_size <= unpack_size && *props < 9 * 5 * 5 && lc + lp <= 4 && *first == 0
+        }
+        _ => false,
+    }
+}
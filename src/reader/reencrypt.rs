@@ -0,0 +1,137 @@
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, SeekFrom};
+
+use super::{ArchiveReader, BoundedReader, MAX_MEM_LIMIT_KB, password::recheck_block_password};
+use crate::{
+    ArchiveWriter, EncoderMethod, Error, Password, archive::SIGNATURE_HEADER_SIZE,
+    cancellation::check_cancelled, decoder::add_decoder, encoder_options::AesEncoderOptions,
+};
+
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
+    /// Writes a copy of the archive to `output` that is encrypted with `new_password`, without
+    /// recompressing any data.
+    ///
+    /// The pack streams of blocks whose outermost coder is AES-256 are decrypted with the password
+    /// of this reader and encrypted again with `new_password`, using a new salt and IV but the
+    /// same key-derivation cost. The compressed data, as well as blocks that are not encrypted, is
+    /// copied as-is. The header is encrypted again if it was encrypted before.
+    ///
+    /// Pass [`Password::empty`] to remove the encryption: the AES coders are dropped from the
+    /// blocks and the header is written unencrypted.
+    ///
+    /// Every encrypted block is checked against the password before it is copied, which may
+    /// decode the whole block if its codec has no header to check. A wrong password fails with
+    /// [`Error::MaybeBadPassword`] instead of writing garbage.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use async_sevenz::{ArchiveReader, Password};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let mut reader = ArchiveReader::open("backup.7z", "old secret".into()).await?;
+    ///     let output = async_fs::File::create("backup-new.7z").await?;
+    ///     reader.change_password(output, &"new secret".into()).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn change_password<W: AsyncWrite + AsyncSeek + Unpin>(
+        &mut self,
+        output: W,
+        new_password: &Password,
+    ) -> Result<W, Error> {
+        let mut writer = ArchiveWriter::new(output).await?;
+        let aes = (!new_password.is_empty()).then(|| AesEncoderOptions::new(new_password.clone()));
+        match (&aes, self.archive.header_num_cycles_power) {
+            (Some(aes), Some(num_cycles_power)) => {
+                let options = AesEncoderOptions {
+                    num_cycles_power,
+                    ..aes.clone()
+                };
+                writer.set_content_methods(vec![options.into()]);
+            }
+            _ => writer.set_encrypt_header(false),
+        }
+
+        for block_index in 0..self.archive.blocks.len() {
+            check_cancelled(&self.cancellation)?;
+            recheck_block_password(
+                &mut self.source,
+                self.open_range.as_ref(),
+                &self.archive,
+                block_index,
+                &mut self.password,
+                self.thread_count,
+                &self.cancellation,
+            )
+            .await?;
+            let mut block = self.archive.blocks[block_index].clone();
+            let first_pack_stream_index =
+                self.archive.stream_map.block_first_pack_stream_index[block_index];
+            let mut removed = Vec::new();
+            for (i, in_index) in block.packed_streams.clone().into_iter().enumerate() {
+                let pack_stream_index = first_pack_stream_index + i;
+                let offset = SIGNATURE_HEADER_SIZE
+                    + self.archive.pack_pos
+                    + self.archive.stream_map.pack_stream_offsets[pack_stream_index];
+                AsyncSeekExt::seek(&mut self.source, SeekFrom::Start(offset)).await?;
+                let pack_size = self.archive.pack_sizes[pack_stream_index] as usize;
+                let packed = BoundedReader::new(&mut self.source, pack_size);
+
+                let aes_index = block.coder_index_for_in_stream(in_index).filter(|&index| {
+                    block.coders[index].encoder_method_id() == EncoderMethod::ID_AES256_SHA256
+                });
+                let Some(coder_index) = aes_index else {
+                    writer.push_packed_stream(packed, None).await?;
+                    continue;
+                };
+                let coder = &block.coders[coder_index];
+                let unpack_size = block.get_unpack_size_for_coder(coder);
+                let decrypted = add_decoder(
+                    packed,
+                    unpack_size as usize,
+                    coder,
+                    self.password.current(),
+                    self.password.keys(),
+                    MAX_MEM_LIMIT_KB,
+                    1,
+                )
+                .await?;
+                // The last AES block is padded, the coder after it only gets the unpack size.
+                let decrypted = AsyncReadExt::take(decrypted, unpack_size);
+                match &aes {
+                    Some(aes) => {
+                        let mut options = aes.clone();
+                        options.num_cycles_power = coder.properties.first().map_or(0, |b0| b0 & 63);
+                        getrandom::fill(&mut options.iv).expect("Can't generate IV");
+                        block.coders[coder_index].properties = options.properties().to_vec();
+                        writer
+                            .push_packed_stream(decrypted, Some(&options.into()))
+                            .await?;
+                    }
+                    None => {
+                        writer.push_packed_stream(decrypted, None).await?;
+                        removed.push(coder_index);
+                    }
+                }
+            }
+            // Removing a coder shifts the indices of the coders after it.
+            removed.sort_unstable();
+            for coder_index in removed.into_iter().rev() {
+                block.remove_coder(coder_index);
+            }
+
+            let start = self.archive.stream_map.block_first_file_index[block_index];
+            let files = &self.archive.files[start..start + block.num_unpack_sub_streams];
+            writer.push_raw_block(
+                block,
+                files.iter().map(|file| file.size).collect(),
+                files
+                    .iter()
+                    .map(|file| file.has_crc.then_some(file.crc as u32))
+                    .collect(),
+            );
+        }
+        writer.push_raw_entries(self.archive.files.iter().cloned());
+        Ok(writer.finish().await?)
+    }
+}
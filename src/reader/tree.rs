@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use futures_lite::io::{AsyncRead, AsyncSeek};
+use unicode_normalization::UnicodeNormalization;
+
+use super::ArchiveReader;
+use crate::{Archive, ArchiveEntry, Error};
+
+/// Normalises an entry path for lookups.
+///
+/// Both `\` and `/` are treated as separators, empty and `.` components are dropped and the
+/// result is composed to Unicode NFC, so names stored by Windows or macOS tools resolve.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for component in path.split(['/', '\\']) {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+        if !normalized.is_empty() {
+            normalized.push('/');
+        }
+        normalized.extend(component.nfc());
+    }
+    normalized
+}
+
+#[derive(Debug)]
+struct Node {
+    path: String,
+    file_index: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// Directory tree over the entries of an archive, including implicit directories for paths that
+/// have no directory entry of their own.
+#[derive(Debug)]
+pub(crate) struct ArchiveTree {
+    nodes: Vec<Node>,
+    by_path: HashMap<String, usize>,
+    by_lowercase_path: HashMap<String, usize>,
+}
+
+impl ArchiveTree {
+    const ROOT: usize = 0;
+
+    pub(crate) fn new(archive: &Archive) -> Self {
+        let mut tree = Self {
+            nodes: vec![Node {
+                path: String::new(),
+                file_index: None,
+                parent: None,
+                children: Vec::new(),
+            }],
+            by_path: HashMap::default(),
+            by_lowercase_path: HashMap::default(),
+        };
+        for (file_index, file) in archive.files.iter().enumerate() {
+            let path = normalize_path(file.name());
+            if path.is_empty() {
+                continue;
+            }
+            let node = tree.insert(&path);
+            tree.nodes[node].file_index = Some(file_index);
+        }
+        tree
+    }
+
+    fn insert(&mut self, path: &str) -> usize {
+        if let Some(&node) = self.by_path.get(path) {
+            return node;
+        }
+        let parent = match path.rsplit_once('/') {
+            Some((parent, _)) => self.insert(parent),
+            None => Self::ROOT,
+        };
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            path: path.to_string(),
+            file_index: None,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(node);
+        self.by_path.insert(path.to_string(), node);
+        self.by_lowercase_path
+            .entry(path.to_lowercase())
+            .or_insert(node);
+        node
+    }
+
+    fn find(&self, path: &str, case_insensitive: bool) -> Option<usize> {
+        let path = normalize_path(path);
+        if path.is_empty() {
+            return Some(Self::ROOT);
+        }
+        self.by_path.get(&path).copied().or_else(|| {
+            case_insensitive
+                .then(|| self.by_lowercase_path.get(&path.to_lowercase()).copied())
+                .flatten()
+        })
+    }
+}
+
+/// A file or directory in the directory tree of an archive.
+///
+/// Obtained from [`ArchiveReader::lookup`], [`ArchiveReader::list_dir`] or
+/// [`ArchiveReader::root`].
+#[derive(Clone, Copy)]
+pub struct TreeNode<'a> {
+    tree: &'a ArchiveTree,
+    archive: &'a Archive,
+    index: usize,
+}
+
+impl std::fmt::Debug for TreeNode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TreeNode")
+            .field("path", &self.path())
+            .field("entry", &self.entry())
+            .finish()
+    }
+}
+
+impl<'a> TreeNode<'a> {
+    fn node(&self) -> &'a Node {
+        &self.tree.nodes[self.index]
+    }
+
+    /// Returns the normalised path of this node, using `/` as separator. Empty for the root.
+    pub fn path(&self) -> &'a str {
+        &self.node().path
+    }
+
+    /// Returns the last component of the path of this node.
+    pub fn name(&self) -> &'a str {
+        let path = self.path();
+        path.rsplit_once('/').map_or(path, |(_, name)| name)
+    }
+
+    /// Returns the archive entry of this node, or `None` for the root and implicit directories.
+    pub fn entry(&self) -> Option<&'a ArchiveEntry> {
+        self.node()
+            .file_index
+            .map(|index| &self.archive.files[index])
+    }
+
+    /// Returns `true` if this node is a directory, either stored explicitly in the archive or
+    /// implied by the paths of other entries.
+    pub fn is_directory(&self) -> bool {
+        self.entry().is_none_or(|entry| entry.is_directory()) || !self.node().children.is_empty()
+    }
+
+    /// Returns `true` if this node is a directory without an entry of its own in the archive.
+    pub fn is_implicit_directory(&self) -> bool {
+        self.index != ArchiveTree::ROOT && self.node().file_index.is_none()
+    }
+
+    /// Returns the parent directory, or `None` for the root.
+    pub fn parent(&self) -> Option<TreeNode<'a>> {
+        self.node().parent.map(|index| TreeNode { index, ..*self })
+    }
+
+    /// Returns the direct children of this node in archive order.
+    pub fn children(&self) -> impl Iterator<Item = TreeNode<'a>> + 'a {
+        let (tree, archive) = (self.tree, self.archive);
+        self.node().children.iter().map(move |&index| TreeNode {
+            tree,
+            archive,
+            index,
+        })
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> ArchiveReader<R> {
+    /// Sets whether [`lookup`](Self::lookup), [`list_dir`](Self::list_dir) and
+    /// [`read_file`](Self::read_file) ignore the case of entry names. Default is `false`.
+    pub fn set_case_insensitive_lookup(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// Returns the root directory of the archive's directory tree.
+    pub fn root(&self) -> TreeNode<'_> {
+        TreeNode {
+            tree: &self.tree,
+            archive: &self.archive,
+            index: ArchiveTree::ROOT,
+        }
+    }
+
+    /// Looks up a file or directory by path.
+    ///
+    /// `\` and `/` are both accepted as separators, leading `./` and trailing separators are
+    /// ignored and names are compared in Unicode NFC form. Directories that only exist as
+    /// prefix of other entries are found as well.
+    pub fn lookup(&self, path: &str) -> Option<TreeNode<'_>> {
+        self.tree
+            .find(path, self.case_insensitive)
+            .map(|index| TreeNode {
+                tree: &self.tree,
+                archive: &self.archive,
+                index,
+            })
+    }
+
+    /// Returns the direct children of the directory at `path`. An empty path lists the root.
+    pub fn list_dir(&self, path: &str) -> Result<Vec<TreeNode<'_>>, Error> {
+        let node = self.lookup(path).ok_or(Error::FileNotFound)?;
+        if !node.is_directory() {
+            return Err(Error::other(format!("Not a directory: {path}")));
+        }
+        Ok(node.children().collect())
+    }
+
+    /// Resolves a path to the index of its entry, trying the exact stored name first.
+    pub(crate) fn resolve_file_index(&self, name: &str) -> Option<usize> {
+        if let Some(&file_index) = self.index.get(name) {
+            return Some(file_index);
+        }
+        self.lookup(name)
+            .and_then(|node| self.tree.nodes[node.index].file_index)
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_lite::io::{AsyncRead, AsyncSeek, AsyncWrite};
+use tokio::io::ReadBuf;
+
+use crate::{ArchiveReader, EntryReader, Error, Password};
+
+/// Adapter between the I/O traits of tokio and the `futures` traits used by this crate.
+///
+/// Wrapping a tokio reader, writer or seeker implements the corresponding `futures` trait, so
+/// e.g. a `tokio::fs::File` can be read by an [`ArchiveReader`]. Wrapping a `futures` type
+/// implements the tokio traits instead, so the data of an entry can be copied with
+/// `tokio::io::copy`.
+#[derive(Debug, Default)]
+pub struct TokioCompat<T> {
+    inner: T,
+    seek_pos: Option<SeekFrom>,
+}
+
+impl<T> TokioCompat<T> {
+    /// Wraps `inner`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            seek_pos: None,
+        }
+    }
+
+    /// Returns a reference to the wrapped value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: tokio::io::AsyncRead + Unpin> AsyncRead for TokioCompat<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<T: tokio::io::AsyncWrite + Unpin> AsyncWrite for TokioCompat<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<T: tokio::io::AsyncSeek + Unpin> AsyncSeek for TokioCompat<T> {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = &mut *self;
+        // A tokio seek is started once and then polled until it completes.
+        if this.seek_pos != Some(pos) {
+            // Finish a seek that was started by an earlier, abandoned call first.
+            ready!(Pin::new(&mut this.inner).poll_complete(cx))?;
+            Pin::new(&mut this.inner).start_seek(pos)?;
+            this.seek_pos = Some(pos);
+        }
+        let result = ready!(Pin::new(&mut this.inner).poll_complete(cx));
+        this.seek_pos = None;
+        Poll::Ready(result)
+    }
+}
+
+impl<T: AsyncRead + Unpin> tokio::io::AsyncRead for TokioCompat<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let size = ready!(Pin::new(&mut self.inner).poll_read(cx, buf.initialize_unfilled()))?;
+        buf.advance(size);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> tokio::io::AsyncWrite for TokioCompat<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl<T: AsyncSeek + Unpin> tokio::io::AsyncSeek for TokioCompat<T> {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.seek_pos = Some(position);
+        Ok(())
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        // Without a started seek tokio expects the current position.
+        let pos = self.seek_pos.unwrap_or(SeekFrom::Current(0));
+        let result = ready!(Pin::new(&mut self.inner).poll_seek(cx, pos));
+        self.seek_pos = None;
+        Poll::Ready(result)
+    }
+}
+
+impl<R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin + Send> ArchiveReader<TokioCompat<R>> {
+    /// Opens a 7z archive from a tokio reader, e.g. a `tokio::fs::File`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use async_sevenz::{ArchiveReader, Password};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), async_sevenz::Error> {
+    ///     let file = tokio::fs::File::open("example.7z").await?;
+    ///     let mut reader = ArchiveReader::from_tokio(file, Password::empty()).await?;
+    ///     let data = reader.read_file("example.txt").await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn from_tokio(source: R, password: Password) -> Result<Self, Error> {
+        Self::new(TokioCompat::new(source), password).await
+    }
+}
+
+#[cfg(feature = "compress")]
+impl<W: tokio::io::AsyncWrite + tokio::io::AsyncSeek + Unpin> crate::ArchiveWriter<TokioCompat<W>> {
+    /// Prepares to write a 7z archive to a tokio writer, e.g. a `tokio::fs::File`.
+    ///
+    /// [`finish`](Self::finish) returns the writer wrapped in a [`TokioCompat`], use
+    /// [`TokioCompat::into_inner`] to get it back.
+    pub async fn from_tokio(writer: W) -> Result<Self, Error> {
+        Self::new(TokioCompat::new(writer)).await
+    }
+}
+
+impl tokio::io::AsyncRead for EntryReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let size = ready!(AsyncRead::poll_read(self, cx, buf.initialize_unfilled()))?;
+        buf.advance(size);
+        Poll::Ready(Ok(()))
+    }
+}
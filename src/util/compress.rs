@@ -8,7 +8,10 @@ use futures_lite::io::{AsyncSeek, AsyncWrite, Cursor};
 
 #[cfg(feature = "aes256")]
 use crate::encoder_options::AesEncoderOptions;
-use crate::{ArchiveEntry, ArchiveWriter, EncoderMethod, Error, Password, writer::LazyFileReader};
+use crate::{
+    ArchiveEntry, ArchiveWriter, EncoderMethod, Error, Password, ProgressEvent, ProgressObserver,
+    ProgressTotals, progress::report, writer::LazyFileReader,
+};
 
 /// Compresses a source file or directory to a destination writer.
 ///
@@ -18,16 +21,26 @@ use crate::{ArchiveEntry, ArchiveWriter, EncoderMethod, Error, Password, writer:
 pub async fn compress<W: AsyncWrite + AsyncSeek + Unpin>(
     src: impl AsRef<Path>,
     dest: W,
+) -> Result<W, Error> {
+    let archive_writer = ArchiveWriter::new(dest).await?;
+    compress_with_writer(src, archive_writer).await
+}
+
+/// Compresses a source file or directory to a destination writer and reports the progress to
+/// `observer`.
+///
+/// # Arguments
+/// * `src` - Path to the source file or directory to compress
+/// * `dest` - Writer that implements `AsyncWrite + AsyncSeek + Unpin`
+/// * `observer` - Observer that receives the progress events
+pub async fn compress_with_progress<W: AsyncWrite + AsyncSeek + Unpin>(
+    src: impl AsRef<Path>,
+    dest: W,
+    observer: impl ProgressObserver + 'static,
 ) -> Result<W, Error> {
     let mut archive_writer = ArchiveWriter::new(dest).await?;
-    let parent = if src.as_ref().is_dir() {
-        src.as_ref()
-    } else {
-        src.as_ref().parent().unwrap_or(src.as_ref())
-    };
-    compress_path(src.as_ref(), parent, &mut archive_writer).await?;
-    let out = archive_writer.finish().await?;
-    Ok(out)
+    archive_writer.set_progress_observer(observer);
+    compress_with_writer(src, archive_writer).await
 }
 
 /// Compresses a source file or directory to a destination writer with password encryption.
@@ -49,6 +62,13 @@ pub async fn compress_encrypted<W: AsyncWrite + AsyncSeek + Unpin>(
             EncoderMethod::LZMA2.into(),
         ]);
     }
+    compress_with_writer(src, archive_writer).await
+}
+
+async fn compress_with_writer<W: AsyncWrite + AsyncSeek + Unpin>(
+    src: impl AsRef<Path>,
+    mut archive_writer: ArchiveWriter<W>,
+) -> Result<W, Error> {
     let parent = if src.as_ref().is_dir() {
         src.as_ref()
     } else {
@@ -81,6 +101,32 @@ pub async fn compress_to_path(src: impl AsRef<Path>, dest: impl AsRef<Path>) ->
     Ok(())
 }
 
+/// Compresses a source file or directory to a destination file path and reports the progress to
+/// `observer`.
+///
+/// # Arguments
+/// * `src` - Path to the source file or directory to compress
+/// * `dest` - Path where the compressed archive will be created
+/// * `observer` - Observer that receives the progress events
+pub async fn compress_to_path_with_progress(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    observer: impl ProgressObserver + 'static,
+) -> Result<(), Error> {
+    if let Some(path) = dest.as_ref().parent() {
+        if !path.exists() {
+            afs::create_dir_all(path)
+                .await
+                .map_err(|e| Error::io_msg(e, format!("Create dir failed:{:?}", dest.as_ref())))?;
+        }
+    }
+    let cursor = Cursor::new(Vec::<u8>::new());
+    let cursor = compress_with_progress(src, cursor, observer).await?;
+    let data = cursor.into_inner();
+    afs::write(dest.as_ref(), data).await?;
+    Ok(())
+}
+
 /// Compresses a source file or directory to a destination file path with password encryption.
 ///
 /// This is a convenience function that handles file creation automatically.
@@ -114,21 +160,15 @@ async fn compress_path<W: AsyncWrite + AsyncSeek + Unpin, P: AsRef<Path>>(
     root: &Path,
     archive_writer: &mut ArchiveWriter<W>,
 ) -> Result<(), Error> {
+    let mut paths: Vec<(PathBuf, bool)> = Vec::new();
+    let mut totals = ProgressTotals::default();
     let mut stack: Vec<PathBuf> = vec![src.as_ref().to_path_buf()];
     while let Some(path) = stack.pop() {
-        let entry_name = path
-            .strip_prefix(root)
-            .map_err(|e| Error::other(e.to_string()))?
-            .to_string_lossy()
-            .to_string();
-        let entry = ArchiveEntry::from_path(path.as_path(), entry_name).await;
         let meta = afs::metadata(&path)
             .await
             .map_err(|e| Error::io_msg(e, "error metadata"))?;
+        totals.entries += 1;
         if meta.is_dir() {
-            archive_writer
-                .push_archive_entry::<&[u8]>(entry, None)
-                .await?;
             let mut rd = afs::read_dir(&path)
                 .await
                 .map_err(|e| Error::io_msg(e, "error read dir"))?;
@@ -142,6 +182,24 @@ async fn compress_path<W: AsyncWrite + AsyncSeek + Unpin, P: AsRef<Path>>(
                     stack.push(dir.path());
                 }
             }
+        } else {
+            totals.unpacked_size += meta.len();
+        }
+        paths.push((path, meta.is_dir()));
+    }
+    report(archive_writer.progress(), ProgressEvent::Totals(totals));
+
+    for (path, is_dir) in paths {
+        let entry_name = path
+            .strip_prefix(root)
+            .map_err(|e| Error::other(e.to_string()))?
+            .to_string_lossy()
+            .to_string();
+        let entry = ArchiveEntry::from_path(path.as_path(), entry_name).await;
+        if is_dir {
+            archive_writer
+                .push_archive_entry::<&[u8]>(entry, None)
+                .await?;
         } else {
             archive_writer
                 .push_archive_entry::<crate::writer::SourceReader<crate::writer::LazyFileReader>>(
@@ -160,6 +218,9 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
     /// The filter function allows selective inclusion of files based on their paths.
     /// Files are compressed using solid compression for better compression ratios.
     ///
+    /// The writer's [`CancellationToken`](crate::CancellationToken) is checked between buffers
+    /// and entries, see [`ArchiveWriter::set_cancellation_token`].
+    ///
     /// # Arguments
     /// * `path` - Path to add to the compression
     /// * `filter` - Function that returns `true` for paths that should be included
@@ -180,6 +241,9 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
     /// Non-solid compression allows individual file extraction without decompressing the entire archive,
     /// but typically results in larger archive sizes compared to solid compression.
     ///
+    /// The writer's [`CancellationToken`](crate::CancellationToken) is checked between buffers
+    /// and entries, see [`ArchiveWriter::set_cancellation_token`].
+    ///
     /// # Arguments
     /// * `path` - Path to add to the compression
     /// * `filter` - Function that returns `true` for paths that should be included
@@ -248,6 +312,17 @@ where
             )
         })?;
 
+    if zip.progress().is_some() {
+        let mut totals = ProgressTotals {
+            entries: paths.len(),
+            ..Default::default()
+        };
+        for path in paths.iter() {
+            totals.unpacked_size += afs::metadata(path).await?.len();
+        }
+        report(zip.progress(), ProgressEvent::Totals(totals));
+    }
+
     if !solid {
         for ele in paths.into_iter() {
             let name = extract_file_name(&src, &ele)?;
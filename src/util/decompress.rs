@@ -6,7 +6,7 @@ use std::future::Future;
 use std::io::{Read, Seek};
 use std::pin::Pin;
 
-use crate::{Error, Password, *};
+use crate::{CancellationToken, Error, Password, progress::SharedProgress, *};
 
 pub(crate) struct AsyncReadSeekAsStd<R: AsyncRead + Unpin> {
     inner: R,
@@ -53,6 +53,119 @@ pub async fn decompress_file(
         src_path.as_ref(),
         dest_path,
         Password::empty(),
+        |_| true,
+        None,
+        None,
+        |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
+    )
+    .await
+}
+
+/// Decompresses only the entries accepted by `filter` from an archive file to a destination
+/// directory.
+///
+/// Blocks that contain no selected entry are skipped without reading their packed data, and
+/// decoding of a solid block stops after its last selected entry.
+///
+/// # Arguments
+/// * `src_path` - Path to the source archive file
+/// * `dest` - Path to the destination directory where files will be extracted
+/// * `filter` - Function that returns `true` for entries that should be extracted
+///
+/// # Example
+///
+/// ```no_run
+/// use async_sevenz::decompress_file_with_filter;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     decompress_file_with_filter("example.7z", "output", |entry| {
+///         entry.name().ends_with(".txt")
+///     })
+///     .await
+///     .expect("complete");
+/// }
+/// ```
+pub async fn decompress_file_with_filter(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    filter: impl FnMut(&ArchiveEntry) -> bool,
+) -> Result<(), Error> {
+    decompress_path_impl(
+        src_path.as_ref(),
+        dest.as_ref().to_path_buf(),
+        Password::empty(),
+        filter,
+        None,
+        None,
+        |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
+    )
+    .await
+}
+
+/// Decompresses an archive file to a destination directory and reports the progress to
+/// `observer`.
+///
+/// # Arguments
+/// * `src_path` - Path to the source archive file
+/// * `dest` - Path to the destination directory where files will be extracted
+/// * `observer` - Observer that receives the progress events
+///
+/// # Example
+///
+/// ```no_run
+/// use async_sevenz::{ProgressEvent, decompress_file_with_progress};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     decompress_file_with_progress("example.7z", "output", |event: ProgressEvent<'_>| {
+///         if let ProgressEvent::EntryFinished(entry) = event {
+///             println!("extracted {}", entry.name());
+///         }
+///     })
+///     .await
+///     .expect("complete");
+/// }
+/// ```
+pub async fn decompress_file_with_progress(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    observer: impl ProgressObserver + 'static,
+) -> Result<(), Error> {
+    decompress_path_impl(
+        src_path.as_ref(),
+        dest.as_ref().to_path_buf(),
+        Password::empty(),
+        |_| true,
+        Some(std::sync::Arc::new(observer)),
+        None,
+        |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
+    )
+    .await
+}
+
+/// Decompresses an archive file to a destination directory until `token` is cancelled.
+///
+/// The token is checked between buffers and entries. On cancellation [`Error::Cancelled`] is
+/// returned and the partially written file of the interrupted entry is removed, while entries
+/// that were already extracted completely are kept.
+///
+/// # Arguments
+/// * `src_path` - Path to the source archive file
+/// * `dest` - Path to the destination directory where files will be extracted
+/// * `token` - Token to cancel the extraction
+pub async fn decompress_file_with_cancellation(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    token: CancellationToken,
+) -> Result<(), Error> {
+    decompress_path_impl(
+        src_path.as_ref(),
+        dest.as_ref().to_path_buf(),
+        Password::empty(),
+        |_| true,
+        None,
+        Some(token),
         |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
     )
     .await
@@ -83,6 +196,9 @@ pub async fn decompress_file_with_extract_fn(
         src_path.as_ref(),
         dest.as_ref().to_path_buf(),
         Password::empty(),
+        |_| true,
+        None,
+        None,
         move |entry, reader, path| extract_fn(entry, reader, path),
     )
     .await
@@ -151,6 +267,38 @@ pub async fn decompress_file_with_password(
         src_path.as_ref(),
         dest_path,
         password,
+        |_| true,
+        None,
+        None,
+        |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
+    )
+    .await
+}
+
+/// Decompresses only the entries accepted by `filter` from an encrypted archive file with the
+/// given password.
+///
+/// See [`decompress_file_with_filter`] for how blocks without selected entries are skipped.
+///
+/// # Arguments
+/// * `src_path` - Path to the encrypted source archive file
+/// * `dest` - Path to the destination directory where files will be extracted
+/// * `password` - Password to decrypt the archive
+/// * `filter` - Function that returns `true` for entries that should be extracted
+#[cfg(all(feature = "aes256", not(target_arch = "wasm32")))]
+pub async fn decompress_file_with_filter_and_password(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    password: Password,
+    filter: impl FnMut(&ArchiveEntry) -> bool,
+) -> Result<(), Error> {
+    decompress_path_impl(
+        src_path.as_ref(),
+        dest.as_ref().to_path_buf(),
+        password,
+        filter,
+        None,
+        None,
         |entry, reader, dest| Box::pin(default_entry_extract_fn(entry, reader, dest)),
     )
     .await
@@ -249,6 +397,9 @@ async fn decompress_path_impl(
     src_path: &Path,
     dest: PathBuf,
     password: Password,
+    select: impl FnMut(&ArchiveEntry) -> bool,
+    progress: SharedProgress,
+    cancellation: Option<CancellationToken>,
     extract_fn: impl for<'a> FnMut(
         &'a ArchiveEntry,
         &'a mut (dyn AsyncRead + Unpin + Send + 'a),
@@ -259,24 +410,40 @@ async fn decompress_path_impl(
     + Send,
 ) -> Result<(), Error> {
     let mut seven = ArchiveReader::open(src_path, password).await?;
+    seven.progress = progress;
+    seven.cancellation = cancellation;
     if !dest.exists() {
         afs::create_dir_all(&dest).await?;
     }
     let extract_fn_cell = std::sync::Arc::new(std::sync::Mutex::new(extract_fn));
-    seven
-        .for_each_entries(|entry, reader| {
+    let current_file = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let result = seven
+        .for_each_selected_entries(select, |entry, reader| {
             let dest_path = dest.join(entry.name());
             let extract_fn_cell = std::sync::Arc::clone(&extract_fn_cell);
+            let current_file = std::sync::Arc::clone(&current_file);
             Box::pin(async move {
+                if !entry.is_directory() {
+                    *current_file.lock().unwrap() = Some(dest_path.clone());
+                }
                 let fut = {
                     let mut f = extract_fn_cell.lock().unwrap();
                     f(entry, reader, dest_path.as_path())
                 };
-                fut.await
+                let cont = fut.await?;
+                *current_file.lock().unwrap() = None;
+                Ok(cont)
             })
         })
-        .await?;
-    Ok(())
+        .await;
+    if let Err(Error::Cancelled) = result {
+        // Remove the partially extracted file of the entry that was interrupted.
+        let partial = current_file.lock().unwrap().take();
+        if let Some(path) = partial {
+            let _ = afs::remove_file(path).await;
+        }
+    }
+    result
 }
 
 /// Default extraction function that handles standard file and directory extraction.
@@ -314,3 +481,70 @@ pub async fn default_entry_extract_fn(
 
     Ok(true)
 }
+
+#[cfg(all(test, feature = "compress", not(target_arch = "wasm32")))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_in_the_middle_of_an_entry_removes_the_partial_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let archive_path = temp_dir.path().join("archive.7z");
+        let big: Vec<u8> = (0..4 * 1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_content_methods(vec![EncoderMethod::COPY.into()]);
+        writer
+            .push_archive_entry(ArchiveEntry::new_file("small.txt"), Some(&b"complete"[..]))
+            .await
+            .unwrap();
+        writer
+            .push_archive_entry(ArchiveEntry::new_file("big.bin"), Some(big.as_slice()))
+            .await
+            .unwrap();
+        let archive = writer.finish().await.unwrap().into_inner();
+        afs::write(&archive_path, archive).await.unwrap();
+
+        // Cancels after the first decoded buffer of the big entry.
+        let token = CancellationToken::new();
+        let in_big_entry = Arc::new(AtomicBool::new(false));
+        let observer_token = token.clone();
+        let observer = move |event: ProgressEvent<'_>| match event {
+            ProgressEvent::EntryStarted(entry) => {
+                in_big_entry.store(entry.name() == "big.bin", Ordering::Relaxed)
+            }
+            ProgressEvent::BytesOut(_) if in_big_entry.load(Ordering::Relaxed) => {
+                observer_token.cancel()
+            }
+            _ => {}
+        };
+        let dest = temp_dir.path().join("output");
+        let result = decompress_path_impl(
+            &archive_path,
+            dest.clone(),
+            Password::empty(),
+            |_| true,
+            Some(Arc::new(observer)),
+            Some(token),
+            // Streams to the file, so the interrupted entry leaves a partial file behind.
+            |_, reader, dest| {
+                Box::pin(async move {
+                    let mut file = afs::File::create(dest).await?;
+                    futures_lite::io::copy(reader, &mut file).await?;
+                    Ok(true)
+                })
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert_eq!(std::fs::read(dest.join("small.txt")).unwrap(), b"complete");
+        assert!(!dest.join("big.bin").exists());
+    }
+}
@@ -21,7 +21,17 @@ pub(crate) use self::lazy_file_reader::LazyFileReader;
 pub(crate) use self::seq_reader::SeqReader;
 pub use self::source_reader::SourceReader;
 use self::{pack_info::PackInfo, unpack_info::UnpackInfo};
-use crate::{ArchiveEntry, AutoFinish, AutoFinisher, Error, archive::*, bitset::BitSet, encoder};
+use crate::{
+    ArchiveEntry, AutoFinish, AutoFinisher, Block, Coder, Error,
+    archive::*,
+    bitset::BitSet,
+    block::BindPair,
+    cancellation::{CancellationToken, check_cancelled},
+    codec::bcj2::Bcj2Writer,
+    encoder,
+    encryption::KeyCache,
+    progress::{ProgressEvent, ProgressObserver, SharedProgress, report},
+};
 
 macro_rules! write_times {
     ($fn_name:tt, $nid:expr, $has_time:tt, $time:tt) => {
@@ -108,6 +118,10 @@ pub struct ArchiveWriter<W: AsyncWrite + AsyncSeek + Unpin> {
     pack_info: PackInfo,
     unpack_info: UnpackInfo,
     encrypt_header: bool,
+    progress: SharedProgress,
+    cancellation: Option<CancellationToken>,
+    cancelled: bool,
+    keys: KeyCache,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -133,6 +147,10 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
             pack_info: Default::default(),
             unpack_info: Default::default(),
             encrypt_header: true,
+            progress: None,
+            cancellation: None,
+            cancelled: false,
+            keys: KeyCache::default(),
         })
     }
 
@@ -142,10 +160,25 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
     }
 
     /// Sets the default compression methods to use for entry data. Default is LZMA2.
+    ///
+    /// [`EncoderMethod::BCJ2_FILTER`] has to be the last method. It splits the data into a main
+    /// stream, which is compressed by the methods before it or by LZMA if there are none, and
+    /// three smaller streams that are compressed by LZMA, after the encryption of the main stream
+    /// if there is one.
+    ///
+    /// The three smaller streams follow the main stream in the archive. They are compressed while
+    /// the data is written, and kept in memory until the main stream is complete. For x86 code
+    /// this is a small fraction of the data, mostly the compressed targets of calls and jumps.
     pub fn set_content_methods(&mut self, content_methods: Vec<EncoderConfiguration>) -> &mut Self {
         if content_methods.is_empty() {
             return self;
         }
+        let content_methods = match content_methods.as_slice() {
+            [bcj2] if bcj2.method.id() == EncoderMethod::ID_BCJ2 => {
+                vec![EncoderConfiguration::new(EncoderMethod::LZMA), bcj2.clone()]
+            }
+            _ => content_methods,
+        };
         self.content_methods = Arc::new(content_methods);
         self
     }
@@ -155,6 +188,32 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         self.encrypt_header = enabled;
     }
 
+    /// Sets an observer that is notified about the progress of compressing entries.
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Some(Arc::new(observer));
+    }
+
+    pub(crate) fn progress(&self) -> &SharedProgress {
+        &self.progress
+    }
+
+    /// Sets a token to cancel compression.
+    ///
+    /// Pushing entries checks the token between buffers and entries. Once an operation was
+    /// cancelled it returns [`Error::Cancelled`] and the writer becomes unusable: every further
+    /// push and [`finish`](Self::finish) fails, since the output only contains a partial block.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Checks whether the writer was or should be cancelled and marks it as unusable if so.
+    fn check_cancelled(&mut self) -> Result<()> {
+        if self.cancelled {
+            return Err(Error::Cancelled);
+        }
+        check_cancelled(&self.cancellation).inspect_err(|_| self.cancelled = true)
+    }
+
     /// Non-solid compression - Adds an archive `entry` with data from `reader`.
     ///
     /// # Example
@@ -183,42 +242,55 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         mut entry: ArchiveEntry,
         reader: Option<R>,
     ) -> Result<&ArchiveEntry> {
+        self.check_cancelled()?;
+        report(&self.progress, ProgressEvent::EntryStarted(&entry));
         if !entry.is_directory {
             if let Some(mut r) = reader {
+                report(
+                    &self.progress,
+                    ProgressEvent::BlockStarted(self.unpack_info.blocks.len()),
+                );
                 let mut compressed_len = 0;
-                let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len);
+                let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len)
+                    .with_progress(self.progress.clone());
 
                 let mut more_sizes: Vec<Rc<Cell<usize>>> =
                     Vec::with_capacity(self.content_methods.len() - 1);
+                let mut bcj2_side = Bcj2SideStreams::default();
 
                 let (crc, size) = {
                     let mut w = Self::create_writer(
                         &self.content_methods,
                         &mut compressed,
                         &mut more_sizes,
+                        Some(&mut bcj2_side),
+                        &self.keys,
                     )?;
                     let mut write_len = 0;
                     let mut w = CompressWrapWriter::new(&mut w, &mut write_len);
                     let mut buf = [0u8; 4096];
                     loop {
-                        let n = AsyncReadExt::read(&mut r, &mut buf).await.map_err(|e| {
-                            Error::io_msg(e, format!("Encode entry:{}", entry.name()))
-                        })?;
+                        let n = AsyncReadExt::read(&mut r, &mut buf)
+                            .await
+                            .map_err(|e| Error::from(e).with_entry(entry.name()))?;
                         if n == 0 {
                             break;
                         }
+                        if let Err(e) = check_cancelled(&self.cancellation) {
+                            self.cancelled = true;
+                            return Err(e);
+                        }
+                        report(&self.progress, ProgressEvent::BytesIn(n as u64));
                         AsyncWriteExt::write_all(&mut w, &buf[..n])
                             .await
-                            .map_err(|e| {
-                                Error::io_msg(e, format!("Encode entry:{}", entry.name()))
-                            })?;
+                            .map_err(|e| Error::from(e).with_entry(entry.name()))?;
                     }
                     AsyncWriteExt::flush(&mut w)
                         .await
-                        .map_err(|e| Error::io_msg(e, format!("Encode entry:{}", entry.name())))?;
+                        .map_err(|e| Error::from(e).with_entry(entry.name()))?;
                     AsyncWriteExt::write(&mut w, &[])
                         .await
-                        .map_err(|e| Error::io_msg(e, format!("Encode entry:{}", entry.name())))?;
+                        .map_err(|e| Error::from(e).with_entry(entry.name()))?;
 
                     (w.crc_value(), write_len)
                 };
@@ -236,9 +308,17 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
                 sizes.extend(more_sizes.iter().map(|s| s.get() as u64));
                 sizes.push(size as u64);
 
-                self.unpack_info
-                    .add(self.content_methods.clone(), sizes, crc);
+                if ends_with_bcj2(&self.content_methods) {
+                    entry.compressed_size += self
+                        .push_bcj2_streams(bcj2_side, sizes, crc, vec![entry.size], vec![crc])
+                        .await
+                        .map_err(|e| e.with_entry(entry.name()))?;
+                } else {
+                    self.unpack_info
+                        .add(self.content_methods.clone(), sizes, crc);
+                }
 
+                report(&self.progress, ProgressEvent::EntryFinished(&entry));
                 self.files.push(entry);
                 return Ok(self.files.last().unwrap());
             }
@@ -247,6 +327,7 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         entry.size = 0;
         entry.compressed_size = 0;
         entry.has_crc = false;
+        report(&self.progress, ProgressEvent::EntryFinished(&entry));
         self.files.push(entry);
         Ok(self.files.last().unwrap())
     }
@@ -260,16 +341,32 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         entries: Vec<ArchiveEntry>,
         reader: Vec<SourceReader<R>>,
     ) -> Result<&mut Self> {
+        self.check_cancelled()?;
         let mut entries = entries;
         let mut r = SeqReader::new(reader);
         assert_eq!(r.reader_len(), entries.len());
+        report(
+            &self.progress,
+            ProgressEvent::BlockStarted(self.unpack_info.blocks.len()),
+        );
+        let progress = &self.progress;
+        let cancellation = &self.cancellation;
+        let cancelled = &mut self.cancelled;
         let mut compressed_len = 0;
-        let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len);
+        let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len)
+            .with_progress(progress.clone());
         let content_methods = &self.content_methods;
         let mut more_sizes: Vec<Rc<Cell<usize>>> = Vec::with_capacity(content_methods.len() - 1);
+        let mut bcj2_side = Bcj2SideStreams::default();
 
         let (crc, size) = {
-            let mut w = Self::create_writer(content_methods, &mut compressed, &mut more_sizes)?;
+            let mut w = Self::create_writer(
+                content_methods,
+                &mut compressed,
+                &mut more_sizes,
+                Some(&mut bcj2_side),
+                &self.keys,
+            )?;
             let mut write_len = 0;
             let mut w = CompressWrapWriter::new(&mut w, &mut write_len);
             let mut buf = [0u8; 4096];
@@ -286,13 +383,23 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
                 names
             }
 
+            let mut started = 0;
+            let mut finished = 0;
             loop {
                 let n = AsyncReadExt::read(&mut r, &mut buf).await.map_err(|e| {
                     Error::io_msg(e, format!("Encode entries:{}", entries_names(&entries)))
                 })?;
+                if progress.is_some() {
+                    report_solid_entries(progress, &mut entries, &r, &mut started, &mut finished);
+                }
                 if n == 0 {
                     break;
                 }
+                if let Err(e) = check_cancelled(cancellation) {
+                    *cancelled = true;
+                    return Err(e);
+                }
+                report(progress, ProgressEvent::BytesIn(n as u64));
                 AsyncWriteExt::write_all(&mut w, &buf[..n])
                     .await
                     .map_err(|e| {
@@ -336,42 +443,232 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         sizes.extend(more_sizes.iter().map(|s| s.get() as u64));
         sizes.push(size as u64);
 
-        self.unpack_info.add_multiple(
-            content_methods.clone(),
-            sizes,
-            crc,
-            entries.len() as u64,
-            sub_stream_sizes,
-            sub_stream_crcs,
-        );
+        match ends_with_bcj2(&self.content_methods) {
+            true => {
+                self.push_bcj2_streams(bcj2_side, sizes, crc, sub_stream_sizes, sub_stream_crcs)
+                    .await?;
+            }
+            false => self.unpack_info.add_multiple(
+                self.content_methods.clone(),
+                sizes,
+                crc,
+                entries.len() as u64,
+                sub_stream_sizes,
+                sub_stream_crcs,
+            ),
+        }
 
         self.files.extend(entries);
         Ok(self)
     }
 
+    /// Chains the encoders of `methods` in front of `out`. A BCJ2 encoder compresses its call,
+    /// jump and range coder streams into `bcj2_side`.
     fn create_writer<'a, O: AsyncWrite + Unpin + 'a>(
         methods: &[EncoderConfiguration],
         out: O,
         more_sized: &mut Vec<Rc<Cell<usize>>>,
+        mut bcj2_side: Option<&'a mut Bcj2SideStreams>,
+        keys: &KeyCache,
     ) -> Result<Box<dyn AsyncWrite + Unpin + 'a>> {
         let mut encoder: Box<dyn AsyncWrite + Unpin> = Box::new(out);
         let mut first = true;
-        for mc in methods.iter() {
+        for (i, mc) in methods.iter().enumerate() {
+            let counting = CountingWriter::new(encoder);
             if !first {
-                let counting = CountingWriter::new(encoder);
                 more_sized.push(counting.counting());
-                encoder = Box::new(encoder::add_encoder(counting, mc)?);
+            }
+            if mc.method.id() == EncoderMethod::ID_BCJ2 {
+                if i + 1 != methods.len() {
+                    return Err(Error::unsupported("BCJ2 must be the last content method"));
+                }
+                let Some(Bcj2SideStreams { packed, sizes }) = bcj2_side.take() else {
+                    return Err(Error::unsupported("BCJ2 is only supported for entry data"));
+                };
+                let side_methods = bcj2_side_methods(&methods[..i]);
+                let [call, jump, rc] = packed;
+                let [call_sizes, jump_sizes, rc_sizes] = sizes;
+                let side_writer = |packed: &'a mut Vec<u8>, sizes: &mut Vec<_>| {
+                    let writer = CountingWriter::new(Self::create_writer(
+                        &side_methods,
+                        packed,
+                        sizes,
+                        None,
+                        keys,
+                    )?);
+                    sizes.push(writer.counting());
+                    Ok::<_, Error>(writer)
+                };
+                let side = [
+                    side_writer(call, call_sizes)?,
+                    side_writer(jump, jump_sizes)?,
+                    side_writer(rc, rc_sizes)?,
+                ];
+                encoder = Box::new(Bcj2Writer::new(counting, side));
             } else {
-                let counting = CountingWriter::new(encoder);
-                encoder = Box::new(encoder::add_encoder(counting, mc)?);
+                encoder = Box::new(encoder::add_encoder(counting, mc, keys)?);
             }
             first = false;
         }
         Ok(encoder)
     }
 
+    /// Writes the call, jump and range coder streams of a BCJ2 block as pack streams after its
+    /// main stream and adds the block. Returns the size of the written pack streams.
+    ///
+    /// `sizes` are the unpack sizes of the content methods, as for other blocks.
+    async fn push_bcj2_streams(
+        &mut self,
+        side: Bcj2SideStreams,
+        sizes: Vec<u64>,
+        crc: u32,
+        sub_stream_sizes: Vec<u64>,
+        sub_stream_crcs: Vec<u32>,
+    ) -> Result<u64> {
+        let content_methods = self.content_methods.clone();
+        let main_methods = &content_methods[..content_methods.len() - 1];
+        let side_methods = bcj2_side_methods(main_methods);
+
+        let mut block = Block::default();
+        block
+            .coders
+            .push(Coder::new(EncoderMethod::ID_BCJ2, 4, Vec::new()));
+        block.unpack_sizes.push(sizes[sizes.len() - 1]);
+        let mut packed_len = 0;
+        let mut next_in_index = 4;
+        let Bcj2SideStreams {
+            packed,
+            sizes: side_sizes,
+        } = side;
+        let side_streams = packed.into_iter().zip(side_sizes).map(Some);
+        for (stream, side) in [None].into_iter().chain(side_streams).enumerate() {
+            let (methods, sizes) = match side {
+                None => (main_methods, sizes[..sizes.len() - 1].to_vec()),
+                Some((packed, sizes)) => {
+                    packed_len += self.push_side_stream(&packed).await?;
+                    let sizes = sizes.iter().map(|size| size.get() as u64).collect();
+                    (side_methods.as_slice(), sizes)
+                }
+            };
+            // Coders are listed from the unpacked data to the packed stream, each one reading
+            // the output of the next.
+            let mut in_index = stream as u64;
+            for (mc, size) in methods.iter().zip(sizes).rev() {
+                let mut temp = [0u8; 256];
+                let props =
+                    encoder::get_options_as_properties(mc.method, mc.options.as_ref(), &mut temp);
+                block
+                    .coders
+                    .push(Coder::new(mc.method.id(), 1, props.to_vec()));
+                block.bind_pairs.push(BindPair {
+                    in_index,
+                    out_index: block.unpack_sizes.len() as u64,
+                });
+                block.unpack_sizes.push(size);
+                in_index = next_in_index;
+                next_in_index += 1;
+            }
+            block.packed_streams.push(in_index);
+        }
+        block.total_input_streams = next_in_index as usize;
+        block.total_output_streams = block.unpack_sizes.len();
+        self.unpack_info
+            .add_raw(block, crc, sub_stream_sizes, sub_stream_crcs, None);
+        Ok(packed_len)
+    }
+
+    /// Writes a compressed side stream of a BCJ2 block as a pack stream. Returns its size.
+    async fn push_side_stream(&mut self, packed: &[u8]) -> Result<u64> {
+        let mut packed_len = 0;
+        let mut writer = CompressWrapWriter::new(&mut self.output, &mut packed_len)
+            .with_progress(self.progress.clone());
+        AsyncWriteExt::write_all(&mut writer, packed).await?;
+        AsyncWriteExt::flush(&mut writer).await?;
+        let packed_crc = writer.crc_value();
+        self.pack_info.add_stream(packed_len as u64, packed_crc);
+        Ok(packed_len as u64)
+    }
+
+    /// Copies an already encoded pack stream from `reader` to the archive, encoded once more with
+    /// `method` if given.
+    #[cfg_attr(not(feature = "aes256"), allow(dead_code))]
+    pub(crate) async fn push_packed_stream<R: AsyncRead + Unpin>(
+        &mut self,
+        mut reader: R,
+        method: Option<&EncoderConfiguration>,
+    ) -> Result<()> {
+        self.check_cancelled()?;
+        let mut packed_len = 0;
+        let mut packed = CompressWrapWriter::new(&mut self.output, &mut packed_len)
+            .with_progress(self.progress.clone());
+        {
+            let mut w: Box<dyn AsyncWrite + Unpin> = match method {
+                Some(method) => Self::create_writer(
+                    std::slice::from_ref(method),
+                    &mut packed,
+                    &mut vec![],
+                    None,
+                    &self.keys,
+                )?,
+                None => Box::new(&mut packed),
+            };
+            futures_lite::io::copy(&mut reader, &mut w).await?;
+            AsyncWriteExt::flush(&mut w).await?;
+            AsyncWriteExt::write(&mut w, &[]).await?;
+        }
+        let packed_crc = packed.crc_value();
+        self.pack_info.add_stream(packed_len as u64, packed_crc);
+        Ok(())
+    }
+
+    /// Adds a block whose pack streams were pushed with
+    /// [`push_packed_stream`](Self::push_packed_stream), together with the sizes and CRCs of
+    /// its entries, `None` for entries without a CRC.
+    #[cfg_attr(not(feature = "aes256"), allow(dead_code))]
+    pub(crate) fn push_raw_block(
+        &mut self,
+        block: Block,
+        sub_stream_sizes: Vec<u64>,
+        sub_stream_crcs: Vec<Option<u32>>,
+    ) {
+        let crc = match sub_stream_crcs.first() {
+            Some(Some(crc)) if !block.has_crc => *crc,
+            _ => block.crc as u32,
+        };
+        let mut defined = BitSet::with_capacity(sub_stream_crcs.len());
+        for (i, crc) in sub_stream_crcs.iter().enumerate() {
+            if crc.is_some() {
+                defined.insert(i);
+            }
+        }
+        let all_defined = sub_stream_crcs.iter().all(Option::is_some);
+        self.unpack_info.add_raw(
+            block,
+            crc,
+            sub_stream_sizes,
+            sub_stream_crcs
+                .into_iter()
+                .map(Option::unwrap_or_default)
+                .collect(),
+            (!all_defined).then_some(defined),
+        );
+    }
+
+    /// Adds entries whose data was added with [`push_raw_block`](Self::push_raw_block), in the
+    /// order of the original archive.
+    #[cfg_attr(not(feature = "aes256"), allow(dead_code))]
+    pub(crate) fn push_raw_entries(&mut self, entries: impl IntoIterator<Item = ArchiveEntry>) {
+        self.files.extend(entries);
+    }
+
     /// Finishes the compression.
+    ///
+    /// Fails with [`Error::Cancelled`] wrapped in an [`std::io::Error`] if compression was
+    /// cancelled before.
     pub async fn finish(mut self) -> std::io::Result<W> {
+        if self.cancelled {
+            return Err(std::io::Error::other(Error::Cancelled));
+        }
         let mut cursor = Cursor::new(Vec::with_capacity(64 * 1024));
         self.write_encoded_header(&mut cursor).await?;
         let header = cursor.into_inner();
@@ -442,8 +739,9 @@ impl<W: AsyncWrite + AsyncSeek + Unpin> ArchiveWriter<W> {
         let mut compress_size = 0;
         let mut compressed = CompressWrapWriter::new(&mut encoded_cursor, &mut compress_size);
         {
-            let mut encoder = Self::create_writer(&methods, &mut compressed, &mut more_sizes)
-                .map_err(std::io::Error::other)?;
+            let mut encoder =
+                Self::create_writer(&methods, &mut compressed, &mut more_sizes, None, &self.keys)
+                    .map_err(std::io::Error::other)?;
             AsyncWriteExt::write_all(&mut encoder, &raw_header).await?;
             AsyncWriteExt::flush(&mut encoder).await?;
             let _ = AsyncWriteExt::write(&mut encoder, &[]).await?;
@@ -682,11 +980,67 @@ pub(crate) fn bitset_to_bytes(bs: &BitSet, capacity: usize) -> Vec<u8> {
     out
 }
 
+/// Reports entry start and finish events of a solid block as the sequential reader moves
+/// through the entry readers.
+fn report_solid_entries<R>(
+    progress: &SharedProgress,
+    entries: &mut [ArchiveEntry],
+    reader: &SeqReader<SourceReader<R>>,
+    started: &mut usize,
+    finished: &mut usize,
+) {
+    let current = reader.current_index().min(entries.len());
+    while *finished < current {
+        if *started == *finished {
+            report(progress, ProgressEvent::EntryStarted(&entries[*started]));
+            *started += 1;
+        }
+        let entry = &mut entries[*finished];
+        entry.size = reader[*finished].read_count() as u64;
+        entry.crc = reader[*finished].crc_value() as u64;
+        entry.has_crc = true;
+        report(progress, ProgressEvent::EntryFinished(entry));
+        *finished += 1;
+    }
+    if *started == current && current < entries.len() {
+        report(progress, ProgressEvent::EntryStarted(&entries[current]));
+        *started += 1;
+    }
+}
+
+/// The call, jump and range coder streams of a BCJ2 block, compressed while the block is written.
+/// They are kept in memory until they can follow the main stream as pack streams.
+#[derive(Default)]
+struct Bcj2SideStreams {
+    packed: [Vec<u8>; 3],
+    /// Unpack sizes of the side methods for each stream, as collected by `create_writer`,
+    /// followed by the size of the stream itself.
+    sizes: [Vec<Rc<Cell<usize>>>; 3],
+}
+
+fn ends_with_bcj2(methods: &[EncoderConfiguration]) -> bool {
+    methods
+        .last()
+        .is_some_and(|mc| mc.method.id() == EncoderMethod::ID_BCJ2)
+}
+
+/// Returns the methods of the side streams of a BCJ2 block: LZMA, behind the encryption of the
+/// main stream if there is one.
+fn bcj2_side_methods(main_methods: &[EncoderConfiguration]) -> Vec<EncoderConfiguration> {
+    main_methods
+        .iter()
+        .filter(|mc| mc.method.id() == EncoderMethod::ID_AES256_SHA256)
+        .cloned()
+        .chain([EncoderConfiguration::new(EncoderMethod::LZMA)])
+        .collect()
+}
+
 struct CompressWrapWriter<'a, W> {
     writer: W,
     crc: Hasher,
     cache: Vec<u8>,
     bytes_written: &'a mut usize,
+    progress: SharedProgress,
 }
 
 impl<'a, W> CompressWrapWriter<'a, W> {
@@ -696,9 +1050,16 @@ impl<'a, W> CompressWrapWriter<'a, W> {
             crc: Hasher::new(),
             cache: Vec::with_capacity(8192),
             bytes_written,
+            progress: None,
         }
     }
 
+    /// Reports every write as produced bytes to `progress`.
+    pub fn with_progress(mut self, progress: SharedProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
     pub fn crc_value(&mut self) -> u32 {
         let crc = std::mem::replace(&mut self.crc, Hasher::new());
         crc.finalize()
@@ -719,6 +1080,9 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for CompressWrapWriter<'_, W> {
         if let std::task::Poll::Ready(Ok(len)) = &poll {
             this.crc.update(&buf[..*len]);
             *this.bytes_written += *len;
+            if *len > 0 {
+                report(&this.progress, ProgressEvent::BytesOut(*len as u64));
+            }
         }
         poll
     }
@@ -25,6 +25,11 @@ impl<R> SeqReader<R> {
     pub(crate) fn reader_len(&self) -> usize {
         self.readers.len()
     }
+
+    /// Index of the reader that is currently read from, `reader_len()` once all are exhausted.
+    pub(crate) fn current_index(&self) -> usize {
+        self.current
+    }
 }
 
 impl<R: AsyncRead + Unpin> AsyncRead for SeqReader<R> {
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use super::*;
-use crate::EncoderConfiguration;
+use crate::{Block, EncoderConfiguration};
 #[derive(Debug, Clone, Default)]
 pub(crate) struct UnpackInfo {
     pub(crate) blocks: Vec<BlockInfo>,
@@ -39,6 +39,31 @@ impl UnpackInfo {
             num_sub_unpack_streams,
             sub_stream_crcs,
             sub_stream_sizes,
+            ..Default::default()
+        })
+    }
+
+    /// Adds a block whose coders were copied from another archive.
+    ///
+    /// `sub_stream_crcs_defined` tells which of the CRCs are defined, `None` if all of them are.
+    #[cfg_attr(not(feature = "aes256"), allow(dead_code))]
+    pub(crate) fn add_raw(
+        &mut self,
+        block: Block,
+        crc: u32,
+        sub_stream_sizes: Vec<u64>,
+        sub_stream_crcs: Vec<u32>,
+        sub_stream_crcs_defined: Option<BitSet>,
+    ) {
+        self.blocks.push(BlockInfo {
+            sizes: block.unpack_sizes.clone(),
+            crc,
+            num_sub_unpack_streams: sub_stream_sizes.len() as u64,
+            sub_stream_sizes,
+            sub_stream_crcs,
+            sub_stream_crcs_defined,
+            raw: Some(block),
+            ..Default::default()
         })
     }
 
@@ -100,22 +125,28 @@ impl UnpackInfo {
             }
         }
 
-        // We always write the CRC values in the substreams info.
+        // We always write the CRC values in the substreams info, raw blocks copied from another
+        // archive may leave some of them undefined.
         let mut crcs_to_write = Vec::new();
         for f in &self.blocks {
+            let is_defined = |i: usize| {
+                f.sub_stream_crcs_defined
+                    .as_ref()
+                    .is_none_or(|d| d.contains(i))
+            };
             if f.num_sub_unpack_streams > 1 {
                 // Multiple substreams - write all CRCs.
-                for &crc in &f.sub_stream_crcs {
-                    crcs_to_write.push(crc);
+                for (i, &crc) in f.sub_stream_crcs.iter().enumerate() {
+                    crcs_to_write.push(is_defined(i).then_some(crc));
                 }
             } else if f.num_sub_unpack_streams == 1 {
                 // Single substream - write CRC here and not in the folder section.
                 match f.sub_stream_crcs.first() {
                     None => {
-                        crcs_to_write.push(f.crc);
+                        crcs_to_write.push(Some(f.crc));
                     }
                     Some(crc) => {
-                        crcs_to_write.push(*crc);
+                        crcs_to_write.push(is_defined(0).then_some(*crc));
                     }
                 };
             }
@@ -123,8 +154,20 @@ impl UnpackInfo {
 
         if !crcs_to_write.is_empty() {
             AsyncWriteExt::write_all(header, &[K_CRC]).await?;
-            AsyncWriteExt::write_all(header, &[1]).await?; // all CRCs defined.
-            for crc in crcs_to_write {
+            if crcs_to_write.iter().all(Option::is_some) {
+                AsyncWriteExt::write_all(header, &[1]).await?; // all CRCs defined.
+            } else {
+                AsyncWriteExt::write_all(header, &[0]).await?; // not all defined
+                let mut crc_define_bits = BitSet::with_capacity(crcs_to_write.len());
+                for (i, crc) in crcs_to_write.iter().enumerate() {
+                    if crc.is_some() {
+                        crc_define_bits.insert(i);
+                    }
+                }
+                let temp = bitset_to_bytes(&crc_define_bits, crcs_to_write.len());
+                AsyncWriteExt::write_all(header, &temp).await?;
+            }
+            for crc in crcs_to_write.into_iter().flatten() {
                 AsyncWriteExt::write_all(header, &crc.to_le_bytes()).await?;
             }
         }
@@ -142,6 +185,10 @@ pub(crate) struct BlockInfo {
     pub(crate) num_sub_unpack_streams: u64,
     pub(crate) sub_stream_sizes: Vec<u64>,
     pub(crate) sub_stream_crcs: Vec<u32>,
+    /// Which of `sub_stream_crcs` are defined, `None` if all of them are.
+    pub(crate) sub_stream_crcs_defined: Option<BitSet>,
+    /// Coders copied from another archive, written instead of `methods`.
+    pub(crate) raw: Option<Block>,
 }
 
 impl BlockInfo {
@@ -150,6 +197,9 @@ impl BlockInfo {
         header: &mut W,
         cache: &mut Vec<u8>,
     ) -> std::io::Result<()> {
+        if let Some(block) = &self.raw {
+            return Self::write_raw(block, header).await;
+        }
         cache.clear();
         let mut num_coders = 0;
         for mc in self.methods.iter() {
@@ -165,6 +215,44 @@ impl BlockInfo {
         Ok(())
     }
 
+    async fn write_raw<W: AsyncWrite + Unpin>(
+        block: &Block,
+        header: &mut W,
+    ) -> std::io::Result<()> {
+        write_encoded_u64(header, block.coders.len() as u64).await?;
+        for coder in block.coders.iter() {
+            let id = coder.encoder_method_id();
+            let is_simple = coder.num_in_streams == 1 && coder.num_out_streams == 1;
+            let mut codec_flags = id.len() as u8;
+            if !is_simple {
+                codec_flags |= 0x10;
+            }
+            if !coder.properties.is_empty() {
+                codec_flags |= 0x20;
+            }
+            AsyncWriteExt::write_all(header, &[codec_flags]).await?;
+            AsyncWriteExt::write_all(header, id).await?;
+            if !is_simple {
+                write_encoded_u64(header, coder.num_in_streams).await?;
+                write_encoded_u64(header, coder.num_out_streams).await?;
+            }
+            if !coder.properties.is_empty() {
+                write_encoded_u64(header, coder.properties.len() as u64).await?;
+                AsyncWriteExt::write_all(header, &coder.properties).await?;
+            }
+        }
+        for bp in block.bind_pairs.iter() {
+            write_encoded_u64(header, bp.in_index).await?;
+            write_encoded_u64(header, bp.out_index).await?;
+        }
+        if block.packed_streams.len() > 1 {
+            for index in block.packed_streams.iter().copied() {
+                write_encoded_u64(header, index).await?;
+            }
+        }
+        Ok(())
+    }
+
     async fn write_single_codec<W: AsyncWrite + Unpin>(
         &self,
         mc: &EncoderConfiguration,
@@ -382,12 +382,120 @@ async fn compress_with_bcj_riscv_lzma2_algorithm() {
     .await;
 }
 
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn compress_with_bcj_start_offset() {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+
+    for method in [
+        EncoderMethod::BCJ_X86_FILTER,
+        EncoderMethod::BCJ_ARM64_FILTER,
+        EncoderMethod::BCJ_RISCV_FILTER,
+    ] {
+        let bcj = EncoderConfiguration::new(method)
+            .with_options(BcjOptions::from_start_offset(0x1000).into());
+        test_compression_method(&[EncoderMethod::LZMA2.into(), bcj.clone()]).await;
+
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_content_methods(vec![EncoderMethod::LZMA2.into(), bcj]);
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file("decompress_x86.exe"),
+                Some(content.as_slice()),
+            )
+            .await
+            .unwrap();
+        let data = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+            .await
+            .unwrap();
+        let blocks = reader.archive().block_details();
+        assert!(
+            blocks[0].methods.iter().any(|m| m.properties
+                == CoderProperties::Bcj {
+                    start_offset: 0x1000
+                }),
+            "{:?}",
+            blocks[0].methods
+        );
+        assert_eq!(
+            reader.read_file("decompress_x86.exe").await.unwrap(),
+            content
+        );
+    }
+
+    // ARM64 instructions are 4 bytes long, so the start offset has to be a multiple of 4.
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![
+        EncoderMethod::LZMA2.into(),
+        EncoderConfiguration::new(EncoderMethod::BCJ_ARM64_FILTER)
+            .with_options(BcjOptions::from_start_offset(2).into()),
+    ]);
+    let result = writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("decompress_x86.exe"),
+            Some(content.as_slice()),
+        )
+        .await;
+    assert!(result.is_err());
+}
+
 #[cfg(feature = "compress")]
 #[tokio::test]
 async fn compress_with_lzma_algorithm() {
     test_compression_method(&[EncoderMethod::LZMA.into()]).await;
 }
 
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn compress_with_lzma_options() {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+
+    let mut sizes = Vec::new();
+    for level in [0, 9] {
+        let mut options = LzmaOptions::from_level(level);
+        options.set_dictionary_size(1 << 20);
+        options.set_lc_lp_pb(4, 0, 0);
+        test_compression_method(&[options.clone().into()]).await;
+
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_content_methods(vec![options.into()]);
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file("decompress_x86.exe"),
+                Some(content.as_slice()),
+            )
+            .await
+            .unwrap();
+        let data = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+            .await
+            .unwrap();
+        let blocks = reader.archive().block_details();
+        assert_eq!(
+            blocks[0].methods[0].properties,
+            CoderProperties::Lzma {
+                dict_size: 1 << 20,
+                lc: 4,
+                lp: 0,
+                pb: 0,
+            }
+        );
+        sizes.push(blocks[0].packed_size);
+        assert_eq!(
+            reader.read_file("decompress_x86.exe").await.unwrap(),
+            content
+        );
+    }
+    assert!(sizes[1] < sizes[0], "{sizes:?}");
+}
+
 #[cfg(feature = "compress")]
 #[tokio::test]
 async fn compress_with_lzma2_algorithm() {
@@ -442,8 +550,727 @@ async fn compress_with_lz4_skippable_algorithm() {
     .await;
 }
 
-#[cfg(all(feature = "compress", feature = "lz4"))]
+#[cfg(all(feature = "compress", feature = "zstd"))]
 #[tokio::test]
 async fn compress_with_zstd_algorithm() {
     test_compression_method(&[EncoderMethod::ZSTD.into()]).await;
 }
+
+#[cfg(all(feature = "compress", feature = "zstd"))]
+#[tokio::test]
+async fn compress_with_zstd_single_frame_in_several_chunks() {
+    // The single frame is compressed in chunks of 1 MiB on the blocking thread pool.
+    let content: Vec<u8> = (0..3 * 1024 * 1024 + 100)
+        .map(|i: u32| ((i / 7) ^ (i % 251)) as u8)
+        .collect();
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![ZstandardOptions::from_level(3).into()]);
+    writer
+        .push_archive_entry(ArchiveEntry::new_file("data.bin"), Some(content.as_slice()))
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+    assert!(data.len() < content.len() / 2);
+
+    let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(reader.read_file("data.bin").await.unwrap(), content);
+}
+
+#[cfg(all(feature = "compress", feature = "zstd"))]
+#[tokio::test]
+async fn compress_with_zstd_skippable_algorithm() {
+    test_compression_method(&[ZstandardOptions::default()
+        .with_skippable_frame_size(64 * 1024)
+        .into()])
+    .await;
+}
+
+/// Compresses frames on several threads, and decodes them on one and several threads.
+#[cfg(feature = "compress")]
+#[allow(dead_code)]
+async fn test_multi_threaded_frames(method: EncoderConfiguration) {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+
+    let mut writer = ArchiveWriter::new(futures_lite::io::Cursor::new(Vec::<u8>::new()))
+        .await
+        .unwrap();
+    writer.set_content_methods(vec![method]);
+    writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("decompress_x86.exe"),
+            Some(content.as_slice()),
+        )
+        .await
+        .unwrap();
+    let bytes = writer.finish().await.unwrap().into_inner();
+
+    for threads in [1, 4] {
+        let mut reader = ArchiveReader::open_from_bytes(bytes.clone(), Password::empty())
+            .await
+            .unwrap();
+        reader.set_thread_count(threads);
+        let data = reader.read_file("decompress_x86.exe").await.unwrap();
+        assert!(data == content, "content differs with {threads} threads");
+    }
+}
+
+#[cfg(all(feature = "compress", feature = "brotli"))]
+#[tokio::test]
+async fn compress_with_brotli_multi_threaded() {
+    test_multi_threaded_frames(
+        BrotliOptions::from_quality(5)
+            .with_skippable_frame_size(64 * 1024)
+            .with_threads(4)
+            .into(),
+    )
+    .await;
+}
+
+#[cfg(all(feature = "compress", feature = "lz4"))]
+#[tokio::test]
+async fn compress_with_lz4_multi_threaded() {
+    test_multi_threaded_frames(Lz4Options::default().with_threads(4).into()).await;
+}
+
+#[cfg(all(feature = "compress", feature = "brotli"))]
+#[tokio::test]
+async fn compress_with_brotli_tuning_options() {
+    let options = BrotliOptions::from_quality(9)
+        .with_window_size(16)
+        .with_mode(BrotliMode::Text);
+    test_compression_method(&[options.with_skippable_frame_size(0).into()]).await;
+    test_multi_threaded_frames(options.with_threads(2).into()).await;
+}
+
+#[cfg(all(feature = "compress", feature = "lz4"))]
+#[tokio::test]
+async fn compress_with_lz4_tuning_options() {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+
+    let mut sizes = Vec::new();
+    for level in [1, 9] {
+        let options = Lz4Options::from_level(level).with_block_size(Lz4BlockSize::Max256KB);
+        test_multi_threaded_frames(options.with_threads(2).into()).await;
+
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_content_methods(vec![options.into()]);
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file("decompress_x86.exe"),
+                Some(content.as_slice()),
+            )
+            .await
+            .unwrap();
+        let data = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+            .await
+            .unwrap();
+        let blocks = reader.archive().block_details();
+        assert_eq!(
+            blocks[0].methods[0].properties,
+            CoderProperties::Raw(vec![1, 0, level as u8])
+        );
+        sizes.push(blocks[0].packed_size);
+        assert_eq!(
+            reader.read_file("decompress_x86.exe").await.unwrap(),
+            content
+        );
+    }
+    assert!(sizes[1] < sizes[0], "{sizes:?}");
+}
+
+#[cfg(all(feature = "compress", feature = "zstd"))]
+#[tokio::test]
+async fn compress_with_zstd_tuning_options() {
+    let options = ZstandardOptions::from_level(9)
+        .with_window_log(20)
+        .with_long_distance_matching(true)
+        .with_strategy(ZstdStrategy::BtOpt);
+    test_compression_method(&[options.into()]).await;
+    test_multi_threaded_frames(options.with_threads(2).into()).await;
+}
+
+#[cfg(all(feature = "compress", feature = "zstd"))]
+#[tokio::test]
+async fn compress_with_zstd_multi_threaded() {
+    test_multi_threaded_frames(
+        ZstandardOptions::from_level(3)
+            .with_skippable_frame_size(64 * 1024)
+            .with_threads(4)
+            .into(),
+    )
+    .await;
+}
+
+#[cfg(all(feature = "compress", feature = "bzip2"))]
+#[tokio::test]
+async fn compress_with_bzip2_multi_threaded() {
+    let options = Bzip2Options::from_level(1).with_threads(4);
+    test_multi_threaded_frames(options.into()).await;
+
+    // Runs expand the most in bzip2's first run-length encoding, and empty input has no block.
+    for content in [vec![0u8; 1024 * 1024], Vec::new()] {
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_content_methods(vec![options.into()]);
+        writer
+            .push_archive_entry(ArchiveEntry::new_file("data.bin"), Some(content.as_slice()))
+            .await
+            .unwrap();
+        let bytes = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(bytes, Password::empty())
+            .await
+            .unwrap();
+        assert_eq!(reader.read_file("data.bin").await.unwrap(), content);
+    }
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn cancel_push_source_path_between_buffers() {
+    let temp_dir = tempdir().unwrap();
+    let folder = temp_dir.path().join("folder");
+    async_fs::create_dir(&folder).await.unwrap();
+    async_fs::write(folder.join("big.bin"), vec![7u8; 64 * 1024])
+        .await
+        .unwrap();
+
+    let token = CancellationToken::new();
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_cancellation_token(token.clone());
+    writer.set_progress_observer(move |event: ProgressEvent<'_>| {
+        if let ProgressEvent::BytesIn(_) = event {
+            token.cancel();
+        }
+    });
+
+    let result = writer.push_source_path(&folder, |_| async { true }).await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+
+    let result = writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("other.txt"),
+            Some(futures_lite::io::Cursor::new(&b"other"[..])),
+        )
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(writer.finish().await.is_err());
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn cancel_push_archive_entry_in_the_middle_of_an_entry() {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    let token = CancellationToken::new();
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_cancellation_token(token.clone());
+    let buffers = Arc::new(AtomicUsize::new(0));
+    let observed = buffers.clone();
+    writer.set_progress_observer(move |event: ProgressEvent<'_>| {
+        if let ProgressEvent::BytesIn(_) = event {
+            if observed.fetch_add(1, Ordering::Relaxed) == 1 {
+                token.cancel();
+            }
+        }
+    });
+
+    let big = vec![7u8; 4 * 1024 * 1024];
+    let result = writer
+        .push_archive_entry(ArchiveEntry::new_file("big.bin"), Some(big.as_slice()))
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+    assert!(buffers.load(Ordering::Relaxed) >= 2);
+
+    // The writer stays unusable even with a token that is not cancelled.
+    writer.set_cancellation_token(CancellationToken::new());
+    let result = writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("other.txt"),
+            Some(futures_lite::io::Cursor::new(&b"other"[..])),
+        )
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    let result = writer
+        .push_archive_entries(
+            vec![ArchiveEntry::new_file("solid.txt")],
+            vec![futures_lite::io::Cursor::new(&b"solid"[..]).into()],
+        )
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(writer.finish().await.is_err());
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn directory_tree_with_normalised_lookup() {
+    use futures_lite::io::Cursor;
+
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("docs\\guide\\Cafe\u{301}.txt"),
+            Some(Cursor::new(&b"coffee"[..])),
+        )
+        .await
+        .unwrap();
+    writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("docs/readme.md"),
+            Some(Cursor::new(&b"readme"[..])),
+        )
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+        .await
+        .unwrap();
+
+    let root = reader.list_dir("").unwrap();
+    assert_eq!(root.len(), 1);
+    assert_eq!(root[0].name(), "docs");
+    assert!(root[0].is_implicit_directory());
+    assert!(root[0].is_directory());
+
+    let names: Vec<_> = reader
+        .list_dir("./docs/")
+        .unwrap()
+        .iter()
+        .map(|node| node.path().to_string())
+        .collect();
+    assert_eq!(names, ["docs/guide", "docs/readme.md"]);
+
+    let cafe = reader
+        .lookup("docs/guide/Caf\u{e9}.txt")
+        .expect("NFC lookup");
+    assert_eq!(cafe.entry().unwrap().size(), 6);
+    assert_eq!(cafe.parent().unwrap().path(), "docs/guide");
+    assert!(reader.list_dir("docs/readme.md").is_err());
+
+    assert!(reader.lookup("DOCS/README.MD").is_none());
+    reader.set_case_insensitive_lookup(true);
+    assert_eq!(
+        reader.read_file(".\\DOCS\\README.MD").await.unwrap(),
+        b"readme"
+    );
+    assert_eq!(
+        reader.read_file("docs/guide/Caf\u{e9}.txt").await.unwrap(),
+        b"coffee"
+    );
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn check_password_of_encrypted_blocks() {
+    for method in [
+        EncoderMethod::LZMA2,
+        EncoderMethod::LZMA,
+        EncoderMethod::COPY,
+    ] {
+        let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+        writer.set_encrypt_header(false);
+        writer.set_content_methods(vec![
+            AesEncoderOptions::new("rust".into()).into(),
+            method.into(),
+        ]);
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file("file.txt"),
+                Some(b"some content to encrypt".as_slice()),
+            )
+            .await
+            .unwrap();
+        let data = writer.finish().await.unwrap().into_inner();
+
+        let mut reader = ArchiveReader::open_from_bytes(data.clone(), "rust".into())
+            .await
+            .unwrap();
+        assert!(reader.check_password().await.unwrap(), "{method:?}");
+        let mut reader = ArchiveReader::open_from_bytes(data, "wrong".into())
+            .await
+            .unwrap();
+        assert!(!reader.check_password().await.unwrap(), "{method:?}");
+    }
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn inspect_encrypted_header() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![
+        AesEncoderOptions::new("rust".into()).into(),
+        EncoderMethod::LZMA2.into(),
+    ]);
+    // The header is only encoded, and thus encrypted, if that makes it smaller.
+    for i in 0..100 {
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file(&format!("some/nested/directory/file{i}.txt")),
+                Some(b"content".as_slice()),
+            )
+            .await
+            .unwrap();
+    }
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let info = Archive::inspect_encryption_from_bytes(&data).await.unwrap();
+    assert!(info.is_header_encrypted());
+    assert_eq!(info.blocks, None);
+
+    let result = ArchiveReader::open_from_bytes(data.clone(), Password::empty()).await;
+    assert!(matches!(result, Err(Error::PasswordRequired)));
+
+    let reader = ArchiveReader::open_from_bytes(data, "rust".into())
+        .await
+        .unwrap();
+    let info = reader.archive().encryption_info();
+    assert_eq!(info.header_num_cycles_power, info.max_num_cycles_power());
+    assert_eq!(info.blocks.map(|blocks| blocks.len()), Some(100));
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn change_password_keeps_blocks_and_header_encryption() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![
+        AesEncoderOptions::new("old".into()).into(),
+        EncoderMethod::LZMA2.into(),
+    ]);
+    writer
+        .push_archive_entry::<&[u8]>(ArchiveEntry::new_directory("dir"), None)
+        .await
+        .unwrap();
+    for i in 0..100 {
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file(&format!("dir/file{i}.txt")),
+                Some(format!("content of file {i}").as_bytes()),
+            )
+            .await
+            .unwrap();
+    }
+    writer
+        .push_archive_entries(
+            vec![
+                ArchiveEntry::new_file("solid/a.txt"),
+                ArchiveEntry::new_file("solid/b.txt"),
+            ],
+            vec![
+                SourceReader::new(b"first solid entry".as_slice()),
+                SourceReader::new(b"second solid entry".as_slice()),
+            ],
+        )
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(data, "old".into())
+        .await
+        .unwrap();
+    let changed = reader
+        .change_password(futures_lite::io::Cursor::new(Vec::new()), &"new".into())
+        .await
+        .unwrap()
+        .into_inner();
+    let result = ArchiveReader::open_from_bytes(changed.clone(), "old".into()).await;
+    assert!(matches!(result, Err(Error::MaybeBadPassword(_))));
+
+    let mut reader = ArchiveReader::open_from_bytes(changed, "new".into())
+        .await
+        .unwrap();
+    let info = reader.archive().encryption_info();
+    assert!(info.is_header_encrypted());
+    assert_eq!(info.blocks.map(|blocks| blocks.len()), Some(101));
+    assert_eq!(reader.archive().files.len(), 103);
+    assert!(reader.archive().files[0].is_directory());
+    assert_eq!(
+        reader.read_file("dir/file42.txt").await.unwrap(),
+        b"content of file 42"
+    );
+    assert_eq!(
+        reader.read_file("solid/b.txt").await.unwrap(),
+        b"second solid entry"
+    );
+
+    let removed = reader
+        .change_password(
+            futures_lite::io::Cursor::new(Vec::new()),
+            &Password::empty(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(
+        !Archive::inspect_encryption_from_bytes(&removed)
+            .await
+            .unwrap()
+            .is_encrypted()
+    );
+    let mut reader = ArchiveReader::open_from_bytes(removed, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(
+        reader.read_file("dir/file7.txt").await.unwrap(),
+        b"content of file 7"
+    );
+    assert_eq!(
+        reader.read_file("solid/a.txt").await.unwrap(),
+        b"first solid entry"
+    );
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn key_derivation_cost_is_bounded() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_encrypt_header(false);
+    let mut aes = AesEncoderOptions::new("rust".into());
+    aes.num_cycles_power = 10;
+    writer.set_content_methods(vec![aes.into(), EncoderMethod::LZMA2.into()]);
+    writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("file.txt"),
+            Some(b"some content to encrypt".as_slice()),
+        )
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(data, "rust".into())
+        .await
+        .unwrap();
+    reader.set_max_num_cycles_power(9);
+    assert!(matches!(
+        reader.read_file("file.txt").await,
+        Err(Error::KeyDerivationTooExpensive {
+            num_cycles_power: 10,
+            max_num_cycles_power: 9,
+        })
+    ));
+    assert!(matches!(
+        reader.check_password().await,
+        Err(Error::KeyDerivationTooExpensive { .. })
+    ));
+
+    reader.set_max_num_cycles_power(10);
+    assert_eq!(
+        reader.read_file("file.txt").await.unwrap(),
+        b"some content to encrypt"
+    );
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn key_derivation_cost_of_encrypted_header_is_bounded_at_open() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    let mut aes = AesEncoderOptions::new("rust".into());
+    aes.num_cycles_power = 10;
+    writer.set_content_methods(vec![aes.into(), EncoderMethod::LZMA2.into()]);
+    // Small headers are stored as they are, enough entries make it encoded and encrypted.
+    for i in 0..100 {
+        writer
+            .push_archive_entry(
+                ArchiveEntry::new_file(&format!("file{i}.txt")),
+                Some(format!("content of file {i}").as_bytes()),
+            )
+            .await
+            .unwrap();
+    }
+    let data = writer.finish().await.unwrap().into_inner();
+    let options = || ReaderOptions::default().with_password("rust".into());
+
+    let result = ArchiveReader::open_from_bytes_with_options(
+        data.clone(),
+        options().with_max_num_cycles_power(9),
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(Error::KeyDerivationTooExpensive {
+            num_cycles_power: 10,
+            max_num_cycles_power: 9,
+        })
+    ));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = ArchiveReader::open_from_bytes_with_options(
+        data.clone(),
+        options().with_cancellation_token(token),
+    )
+    .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+
+    let mut reader =
+        ArchiveReader::open_from_bytes_with_options(data, options().with_max_num_cycles_power(10))
+            .await
+            .unwrap();
+    assert_eq!(
+        reader.read_file("file42.txt").await.unwrap(),
+        b"content of file 42"
+    );
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn checksum_error_names_entry_and_block() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![EncoderMethod::COPY.into()]);
+    for (name, content) in [("a.txt", b"first file"), ("b.txt", b"other file")] {
+        writer
+            .push_archive_entry(ArchiveEntry::new_file(name), Some(content.as_slice()))
+            .await
+            .unwrap();
+    }
+    let mut data = writer.finish().await.unwrap().into_inner();
+    let position = data
+        .windows(10)
+        .position(|window| window == b"other file")
+        .unwrap();
+    data[position] ^= 0xFF;
+
+    let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(reader.read_file("a.txt").await.unwrap(), b"first file");
+    let error = reader.read_file("b.txt").await.unwrap_err();
+    assert!(matches!(
+        error.without_context(),
+        Error::ChecksumVerificationFailed
+    ));
+    let context = error.context().unwrap();
+    assert_eq!(context.entry.as_deref(), Some("b.txt"));
+    assert_eq!(context.block_index, Some(1));
+    assert_eq!(context.methods, ["COPY"]);
+    assert!(context.offset.is_some());
+    assert!(
+        error
+            .to_string()
+            .starts_with("checksum verification failed (entry 'b.txt', block 1, methods COPY")
+    );
+    let source = std::error::Error::source(&error).unwrap();
+    assert_eq!(source.to_string(), "checksum verification failed");
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn io_error_display_includes_the_error() {
+    let error = Error::from(std::io::Error::other("disk full"));
+    assert_eq!(error.to_string(), "I/O error: disk full");
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn compress_with_bcj2_lzma_algorithm() {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![EncoderMethod::BCJ2_FILTER.into()]);
+    let entry = writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("decompress_x86.exe"),
+            Some(content.as_slice()),
+        )
+        .await
+        .unwrap();
+    let compressed_size = entry.compressed_size;
+    writer
+        .push_archive_entries(
+            vec![
+                ArchiveEntry::new_file("solid/a.exe"),
+                ArchiveEntry::new_file("solid/b.exe"),
+            ],
+            vec![
+                SourceReader::new(content.as_slice()),
+                SourceReader::new(&content[1000..]),
+            ],
+        )
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(data, Password::empty())
+        .await
+        .unwrap();
+    let blocks = reader.archive().block_details();
+    assert_eq!(blocks.len(), 2);
+    for block in &blocks {
+        let names: Vec<_> = block.methods.iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["BCJ2", "LZMA", "LZMA", "LZMA", "LZMA"]);
+    }
+    assert_eq!(blocks[0].packed_size, compressed_size);
+    assert_eq!(blocks[1].entry_count, 2);
+    assert_eq!(
+        reader.read_file("decompress_x86.exe").await.unwrap(),
+        content
+    );
+    assert_eq!(reader.read_file("solid/a.exe").await.unwrap(), content);
+    assert_eq!(
+        reader.read_file("solid/b.exe").await.unwrap(),
+        &content[1000..]
+    );
+}
+
+#[cfg(all(feature = "compress", feature = "aes256"))]
+#[tokio::test]
+async fn compress_with_encrypted_bcj2_lzma2_algorithm() {
+    let content = async_fs::read("tests/resources/decompress_x86.exe")
+        .await
+        .unwrap();
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![
+        AesEncoderOptions::new("sevenz-rust".into()).into(),
+        EncoderMethod::LZMA2.into(),
+        EncoderMethod::BCJ2_FILTER.into(),
+    ]);
+    writer
+        .push_archive_entry(
+            ArchiveEntry::new_file("decompress_x86.exe"),
+            Some(content.as_slice()),
+        )
+        .await
+        .unwrap();
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(data, "sevenz-rust".into())
+        .await
+        .unwrap();
+    let blocks = reader.archive().block_details();
+    let names: Vec<_> = blocks[0].methods.iter().map(|m| m.name()).collect();
+    assert_eq!(
+        names.iter().filter(|name| *name == "AES256_SHA256").count(),
+        4
+    );
+    assert!(blocks[0].encrypted);
+    assert_eq!(
+        reader.read_file("decompress_x86.exe").await.unwrap(),
+        content
+    );
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn bcj2_must_be_the_last_method() {
+    let mut writer = ArchiveWriter::create_in_memory().await.unwrap();
+    writer.set_content_methods(vec![
+        EncoderMethod::BCJ2_FILTER.into(),
+        EncoderMethod::LZMA2.into(),
+    ]);
+    let error = writer
+        .push_archive_entry(ArchiveEntry::new_file("a.exe"), Some(b"data".as_slice()))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Unsupported(_)));
+}
@@ -21,3 +21,264 @@ async fn test_decompress_file_with_password() {
             .starts_with("7z is the new archive format, providing high compression ratio.")
     )
 }
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn list_encrypted_entries() {
+    use std::path::PathBuf;
+
+    use async_sevenz::{Archive, CoderProperties, Password};
+
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/encrypted.7z");
+    let archive = Archive::open_with_password(&source_file, &Password::from("sevenz-rust"))
+        .await
+        .unwrap();
+
+    let blocks = archive.block_details();
+    assert!(blocks[0].encrypted);
+    assert!(matches!(
+        blocks[0].methods[0].properties,
+        CoderProperties::Aes256Sha256 {
+            num_cycles_power: 19,
+            ..
+        }
+    ));
+    let details = archive.entry_details();
+    assert!(!details[0].encrypted);
+    assert!(details[1].encrypted && details[2].encrypted);
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn password_provider_is_asked_again_after_wrong_password() {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use async_sevenz::{ArchiveReader, Password};
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let mut reader = ArchiveReader::open_with_password_provider(
+        "tests/resources/encrypted.7z",
+        move |attempt: usize| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            ["wrong", "also wrong", "sevenz-rust"]
+                .get(attempt)
+                .map(|p| Password::from(*p))
+        },
+    )
+    .await
+    .unwrap();
+
+    let data = reader.read_file("encripted/7zFormat.txt").await.unwrap();
+    assert!(data.starts_with(b"7z is the new archive format"));
+    assert_eq!(requests.load(Ordering::Relaxed), 3);
+
+    // The confirmed password is reused without asking again.
+    reader.read_file("encripted/7zFormat.txt").await.unwrap();
+    assert_eq!(requests.load(Ordering::Relaxed), 3);
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn password_provider_giving_up_fails() {
+    use async_sevenz::{ArchiveReader, Error, Password};
+
+    let result = async {
+        let mut reader = ArchiveReader::open_with_password_provider(
+            "tests/resources/encrypted.7z",
+            |attempt: usize| (attempt == 0).then(|| Password::from("wrong")),
+        )
+        .await?;
+        reader.read_file("encripted/7zFormat.txt").await
+    }
+    .await;
+    assert!(matches!(result, Err(Error::MaybeBadPassword(_))));
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn check_password_rejects_wrong_password() {
+    use async_sevenz::{ArchiveReader, Password};
+
+    let mut reader = ArchiveReader::open("tests/resources/encrypted.7z", "sevenz-rust".into())
+        .await
+        .unwrap();
+    assert!(reader.check_password().await.unwrap());
+
+    let mut reader = ArchiveReader::open("tests/resources/encrypted.7z", "wrong".into())
+        .await
+        .unwrap();
+    assert!(!reader.check_password().await.unwrap());
+
+    let mut reader = ArchiveReader::open("tests/resources/encrypted.7z", Password::empty())
+        .await
+        .unwrap();
+    assert!(!reader.check_password().await.unwrap());
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn inspect_encryption_without_password() {
+    use async_sevenz::{Archive, EncryptedBlock};
+
+    let info = Archive::inspect_encryption("tests/resources/encrypted.7z")
+        .await
+        .unwrap();
+    assert!(info.is_encrypted());
+    assert!(!info.is_header_encrypted());
+    assert_eq!(
+        info.blocks,
+        Some(vec![EncryptedBlock {
+            index: 0,
+            num_cycles_power: 19,
+        }])
+    );
+    assert_eq!(info.max_num_cycles_power(), Some(19));
+}
+
+#[cfg(all(feature = "aes256", feature = "compress"))]
+#[tokio::test]
+async fn change_password_of_7zip_archive() {
+    use async_sevenz::{ArchiveReader, Password};
+    use futures_lite::io::Cursor;
+
+    async fn contents(reader: &mut ArchiveReader<Cursor<Vec<u8>>>) -> Vec<(String, Vec<u8>)> {
+        let names: Vec<_> = reader
+            .archive()
+            .files
+            .iter()
+            .filter(|file| file.has_stream())
+            .map(|file| file.name().to_string())
+            .collect();
+        let mut contents = Vec::new();
+        for name in names {
+            let data = reader.read_file(&name).await.unwrap();
+            contents.push((name, data));
+        }
+        contents
+    }
+
+    let data = std::fs::read("tests/resources/encrypted.7z").unwrap();
+    let mut reader = ArchiveReader::open_from_bytes(data, "sevenz-rust".into())
+        .await
+        .unwrap();
+    let expected = contents(&mut reader).await;
+
+    let changed = reader
+        .change_password(Cursor::new(Vec::new()), &"new password".into())
+        .await
+        .unwrap()
+        .into_inner();
+    let mut changed_reader = ArchiveReader::open_from_bytes(changed.clone(), "sevenz-rust".into())
+        .await
+        .unwrap();
+    assert!(!changed_reader.check_password().await.unwrap());
+    let mut changed_reader = ArchiveReader::open_from_bytes(changed, "new password".into())
+        .await
+        .unwrap();
+    let info = changed_reader.archive().encryption_info();
+    assert_eq!(info.max_num_cycles_power(), Some(19));
+    assert_eq!(contents(&mut changed_reader).await, expected);
+
+    let removed = changed_reader
+        .change_password(Cursor::new(Vec::new()), &Password::empty())
+        .await
+        .unwrap()
+        .into_inner();
+    let mut removed_reader = ArchiveReader::open_from_bytes(removed, Password::empty())
+        .await
+        .unwrap();
+    assert!(!removed_reader.archive().encryption_info().is_encrypted());
+    assert_eq!(contents(&mut removed_reader).await, expected);
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn password_provider_is_asked_again_when_block_fails_its_crc() {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use async_sevenz::{ArchiveReader, Error, Password};
+
+    // Corrupt the encrypted pack stream past its first bytes: the header check of the block passes
+    // with the right password, but the decoded data no longer matches its CRC.
+    let mut data = std::fs::read("tests/resources/encrypted.7z").unwrap();
+    data[32 + 100] ^= 0xFF;
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("corrupted.7z");
+    std::fs::write(&path, data).unwrap();
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let mut reader = ArchiveReader::open_with_password_provider(path, move |attempt: usize| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        (attempt == 0).then(|| Password::from("sevenz-rust"))
+    })
+    .await
+    .unwrap();
+
+    let result = reader.read_file("encripted/7zFormat.txt").await;
+    assert!(
+        matches!(result, Err(Error::MaybeBadPassword(_))),
+        "{result:?}"
+    );
+    assert_eq!(requests.load(Ordering::Relaxed), 2);
+}
+
+#[cfg(all(feature = "aes256", feature = "compress"))]
+#[tokio::test]
+async fn change_password_with_wrong_old_password_fails() {
+    use async_sevenz::{ArchiveReader, Error};
+    use futures_lite::io::Cursor;
+
+    let data = std::fs::read("tests/resources/encrypted.7z").unwrap();
+    let mut reader = ArchiveReader::open_from_bytes(data, "wrong".into())
+        .await
+        .unwrap();
+    let result = reader
+        .change_password(Cursor::new(Vec::new()), &"new password".into())
+        .await;
+    assert!(matches!(result, Err(Error::MaybeBadPassword(_))));
+}
+
+#[cfg(feature = "aes256")]
+#[tokio::test]
+async fn decompress_encrypted_bcj2_archive() {
+    use async_sevenz::{ArchiveReader, Error};
+
+    // Written by this crate with AES, LZMA2 and BCJ2 as content methods, since no 7-Zip binary was
+    // at hand to produce it. Each of the four BCJ2 input streams is encrypted on its own.
+    let path = "tests/resources/encrypted_lzma2_bcj2.7z";
+    let expected = std::fs::read("tests/resources/decompress_x86.exe").unwrap();
+
+    let mut reader = ArchiveReader::open(path, "sevenz-rust".into())
+        .await
+        .unwrap();
+    let blocks = reader.archive().block_details();
+    let names: Vec<_> = blocks[0].methods.iter().map(|m| m.name()).collect();
+    assert_eq!(names.iter().filter(|name| **name == "BCJ2").count(), 1);
+    assert_eq!(
+        names
+            .iter()
+            .filter(|name| **name == "AES256_SHA256")
+            .count(),
+        4
+    );
+    assert_eq!(
+        reader.read_file("decompress_x86.exe").await.unwrap(),
+        expected
+    );
+
+    let mut reader = ArchiveReader::open(path, "wrong".into()).await.unwrap();
+    let result = reader.read_file("decompress_x86.exe").await;
+    assert!(
+        matches!(result, Err(Error::MaybeBadPassword(_))),
+        "{result:?}"
+    );
+}
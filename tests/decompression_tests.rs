@@ -2,8 +2,14 @@ use futures_lite::StreamExt;
 use futures_lite::io::{AsyncReadExt, Cursor};
 use std::path::PathBuf;
 
-use async_sevenz::decompress_file;
-use async_sevenz::{Archive, ArchiveReader, BlockDecoder, Password};
+use async_sevenz::{
+    Archive, ArchiveReader, BlockDecoder, CancellationToken, CoderProperties, EncoderMethod, Error,
+    Password,
+};
+use async_sevenz::{
+    ProgressEvent, decompress_file, decompress_file_with_cancellation, decompress_file_with_filter,
+    decompress_file_with_progress,
+};
 use tempfile::tempdir;
 
 #[tokio::test]
@@ -221,6 +227,46 @@ async fn decompress_zstdmt_brotli_file() {
     );
 }
 
+/// Streams written without known size end with an end marker, which 7z archives may contain too.
+#[tokio::test]
+async fn decompress_lzma_with_end_marker() {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/lzma_end_marker.7z");
+
+    let temp_dir = tempdir().unwrap();
+    let target = temp_dir.path().to_path_buf();
+
+    let mut license_path = target.clone();
+    license_path.push("apache2.txt");
+
+    decompress_file(source_file, target).await.unwrap();
+
+    assert_eq!(
+        async_fs::read(license_path).await.unwrap(),
+        async_fs::read("tests/resources/apache2.txt").await.unwrap()
+    );
+}
+
+#[cfg(feature = "deflate")]
+#[tokio::test]
+async fn decompress_deflate64_file() {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/deflate64.7z");
+
+    let temp_dir = tempdir().unwrap();
+    let target = temp_dir.path().to_path_buf();
+
+    let mut license_path = target.clone();
+    license_path.push("apache2.txt");
+
+    decompress_file(source_file, target).await.unwrap();
+
+    assert_eq!(
+        async_fs::read(license_path).await.unwrap(),
+        async_fs::read("tests/resources/apache2.txt").await.unwrap()
+    );
+}
+
 #[cfg(feature = "lz4")]
 #[tokio::test]
 async fn decompress_zstdmt_lz4_file() {
@@ -327,3 +373,305 @@ async fn test_get_file_by_path() {
         assert_eq!(&data0, &data1);
     }
 }
+
+#[tokio::test]
+async fn decompress_file_with_filter_solid() {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/solid.7z");
+    let temp_dir = tempdir().unwrap();
+    let target = temp_dir.path().to_path_buf();
+
+    decompress_file_with_filter(source_file, &target, |entry| {
+        entry.name() == "test/test1.txt"
+    })
+    .await
+    .unwrap();
+
+    assert!(target.join("test/test1.txt").exists());
+    assert!(!target.join("test/test2.txt").exists());
+}
+
+#[tokio::test]
+async fn block_decoder_skips_unselected_blocks() {
+    let archive = Archive::open("tests/resources/non_solid.7z").await.unwrap();
+    let password = Password::empty();
+    let target = "test/test2.txt";
+    let target_block = archive
+        .files
+        .iter()
+        .position(|entry| entry.name() == target)
+        .and_then(|index| archive.stream_map.file_block_index[index])
+        .unwrap();
+    let data = async_fs::read("tests/resources/non_solid.7z")
+        .await
+        .unwrap();
+
+    let decoded = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    for block_index in 0..archive.blocks.len() {
+        // Blocks without a selected entry must not read any data, so give them nothing to read.
+        let mut cursor = if block_index == target_block {
+            Cursor::new(&data[..])
+        } else {
+            Cursor::new(&[][..])
+        };
+        BlockDecoder::new(1, block_index, &archive, &password, &mut cursor)
+            .for_each_selected_entries(&mut |entry| entry.name() == target, &mut |entry, reader| {
+                let decoded = decoded.clone();
+                let name = entry.name().to_string();
+                Box::pin(async move {
+                    let mut buf = Vec::new();
+                    AsyncReadExt::read_to_end(reader, &mut buf).await?;
+                    decoded.lock().unwrap().push((name, buf.len()));
+                    Ok(true)
+                })
+            })
+            .await
+            .unwrap();
+    }
+    assert_eq!(*decoded.lock().unwrap(), vec![(target.to_string(), 2654)]);
+}
+
+#[tokio::test]
+async fn decompress_file_with_progress_solid() {
+    use std::sync::{Arc, Mutex};
+
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/solid.7z");
+    let temp_dir = tempdir().unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let bytes = Arc::new(Mutex::new((0u64, 0u64)));
+    let totals = Arc::new(Mutex::new(None));
+    {
+        let events = events.clone();
+        let bytes = bytes.clone();
+        let totals = totals.clone();
+        decompress_file_with_progress(
+            source_file,
+            temp_dir.path(),
+            move |event: ProgressEvent<'_>| match event {
+                ProgressEvent::Totals(t) => *totals.lock().unwrap() = Some(t),
+                ProgressEvent::BlockStarted(index) => {
+                    events.lock().unwrap().push(format!("block {index}"))
+                }
+                ProgressEvent::EntryStarted(entry) => events
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {}", entry.name())),
+                ProgressEvent::EntryFinished(entry) => events
+                    .lock()
+                    .unwrap()
+                    .push(format!("finish {}", entry.name())),
+                ProgressEvent::BytesIn(n) => bytes.lock().unwrap().0 += n,
+                ProgressEvent::BytesOut(n) => bytes.lock().unwrap().1 += n,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let totals = totals.lock().unwrap().expect("totals reported");
+    assert_eq!(totals.entries, 3);
+    assert_eq!(totals.unpacked_size, 3447 + 2654);
+    let (bytes_in, bytes_out) = *bytes.lock().unwrap();
+    assert!(bytes_in > 0 && bytes_in <= totals.packed_size);
+    assert_eq!(bytes_out, totals.unpacked_size);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "block 0",
+            "start test/test1.txt",
+            "finish test/test1.txt",
+            "start test/test2.txt",
+            "finish test/test2.txt",
+            "start test",
+            "finish test",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn decompress_file_with_cancelled_token() {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/non_solid.7z");
+    let temp_dir = tempdir().unwrap();
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = decompress_file_with_cancellation(source_file, temp_dir.path(), token).await;
+
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(!temp_dir.path().join("test/test1.txt").exists());
+    assert!(!temp_dir.path().join("test/test2.txt").exists());
+}
+
+#[tokio::test]
+async fn block_decoder_cancelled_between_entries() {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/solid.7z");
+    let archive = Archive::open(&source_file).await.unwrap();
+    let mut source = Cursor::new(async_fs::read(&source_file).await.unwrap());
+    let password = Password::empty();
+    let token = CancellationToken::new();
+
+    let mut decoder = BlockDecoder::new(1, 0, &archive, &password, &mut source);
+    decoder.set_cancellation_token(token.clone());
+    let mut names = Vec::new();
+    let result = decoder
+        .for_each_entries(&mut |entry, reader| {
+            names.push(entry.name().to_string());
+            let token = token.clone();
+            Box::pin(async move {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data).await?;
+                token.cancel();
+                Ok(true)
+            })
+        })
+        .await;
+
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert_eq!(names, ["test/test1.txt"]);
+}
+
+#[tokio::test]
+async fn entries_iterate_and_skip() {
+    for archive in ["solid.7z", "non_solid.7z"] {
+        let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        source_file.push("tests/resources");
+        source_file.push(archive);
+        let mut reader = ArchiveReader::open(&source_file, Password::empty())
+            .await
+            .unwrap();
+        let expected = reader.read_file("test/test2.txt").await.unwrap();
+
+        let mut names = Vec::new();
+        let mut data = Vec::new();
+        let mut entries = reader.entries();
+        while let Some((entry, mut entry_reader)) = entries.next().await.unwrap() {
+            names.push(entry.name().to_string());
+            if entry.name() == "test/test2.txt" {
+                assert_eq!(entry_reader.remaining(), 2654);
+                entry_reader.read_to_end(&mut data).await.unwrap();
+                assert_eq!(entry_reader.remaining(), 0);
+            }
+        }
+
+        assert_eq!(names, ["test/test1.txt", "test/test2.txt", "test"]);
+        assert_eq!(data, expected);
+    }
+}
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn entries_return_entries_without_data_last() {
+    use async_sevenz::{ArchiveEntry, ArchiveWriter};
+
+    let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).await.unwrap();
+    writer
+        .push_archive_entry(ArchiveEntry::new_file("a.txt"), Some(&b"first"[..]))
+        .await
+        .unwrap();
+    writer
+        .push_archive_entry::<&[u8]>(ArchiveEntry::new_directory("dir"), None)
+        .await
+        .unwrap();
+    writer
+        .push_archive_entry(ArchiveEntry::new_file("dir/b.txt"), Some(&b"second"[..]))
+        .await
+        .unwrap();
+    let bytes = writer.finish().await.unwrap().into_inner();
+
+    let mut reader = ArchiveReader::open_from_bytes(bytes, Password::empty())
+        .await
+        .unwrap();
+    let stored: Vec<_> = (reader.archive().files.iter())
+        .map(|file| file.name().to_string())
+        .collect();
+    assert_eq!(stored, ["a.txt", "dir", "dir/b.txt"]);
+
+    let mut entries = reader.entries();
+    let mut listed = Vec::new();
+    while let Some((entry, mut entry_reader)) = entries.next().await.unwrap() {
+        let mut data = String::new();
+        entry_reader.read_to_string(&mut data).await.unwrap();
+        listed.push((entry.name().to_string(), data));
+    }
+    assert_eq!(
+        listed,
+        [
+            ("a.txt".to_string(), "first".to_string()),
+            ("dir/b.txt".to_string(), "second".to_string()),
+            ("dir".to_string(), String::new()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn detailed_listing_with_coder_properties() {
+    let resource = |name: &str| {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources");
+        path.push(name);
+        path
+    };
+
+    let archive = Archive::open(resource("7za433_7zip_lzma2_bcj2.7z"))
+        .await
+        .unwrap();
+    let blocks = archive.block_details();
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks[0].is_solid());
+    assert_eq!(
+        blocks[1].methods[0].properties,
+        CoderProperties::Lzma {
+            dict_size: 1 << 19,
+            lc: 3,
+            lp: 0,
+            pb: 2
+        }
+    );
+    assert_eq!(
+        blocks[1].methods[3].method,
+        Some(EncoderMethod::BCJ2_FILTER)
+    );
+    assert_eq!(blocks[1].packed_size, 170865);
+    assert_eq!(blocks[1].unpacked_size, 462336);
+    let exe = archive
+        .entry_details()
+        .into_iter()
+        .find(|details| details.entry.name().ends_with("7za.exe"))
+        .unwrap();
+    assert_eq!(exe.block_index, Some(1));
+    assert_eq!(exe.packed_size, Some(170865));
+    assert!(!exe.solid && !exe.encrypted);
+
+    let archive = Archive::open(resource("delta.7z")).await.unwrap();
+    assert_eq!(
+        archive.block_details()[0].methods[1].properties,
+        CoderProperties::Delta { distance: 4 }
+    );
+
+    let archive = Archive::open(resource("ppmd.7z")).await.unwrap();
+    assert_eq!(
+        archive.block_details()[0].methods[0].properties,
+        CoderProperties::Ppmd {
+            order: 5,
+            memory_size: 1 << 18
+        }
+    );
+
+    let archive = Archive::open(resource("decompress_example_lzma2_bcj_x86.7z"))
+        .await
+        .unwrap();
+    assert_eq!(
+        archive.block_details()[0].methods[1].properties,
+        CoderProperties::Bcj { start_offset: 0 }
+    );
+
+    let archive = Archive::open(resource("solid.7z")).await.unwrap();
+    let details = archive.entry_details();
+    assert_eq!(details[0].block_index, None);
+    assert!(details[1].solid && details[2].solid);
+    assert_eq!(details[1].packed_size, None);
+}
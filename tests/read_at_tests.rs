@@ -0,0 +1,166 @@
+use std::{
+    io,
+    net::SocketAddr,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use async_sevenz::{ArchiveReader, Password, ReadAt};
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncSeek};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Serves `data` over HTTP/1.1, answering `HEAD` with its size and `GET` with the requested range.
+///
+/// The ranges of all `GET` requests are logged to `ranges`.
+async fn serve_ranges(data: Vec<u8>, ranges: Arc<Mutex<Vec<Range<usize>>>>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let data = Arc::new(data);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let data = Arc::clone(&data);
+            let ranges = Arc::clone(&ranges);
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    head.push(stream.read_u8().await.unwrap());
+                }
+                let head = String::from_utf8(head).unwrap();
+                let response = if head.starts_with("HEAD") {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len())
+                        .into_bytes()
+                } else {
+                    let range = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Range: bytes="))
+                        .unwrap();
+                    let (start, end) = range.split_once('-').unwrap();
+                    let start = start.parse::<usize>().unwrap();
+                    let end = (end.parse::<usize>().unwrap() + 1).min(data.len());
+                    ranges.lock().unwrap().push(start..end);
+                    let mut response = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                        end - start
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(&data[start..end]);
+                    response
+                };
+                stream.write_all(&response).await.unwrap();
+                stream.shutdown().await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+/// A minimal HTTP range client.
+struct HttpRanges(SocketAddr);
+
+impl HttpRanges {
+    async fn request(&self, head: String) -> io::Result<(String, Vec<u8>)> {
+        let mut stream = TcpStream::connect(self.0).await?;
+        stream.write_all(head.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let split = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| io::Error::other("no response head"))?;
+        let body = response.split_off(split + 4);
+        Ok((String::from_utf8_lossy(&response).into_owned(), body))
+    }
+}
+
+impl ReadAt for HttpRanges {
+    async fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let end = offset + len as u64 - 1;
+        let head =
+            format!("GET / HTTP/1.1\r\nRange: bytes={offset}-{end}\r\nConnection: close\r\n\r\n");
+        Ok(self.request(head).await?.1)
+    }
+
+    async fn size(&self) -> io::Result<u64> {
+        let (head, _) = self
+            .request("HEAD / HTTP/1.1\r\nConnection: close\r\n\r\n".to_string())
+            .await?;
+        head.lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| io::Error::other("no content length"))
+    }
+}
+
+async fn read_all<R: AsyncRead + AsyncSeek + Unpin + Send>(
+    reader: &mut ArchiveReader<R>,
+) -> Vec<(String, Vec<u8>)> {
+    let mut contents = Vec::new();
+    let mut entries = reader.entries();
+    while let Some((entry, mut data)) = entries.next().await.unwrap() {
+        let mut content = Vec::new();
+        AsyncReadExt::read_to_end(&mut data, &mut content)
+            .await
+            .unwrap();
+        contents.push((entry.name().to_string(), content));
+    }
+    contents
+}
+
+async fn expected(path: &str) -> Vec<(String, Vec<u8>)> {
+    let mut reader = ArchiveReader::open(path, Password::empty()).await.unwrap();
+    read_all(&mut reader).await
+}
+
+// The LZMA decoders read their inputs through a blocking bridge, which must not block the only
+// worker thread that also drives the HTTP server.
+#[tokio::test(flavor = "multi_thread")]
+async fn read_multi_stream_block_over_http_ranges() {
+    let path = "tests/resources/7za433_7zip_lzma2_bcj2.7z";
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let addr = serve_ranges(std::fs::read(path).unwrap(), Arc::clone(&ranges)).await;
+
+    let mut reader = ArchiveReader::from_read_at(HttpRanges(addr), Password::empty())
+        .await
+        .unwrap();
+    ranges.lock().unwrap().clear();
+    assert_eq!(read_all(&mut reader).await, expected(path).await);
+
+    // Every pack stream, one of the first block and four of the BCJ2 block, is read by a reader
+    // of its own that requests exactly its range, instead of sharing the fetches of one cursor.
+    let mut ranges = ranges.lock().unwrap().clone();
+    ranges.sort_by_key(|range| range.start);
+    assert_eq!(ranges.len(), 5);
+    let packed_size: u64 = reader
+        .archive()
+        .block_details()
+        .iter()
+        .map(|block| block.packed_size)
+        .sum();
+    assert_eq!(ranges[0].start, 32);
+    assert_eq!(ranges[4].end, 32 + packed_size as usize);
+    for pair in ranges.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+    }
+}
+
+#[tokio::test]
+async fn read_from_memory_and_file() {
+    let path = "tests/resources/two_files_with_content_lzma.7z";
+    let expected = expected(path).await;
+
+    let data = std::fs::read(path).unwrap();
+    let mut reader = ArchiveReader::from_read_at(data, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(read_all(&mut reader).await, expected);
+
+    let file = Arc::new(std::fs::File::open(path).unwrap());
+    let mut reader = ArchiveReader::from_read_at(file, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(read_all(&mut reader).await, expected);
+}
@@ -114,3 +114,61 @@ async fn compress_multi_files_mix_solid_and_non_solid() {
         );
     }
 }
+
+#[cfg(feature = "compress")]
+#[tokio::test]
+async fn solid_compress_reports_entry_progress() {
+    use futures_lite::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let bytes_in = Arc::new(Mutex::new(0u64));
+    let mut sz = ArchiveWriter::new(Cursor::new(Vec::<u8>::new()))
+        .await
+        .unwrap();
+    {
+        let events = events.clone();
+        let bytes_in = bytes_in.clone();
+        sz.set_progress_observer(move |event: ProgressEvent<'_>| match event {
+            ProgressEvent::BlockStarted(index) => {
+                events.lock().unwrap().push(format!("block {index}"))
+            }
+            ProgressEvent::EntryStarted(entry) => events
+                .lock()
+                .unwrap()
+                .push(format!("start {}", entry.name())),
+            ProgressEvent::EntryFinished(entry) => {
+                events
+                    .lock()
+                    .unwrap()
+                    .push(format!("finish {} {}", entry.name(), entry.size()))
+            }
+            ProgressEvent::BytesIn(n) => *bytes_in.lock().unwrap() += n,
+            _ => {}
+        });
+    }
+    let entries = ["a.txt", "empty.txt", "b.txt"]
+        .into_iter()
+        .map(ArchiveEntry::new_file)
+        .collect();
+    let readers = [&b"aaaa"[..], &b""[..], &b"bb"[..]]
+        .into_iter()
+        .map(SourceReader::new)
+        .collect();
+    sz.push_archive_entries(entries, readers).await.unwrap();
+    sz.finish().await.expect("compress ok");
+
+    assert_eq!(*bytes_in.lock().unwrap(), 6);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "block 0",
+            "start a.txt",
+            "finish a.txt 4",
+            "start empty.txt",
+            "finish empty.txt 0",
+            "start b.txt",
+            "finish b.txt 2",
+        ]
+    );
+}
@@ -0,0 +1,59 @@
+#[cfg(all(feature = "tokio", feature = "compress"))]
+use async_sevenz::*;
+#[cfg(all(feature = "tokio", feature = "compress"))]
+use tempfile::tempdir;
+
+#[cfg(all(feature = "tokio", feature = "compress"))]
+#[tokio::test]
+async fn tokio_file_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("tokio.7z");
+
+    let file = tokio::fs::File::create(&path).await.unwrap();
+    let mut writer = ArchiveWriter::from_tokio(file).await.unwrap();
+    for (name, content) in [("a.txt", "first file"), ("b.txt", "second file")] {
+        writer
+            .push_archive_entry(ArchiveEntry::new_file(name), Some(content.as_bytes()))
+            .await
+            .unwrap();
+    }
+    let mut file = writer.finish().await.unwrap().into_inner();
+    tokio::io::AsyncWriteExt::flush(&mut file).await.unwrap();
+    drop(file);
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let mut reader = ArchiveReader::from_tokio(file, Password::empty())
+        .await
+        .unwrap();
+    assert_eq!(reader.read_file("b.txt").await.unwrap(), b"second file");
+
+    let mut entries = reader.entries();
+    let mut contents = Vec::new();
+    while let Some((entry, mut data)) = entries.next().await.unwrap() {
+        let mut content = Vec::new();
+        tokio::io::copy(&mut data, &mut content).await.unwrap();
+        contents.push((entry.name().to_string(), content));
+    }
+    assert_eq!(
+        contents,
+        [
+            ("a.txt".to_string(), b"first file".to_vec()),
+            ("b.txt".to_string(), b"second file".to_vec()),
+        ]
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn futures_reader_as_tokio_reader() {
+    let data = futures_lite::io::Cursor::new(b"0123456789".to_vec());
+    let mut reader = TokioCompat::new(data);
+    tokio::io::AsyncSeekExt::seek(&mut reader, std::io::SeekFrom::Start(4))
+        .await
+        .unwrap();
+    let mut content = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut reader, &mut content)
+        .await
+        .unwrap();
+    assert_eq!(content, "456789");
+}